@@ -0,0 +1,367 @@
+/**
+ *
+ * Fluent construction of physics bodies.
+ *
+ * A body is a specs entity with a rigid body and a collider in the physics
+ * world. The builder creates all of them in one go and links them through
+ * the `RigidBody` and `Collider` components:
+ *
+ * ```ignore
+ * sceneWorld.body(ShapeDesc::Ball { radius: 10.0 })
+ *     .position(0.0, -50.0)
+ *     .material("rubber")
+ *     .with(Ball { active: false })
+ *     .build();
+ * ```
+ *
+ */
+
+use nphysics2d::object::{BodyHandle, BodyStatus};
+use nphysics2d::volumetric::Volumetric;
+use ncollide2d::world::CollisionGroups;
+
+use specs;
+use specs::prelude::*;
+
+use crate::material::{Density, MaterialLibrary, MaterialRef, DEFAULT_MATERIAL};
+use crate::modifier::{Conveyor, OneWay};
+use crate::render::{Render, RenderStyle};
+use crate::shape::{Shape, ShapeDesc};
+use crate::world::PhysicsWorld;
+
+/// Collision group of every body that doesn't ask for other groups. Bodies
+/// are kept out of the other groups, so a group can blacklist itself
+/// without blacklisting everything else.
+pub const DEFAULT_GROUP: usize = 0;
+
+/// Serializable mirror of `nphysics2d::object::BodyStatus`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Status {
+    Dynamic,
+    Static,
+    Kinematic,
+}
+
+impl Default for Status {
+    fn default() -> Status {
+        Status::Dynamic
+    }
+}
+
+impl From<Status> for BodyStatus {
+    fn from(status: Status) -> BodyStatus {
+        match status {
+            Status::Dynamic => BodyStatus::Dynamic,
+            Status::Static => BodyStatus::Static,
+            Status::Kinematic => BodyStatus::Kinematic,
+        }
+    }
+}
+
+impl From<BodyStatus> for Status {
+    fn from(status: BodyStatus) -> Status {
+        match status {
+            BodyStatus::Static => Status::Static,
+            BodyStatus::Kinematic => Status::Kinematic,
+            _ => Status::Dynamic,
+        }
+    }
+}
+
+
+
+type Extra<'a> = Box<dyn FnOnce(&specs::World, specs::Entity) + 'a>;
+
+/// Adds a multibody link with its inertia and center of mass under the parent body
+type AddLink<'a> = Box<dyn FnOnce(&mut PhysicsWorld, BodyHandle, nphysics2d::math::Inertia<f32>, nalgebra::Point2<f32>) -> BodyHandle + 'a>;
+
+/// The joint of a body built as a multibody link and the entity it hangs from
+struct LinkDesc<'a> {
+    parent: Option<specs::Entity>,
+    add: AddLink<'a>,
+}
+
+pub struct BodyBuilder<'a> {
+    specs: &'a mut specs::World,
+    shape: ShapeDesc,
+    status: Status,
+    pose: nalgebra::Isometry2<f32>,
+    velocity: nphysics2d::math::Velocity<f32>,
+    material: String,
+    density: Option<f32>,
+    groups: Option<CollisionGroups>,
+    sensor: bool,
+    render: Render,
+    entity: Option<specs::Entity>,
+    link: Option<LinkDesc<'a>>,
+    extras: Vec<Extra<'a>>,
+}
+
+impl<'a> BodyBuilder<'a> {
+    /// A dynamic body at the origin with the default material, drawn filled white
+    pub fn new(specsWorld: &'a mut specs::World, shape: ShapeDesc) -> BodyBuilder<'a> {
+        BodyBuilder {
+            specs: specsWorld,
+            shape: shape,
+            status: Status::Dynamic,
+            pose: nalgebra::one(),
+            velocity: nphysics2d::math::Velocity::zero(),
+            material: DEFAULT_MATERIAL.to_owned(),
+            density: None,
+            groups: None,
+            sensor: false,
+            render: Render::default(),
+            entity: None,
+            link: None,
+            extras: Vec::new(),
+        }
+    }
+
+    pub fn status(mut self, status: Status) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn pose(mut self, pose: nalgebra::Isometry2<f32>) -> Self {
+        self.pose = pose;
+        self
+    }
+
+    pub fn position(mut self, x: f32, y: f32) -> Self {
+        self.pose.translation.vector = nalgebra::Vector2::new(x, y);
+        self
+    }
+
+    pub fn rotation(mut self, angle: f32) -> Self {
+        self.pose.rotation = nalgebra::UnitComplex::new(angle);
+        self
+    }
+
+    pub fn velocity(mut self, linear: nalgebra::Vector2<f32>, angular: f32) -> Self {
+        self.velocity = nphysics2d::math::Velocity::new(linear, angular);
+        self
+    }
+
+    /// Name of a material in the `MaterialLibrary`
+    pub fn material(mut self, name: &str) -> Self {
+        self.material = name.to_owned();
+        self
+    }
+
+    /// Overrides the density of the material. Kept on the entity as a
+    /// `Density` component so the body can be described again.
+    pub fn density(mut self, density: f32) -> Self {
+        self.density = Some(density);
+        self
+    }
+
+    pub fn groups(mut self, groups: CollisionGroups) -> Self {
+        self.groups = Some(groups);
+        self
+    }
+
+    /// Only detects what overlaps it instead of colliding
+    pub fn sensor(mut self, sensor: bool) -> Self {
+        self.sensor = sensor;
+        self
+    }
+
+    /// Only holds bodies coming from the side of its normal
+    pub fn one_way(self, oneWay: OneWay) -> Self {
+        self.with(oneWay)
+    }
+
+    /// Carries the bodies resting on it along its surface
+    pub fn conveyor(self, conveyor: Conveyor) -> Self {
+        self.with(conveyor)
+    }
+
+    pub fn render(mut self, style: RenderStyle, color: [f32; 4]) -> Self {
+        self.render = Render::new(style, color);
+        self
+    }
+
+    /**
+     * Builds the body onto an existing entity instead of creating a new one.
+     * Its other components are kept. The entity must not have a body yet.
+     */
+    pub fn reuse(mut self, entity: specs::Entity) -> Self {
+        self.entity = Some(entity);
+        self
+    }
+
+    /**
+     * Builds the body as a link of a multibody instead of a rigid body. The
+     * joint holds it to the `parent` link, or to the ground for the root,
+     * with `parent_shift` from the parent's origin to the joint and
+     * `body_shift` from the joint to the body's origin. The joint sets the
+     * pose of the link, so the pose, status and velocity of the builder are
+     * left out. Links are always dynamic.
+     */
+    pub fn link<J>(mut self, parent: Option<specs::Entity>, joint: J, parent_shift: nalgebra::Vector2<f32>, body_shift: nalgebra::Vector2<f32>) -> Self
+        where J: nphysics2d::joint::Joint<f32> + 'a
+    {
+        self.link = Some(LinkDesc {
+            parent: parent,
+            add: Box::new(move |physics: &mut PhysicsWorld, parentHandle: BodyHandle, inertia, centerOfMass| {
+                physics.add_multibody_link(parentHandle, joint, parent_shift, body_shift, inertia, centerOfMass)
+            }),
+        });
+        self
+    }
+
+    /// Adds any other component to the entity
+    pub fn with<C: specs::Component + Send + Sync + 'a>(mut self, component: C) -> Self {
+        self.extras.push(Box::new(move |world: &specs::World, entity: specs::Entity| {
+            world.write_storage::<C>()
+                .insert(entity, component)
+                .expect("Entity was deleted while being built");
+        }));
+        self
+    }
+
+    /**
+     * Creates the entity, adds its rigid body with the status, activation
+     * and mass properties to the physics world, attaches the collider and
+     * links them together through the `Collider` and `RigidBody` components.
+     *
+     * Panics when the shape can't be built, use `try_build` for shapes that
+     * come from outside the code.
+     */
+    pub fn build(self) -> specs::Entity {
+        self.try_build().expect("Shape without a collider; check it with ShapeDesc::handle")
+    }
+
+    /**
+     * Like `build`, but returns `None` without touching the world when the
+     * shape can't be built, the parent link is gone or the shape of a link
+     * isn't solid. Dynamic bodies without a solid shape are built static.
+     */
+    pub fn try_build(mut self) -> Option<specs::Entity> {
+        let shape = self.shape.handle()?;
+
+        if !self.shape.is_solid() {
+            if self.link.is_some() {
+                warn!("A {} has no mass and can't be a multibody link", self.shape.name());
+                return None;
+            }
+
+            if self.status == Status::Dynamic {
+                warn!("A {} has no mass and can't be dynamic, building it static", self.shape.name());
+                self.status = Status::Static;
+            }
+        }
+
+        // the parent body to joint a link to and the root of its multibody
+        let parent = match self.link {
+            Some(LinkDesc { parent: Some(parent), .. }) => {
+                let handle = self.specs.read_storage::<crate::system::RigidBody>().get(parent)?.object_handle;
+                let root = self.specs.read_storage::<crate::system::Link>().get(parent)?.root;
+                Some((handle, Some(root)))
+            },
+            Some(LinkDesc { parent: None, .. }) => Some((BodyHandle::ground(), None)),
+            None => None,
+        };
+
+        let material = self.specs.read_resource::<MaterialLibrary>().get(&self.material).clone();
+        let density = self.density.unwrap_or(material.density);
+
+        let entity = match self.entity {
+            Some(entity) => entity,
+            None => self.specs.create_entity().build(),
+        };
+
+        self.specs.write_storage::<Shape>()
+            .insert(entity, Shape { desc: self.shape.clone() })
+            .expect("Entity was deleted while being built");
+
+        self.specs.write_storage::<MaterialRef>()
+            .insert(entity, MaterialRef::new(&material.name))
+            .expect("Entity was deleted while being built");
+
+        self.specs.write_storage::<Render>()
+            .insert(entity, self.render.clone())
+            .expect("Entity was deleted while being built");
+
+        match self.density {
+            Some(value) => {
+                self.specs.write_storage::<Density>()
+                    .insert(entity, Density { value: value })
+                    .expect("Entity was deleted while being built");
+            },
+            None => {
+                self.specs.write_storage::<Density>().remove(entity);
+            },
+        }
+
+        let (bodyHandle, collideHandle) = {
+            let mut physics = self.specs.write_resource::<PhysicsWorld>();
+
+            let bodyHandle = match (self.link, parent) {
+                (Some(link), Some((parentHandle, _))) => {
+                    let bodyHandle = (link.add)(&mut *physics, parentHandle, shape.inertia(density), shape.center_of_mass());
+                    if let Some(multibody) = physics.multibody_mut(bodyHandle) {
+                        multibody.activation_status_mut().set_deactivation_threshold(None);
+                    }
+                    bodyHandle
+                },
+
+                _ => {
+                    let (inertia, centerOfMass) = match self.status {
+                        Status::Dynamic => (shape.inertia(density), shape.center_of_mass()),
+                        _ => (nphysics2d::math::Inertia::zero(), nalgebra::Point2::origin()),
+                    };
+
+                    let bodyHandle = physics.add_rigid_body(self.pose, inertia, centerOfMass);
+                    {
+                        let rigidBody = physics.rigid_body_mut(bodyHandle).unwrap();
+                        rigidBody.set_status(self.status.into());
+                        rigidBody.set_velocity(self.velocity);
+                        rigidBody.activation_status_mut().set_deactivation_threshold(None);
+                    }
+                    bodyHandle
+                },
+            };
+
+            let collideHandle = if self.sensor {
+                physics.add_sensor(shape, bodyHandle, nalgebra::one())
+            } else {
+                physics.add_collider(
+                    0.0,
+                    shape,
+                    bodyHandle,
+                    nalgebra::one(),
+                    material.material(),
+                )
+            };
+
+            let groups = self.groups.unwrap_or_else(|| CollisionGroups::new().with_membership(&[DEFAULT_GROUP]));
+            physics.collision_world_mut().set_collision_groups(collideHandle, groups);
+
+            (bodyHandle, collideHandle)
+        };
+
+        self.specs
+            .write_storage::<crate::system::Collider>()
+            .insert(entity, crate::system::Collider { object_handle: collideHandle })
+            .expect("Entity was deleted while being built");
+
+        self.specs
+            .write_storage::<crate::system::RigidBody>()
+            .insert(entity, crate::system::RigidBody { object_handle: bodyHandle })
+            .expect("Entity was deleted while being built");
+
+        if let Some((_, root)) = parent {
+            self.specs
+                .write_storage::<crate::system::Link>()
+                .insert(entity, crate::system::Link { root: root.unwrap_or(entity) })
+                .expect("Entity was deleted while being built");
+        }
+
+        for extra in self.extras {
+            extra(self.specs, entity);
+        }
+
+        Some(entity)
+    }
+}
@@ -0,0 +1,365 @@
+/**
+ *
+ * Kinematic character controller.
+ *
+ * A character is a kinematic capsule moved by the player input rather
+ * than by forces. Every step its motion is swept through the world with
+ * shape casts against static and kinematic colliders and slides along
+ * whatever it hits. It walks up slopes up to its slope limit, steps up
+ * ledges lower than its step height, sticks to the ground going down
+ * slopes and stairs and can still jump for a moment after walking off an
+ * edge. `OneWay` colliders only hold it from their allowed side.
+ *
+ * Dynamic bodies don't block it. They are pushed out of the way by the
+ * physics step and kicked along by an impulse when the character walks
+ * into them, which moves heavy bodies less than light ones.
+ *
+ * The motion is handed to the physics step as the velocity of the
+ * kinematic body, so it has to run before the physics system.
+ *
+ */
+
+use std::collections::HashMap;
+
+use ncollide2d::query;
+use ncollide2d::shape::Shape;
+use ncollide2d::world::CollisionObjectHandle;
+use nphysics2d::object::BodyHandle;
+
+use specs;
+use specs::prelude::*;
+
+use crate::modifier::OneWay;
+use crate::player::PlayerInput;
+use crate::system::{is_dynamic, BodyPoint, Collider, RigidBody};
+use crate::world::PhysicsWorld;
+
+/// Gap kept between the character and what it stands on or walks into
+const SKIN: f32 = 0.5;
+
+/// Times the motion of a step is redirected along what it hits
+const MAX_SLIDES: usize = 4;
+
+#[derive(Clone, Debug, Component)]
+#[storage(HashMapStorage)]
+pub struct CharacterController {
+    /// Top walking speed
+    pub speed: f32,
+
+    /// Upwards speed given by a jump
+    pub jump_speed: f32,
+
+    pub gravity: f32,
+
+    /// Steepest slope it walks up, in radians
+    pub max_slope: f32,
+
+    /// Highest ledge it walks up without jumping
+    pub step_height: f32,
+
+    /// Furthest it drops to stay on the ground going down
+    pub snap_distance: f32,
+
+    /// Seconds after leaving the ground a jump still works
+    pub coyote_time: f32,
+
+    /// Fraction of its speed given to dynamic bodies it walks into
+    pub push: f32,
+
+    /// Mass it pushes with, about that of its capsule at the default density.
+    /// Bodies much heavier than this barely move.
+    pub mass: f32,
+
+    pub velocity: nalgebra::Vector2<f32>,
+    pub grounded: bool,
+
+    /// Seconds since it last stood on something
+    pub airborne: f32,
+}
+
+impl Default for CharacterController {
+    fn default() -> CharacterController {
+        CharacterController {
+            speed: 120.0,
+            jump_speed: 220.0,
+            gravity: 400.0,
+            max_slope: 50.0_f32.to_radians(),
+            step_height: 8.0,
+            snap_distance: 6.0,
+            coyote_time: 0.1,
+            push: 1.0,
+            mass: 300.0,
+            velocity: nalgebra::Vector2::zeros(),
+            grounded: false,
+            airborne: 0.0,
+        }
+    }
+}
+
+impl CharacterController {
+    /// Whether a surface with the normal is flat enough to stand on. Up is -y.
+    pub fn walkable(&self, normal: &nalgebra::Vector2<f32>) -> bool {
+        - normal.y >= self.max_slope.cos()
+    }
+}
+
+
+
+/// How a collider takes part in the sweeps
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Obstacle {
+    Solid,
+    /// Holds from the side the world normal points to
+    OneWay(nalgebra::Vector2<f32>),
+    Dynamic(BodyHandle),
+}
+
+/// The first thing a sweep runs into
+#[derive(Clone, Copy, Debug)]
+struct Hit {
+    /// Fraction of the motion done before touching
+    toi: f32,
+
+    /// Surface normal of the obstacle, pointing out of it
+    normal: nalgebra::Vector2<f32>,
+}
+
+/**
+ * Casts the shape along the motion and returns the first solid obstacle
+ * it hits. Obstacles it moves away from are ignored so it never gets
+ * stuck on what it already touches, and one-way colliders only count
+ * when it comes at them from their allowed side.
+ */
+fn sweep(physics: &PhysicsWorld, obstacles: &HashMap<CollisionObjectHandle, Obstacle>, shape: &dyn Shape<f32>, pose: &nalgebra::Isometry2<f32>, motion: &nalgebra::Vector2<f32>) -> Option<Hit> {
+    let still = nalgebra::Vector2::zeros();
+    let mut first: Option<Hit> = None;
+
+    for object in physics.collision_world().collision_objects() {
+        let kind = match obstacles.get(&object.handle()) {
+            Some(Obstacle::Dynamic(_)) | None => continue,
+            Some(kind) => *kind,
+        };
+
+        let toi = match query::time_of_impact(pose, motion, shape, object.position(), &still, object.shape().as_ref()) {
+            Some(toi) if toi <= 1.0 => toi,
+            _ => continue,
+        };
+
+        if first.map(|hit| toi >= hit.toi).unwrap_or(false) {
+            continue;
+        }
+
+        let mut touching = *pose;
+        touching.translation.vector += motion * toi;
+        let contact = match query::contact(&touching, shape, object.position(), object.shape().as_ref(), SKIN * 2.0) {
+            Some(contact) => contact,
+            None => continue,
+        };
+
+        let normal = - contact.normal.unwrap();
+        if motion.dot(&normal) >= 0.0 {
+            continue;
+        }
+
+        // a one-way collider holds only what comes at it from outside, on its allowed side
+        if let Obstacle::OneWay(allowed) = kind {
+            if toi <= 0.0 || normal.dot(&allowed) < 0.5 {
+                continue;
+            }
+        }
+
+        first = Some(Hit { toi: toi, normal: normal });
+    }
+
+    first
+}
+
+/**
+ * Moves the pose along the motion, sliding along everything in the way.
+ * Returns the normals of what it hit. With `stick` set it stops on the
+ * first walkable surface instead of sliding down it.
+ */
+fn slide(physics: &PhysicsWorld, obstacles: &HashMap<CollisionObjectHandle, Obstacle>, controller: &CharacterController, shape: &dyn Shape<f32>, pose: &mut nalgebra::Isometry2<f32>, motion: nalgebra::Vector2<f32>, stick: bool) -> Vec<nalgebra::Vector2<f32>> {
+    let mut normals = Vec::new();
+    let mut remaining = motion;
+
+    for _ in 0..MAX_SLIDES {
+        let length = remaining.norm();
+        if length < std::f32::EPSILON {
+            break;
+        }
+
+        let hit = match sweep(physics, obstacles, shape, pose, &remaining) {
+            Some(hit) => hit,
+            None => {
+                pose.translation.vector += remaining;
+                break;
+            },
+        };
+
+        let travel = remaining * (hit.toi - SKIN / length).max(0.0);
+        pose.translation.vector += travel;
+        normals.push(hit.normal);
+
+        if stick && controller.walkable(&hit.normal) {
+            break;
+        }
+
+        remaining -= travel;
+        remaining -= hit.normal * remaining.dot(&hit.normal);
+    }
+
+    normals
+}
+
+
+
+/**
+ * Moves every character by the player input. Walks sideways first,
+ * stepping up what is too steep to walk when on the ground, then falls or
+ * jumps and finally snaps back down onto the ground it just walked off.
+ */
+pub struct CharacterSystem;
+
+impl<'a> specs::System<'a> for CharacterSystem {
+    type SystemData = (
+        specs::WriteStorage<'a, CharacterController>,
+        specs::ReadStorage<'a, OneWay>,
+        specs::ReadStorage<'a, Collider>,
+        specs::ReadStorage<'a, RigidBody>,
+        specs::ReadExpect<'a, PlayerInput>,
+        specs::WriteExpect<'a, PhysicsWorld>,
+    );
+
+    fn run(&mut self, (mut controllers, oneWays, colliders, rigidBodies, input, mut physics_world): Self::SystemData) {
+        if (&controllers).join().next().is_none() {
+            return;
+        }
+
+        let dt = physics_world.timestep();
+        let mut obstacles: HashMap<CollisionObjectHandle, Obstacle> = (&colliders, &rigidBodies, oneWays.maybe()).join()
+            .filter_map(|(c, r, oneWay)| {
+                let kind = match (is_dynamic(&physics_world, r.object_handle)?, oneWay) {
+                    (true, _) => Obstacle::Dynamic(r.object_handle),
+                    (false, Some(oneWay)) => Obstacle::OneWay(oneWay.world_normal(&physics_world.body_part(r.object_handle).position())),
+                    (false, None) => Obstacle::Solid,
+                };
+                Some((c.object_handle, kind))
+            })
+            .collect();
+
+        for (controller, c, r) in (&mut controllers, &colliders, &rigidBodies).join() {
+            // characters don't block themselves, but they do block each other
+            let own = obstacles.remove(&c.object_handle);
+
+            let (shape, start) = match physics_world.collision_world().collision_object(c.object_handle) {
+                Some(object) => (object.shape().clone(), *object.position()),
+                None => continue,
+            };
+            let shape = shape.as_ref();
+
+            controller.velocity.x = input.horizontal * controller.speed;
+            controller.velocity.y += controller.gravity * dt;
+
+            if input.jump && (controller.grounded || controller.airborne < controller.coyote_time) {
+                controller.velocity.y = - controller.jump_speed;
+                controller.grounded = false;
+                controller.airborne = controller.coyote_time;
+            }
+
+            let wasGrounded = controller.grounded;
+            let mut pose = start;
+
+            // sideways, trying a step up when a wall is in the way
+            let walk = nalgebra::Vector2::new(controller.velocity.x * dt, 0.0);
+            let mut walked = pose;
+            let normals = slide(&physics_world, &obstacles, controller, shape, &mut walked, walk, false);
+
+            if wasGrounded && normals.iter().any(|n| !controller.walkable(n)) {
+                let mut stepped = pose;
+                slide(&physics_world, &obstacles, controller, shape, &mut stepped, nalgebra::Vector2::new(0.0, - controller.step_height), false);
+                slide(&physics_world, &obstacles, controller, shape, &mut stepped, walk, false);
+                let landed = slide(&physics_world, &obstacles, controller, shape, &mut stepped, nalgebra::Vector2::new(0.0, controller.step_height), true);
+
+                let further = (stepped.translation.vector.x - pose.translation.vector.x).abs()
+                    > (walked.translation.vector.x - pose.translation.vector.x).abs();
+                if further && landed.iter().any(|n| controller.walkable(n)) {
+                    walked = stepped;
+                }
+            }
+            pose = walked;
+
+            // up or down
+            let fall = nalgebra::Vector2::new(0.0, controller.velocity.y * dt);
+            let normals = slide(&physics_world, &obstacles, controller, shape, &mut pose, fall, true);
+
+            controller.grounded = controller.velocity.y >= 0.0 && normals.iter().any(|n| controller.walkable(n));
+            if normals.iter().any(|n| n.y > 0.5) && controller.velocity.y < 0.0 {
+                // bumped a ceiling
+                controller.velocity.y = 0.0;
+            }
+
+            // down onto the ground it just walked off, unless it jumped
+            if wasGrounded && !controller.grounded && controller.velocity.y >= 0.0 {
+                let snap = nalgebra::Vector2::new(0.0, controller.snap_distance);
+                if let Some(hit) = sweep(&physics_world, &obstacles, shape, &pose, &snap).filter(|hit| controller.walkable(&hit.normal)) {
+                    pose.translation.vector += snap * (hit.toi - SKIN / controller.snap_distance).max(0.0);
+                    controller.grounded = true;
+                }
+            }
+
+            if controller.grounded {
+                controller.velocity.y = 0.0;
+                controller.airborne = 0.0;
+            } else {
+                controller.airborne += dt;
+            }
+
+            let displacement = pose.translation.vector - start.translation.vector;
+            let velocity = displacement / dt;
+
+            // kicks the dynamic bodies in its way along
+            let mut kicks = Vec::new();
+            for object in physics_world.collision_world().collision_objects() {
+                let bodyHandle = match obstacles.get(&object.handle()) {
+                    Some(Obstacle::Dynamic(handle)) => *handle,
+                    _ => continue,
+                };
+
+                let touching = query::contact(&pose, shape, object.position(), object.shape().as_ref(), SKIN * 2.0);
+                if let Some(contact) = touching {
+                    kicks.push((bodyHandle, contact.world2, contact.normal.unwrap()));
+                }
+            }
+
+            // the impulse of an inelastic hit between the character's mass and
+            // the body at the contact, handed over as a force for one step
+            for (bodyHandle, point, direction) in kicks {
+                let local = physics_world.body_part(bodyHandle).position().inverse() * point;
+                let body = match BodyPoint::at(&physics_world, bodyHandle, &local) {
+                    Some(body) => body,
+                    None => continue,
+                };
+
+                let closing = (velocity - body.velocity).dot(&direction) * controller.push;
+                let inverse = body.inverse_mass(&direction) + 1.0 / controller.mass;
+                if closing <= 0.0 || body.inertia.is_none() {
+                    continue;
+                }
+
+                body.push(&mut physics_world, &(direction * closing / inverse / dt));
+                if let Some(rb) = physics_world.rigid_body_mut(bodyHandle) {
+                    rb.activate();
+                }
+            }
+
+            if let Some(rb) = physics_world.rigid_body_mut(r.object_handle) {
+                rb.set_velocity(nphysics2d::math::Velocity::new(velocity, 0.0));
+            }
+
+            if let Some(kind) = own {
+                obstacles.insert(c.object_handle, kind);
+            }
+        }
+    }
+}
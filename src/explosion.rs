@@ -0,0 +1,178 @@
+/**
+ *
+ * Radial explosions and implosions.
+ *
+ * An explosion kicks every dynamic body within its radius away from its
+ * center, an implosion (negative strength) pulls them in. Bodies further
+ * away get less depending on the falloff and static bodies can shield
+ * what is behind them:
+ *
+ * ```ignore
+ * Explosion::new(40000.0)
+ *     .radius(150.0)
+ *     .falloff(Falloff::InverseSquare)
+ *     .line_of_sight(true)
+ *     .apply(&sceneWorld.specs, &point);
+ * ```
+ *
+ */
+
+use std::collections::HashSet;
+
+use nphysics2d::object::BodyStatus;
+use ncollide2d::query::Ray;
+use ncollide2d::world::{CollisionGroups, CollisionObjectHandle};
+
+use specs;
+use specs::prelude::*;
+
+use log::*;
+
+use crate::fracture::{Breakable, Impact, Impacts};
+use crate::system::{Collider, RigidBody};
+use crate::world::PhysicsWorld;
+
+/// Distances below this count as this for the inverse square falloff
+const MIN_DISTANCE: f32 = 10.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Falloff {
+    /// Full strength out to the radius
+    Constant,
+
+    /// Full strength at the center down to nothing at the radius
+    Linear,
+
+    /// Full strength within `MIN_DISTANCE`, a quarter at twice that and so on
+    InverseSquare,
+}
+
+impl Falloff {
+    pub fn next(&self) -> Falloff {
+        match self {
+            Falloff::Constant => Falloff::Linear,
+            Falloff::Linear => Falloff::InverseSquare,
+            Falloff::InverseSquare => Falloff::Constant,
+        }
+    }
+
+    /// The fraction of the strength left at the distance
+    pub fn factor(&self, distance: f32, radius: f32) -> f32 {
+        match self {
+            Falloff::Constant => 1.0,
+            Falloff::Linear => (1.0 - distance / radius).max(0.0),
+            Falloff::InverseSquare => (MIN_DISTANCE / distance.max(MIN_DISTANCE)).powi(2),
+        }
+    }
+}
+
+
+
+#[derive(Debug, Copy, Clone)]
+pub struct Explosion {
+    pub strength: f32,
+    pub radius: f32,
+    pub falloff: Falloff,
+    pub line_of_sight: bool,
+}
+
+impl Explosion {
+    /**
+     * An explosion with the given impulse at full strength. Light bodies
+     * fly further than heavy ones. Negative strengths implode.
+     */
+    pub fn new(strength: f32) -> Explosion {
+        Explosion {
+            strength: strength,
+            radius: 150.0,
+            falloff: Falloff::Linear,
+            line_of_sight: false,
+        }
+    }
+
+    pub fn radius(mut self, radius: f32) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    pub fn falloff(mut self, falloff: Falloff) -> Self {
+        self.falloff = falloff;
+        self
+    }
+
+    /// Only bodies the center can see through static bodies are hit
+    pub fn line_of_sight(mut self, line_of_sight: bool) -> Self {
+        self.line_of_sight = line_of_sight;
+        self
+    }
+
+    /**
+     * Applies an impulse to every dynamic body within the radius of the
+     * center, pointing away from the center. Breakable bodies also take it
+     * as an impact. Returns how many bodies were hit.
+     */
+    pub fn apply(&self, specsWorld: &specs::World, center: &nalgebra::Point2<f32>) -> usize {
+        let mut physics = specsWorld.write_resource::<PhysicsWorld>();
+        let colliders = specsWorld.read_storage::<Collider>();
+        let rigidBodies = specsWorld.read_storage::<RigidBody>();
+        let breakables = specsWorld.read_storage::<Breakable>();
+        let entities = specsWorld.entities();
+        let mut impacts = specsWorld.write_resource::<Impacts>();
+
+        let shields: HashSet<CollisionObjectHandle> = (&colliders, &rigidBodies).join()
+            .filter(|(_, r)| physics.rigid_body(r.object_handle).map(|rb| rb.status() == BodyStatus::Static).unwrap_or(false))
+            .map(|(c, _)| c.object_handle)
+            .collect();
+
+        let mut hits = 0;
+        for (entity, c, r) in (&entities, &colliders, &rigidBodies).join() {
+            let offset = match physics.rigid_body(r.object_handle) {
+                Some(rb) if rb.status() == BodyStatus::Dynamic => rb.position().translation.vector - center.coords,
+                _ => continue,
+            };
+
+            let distance = offset.norm();
+            if distance > self.radius {
+                continue;
+            }
+
+            if self.line_of_sight && self.blocked(&physics, &shields, center, &offset) {
+                continue;
+            }
+
+            let direction = offset.try_normalize(std::f32::EPSILON).unwrap_or(- nalgebra::Vector2::y());
+            let impulse = direction * self.strength * self.falloff.factor(distance, self.radius);
+
+            if let Some(rb) = physics.rigid_body_mut(r.object_handle) {
+                let mass = rb.local_inertia().linear;
+                let mut velocity = rb.velocity().clone();
+                velocity.linear += impulse / mass.max(std::f32::EPSILON);
+                rb.set_velocity(velocity);
+                rb.activate();
+                hits += 1;
+
+                if breakables.contains(entity) {
+                    impacts.impacts.push(Impact {
+                        entity: entity,
+                        impulse: impulse.norm(),
+                        point: nalgebra::Point2::from_coordinates(rb.position().translation.vector),
+                    });
+                }
+            }
+
+            debug!("Explosion hit {:?} at {:.1}", c.object_handle, distance);
+        }
+
+        hits
+    }
+
+    /// Whether a static collider lies between the center and the offset from it
+    fn blocked(&self, physics: &PhysicsWorld, shields: &HashSet<CollisionObjectHandle>, center: &nalgebra::Point2<f32>, offset: &nalgebra::Vector2<f32>) -> bool {
+        // the ray isn't normalized so a time of impact below 1.0 is in front of the body
+        let ray = Ray::new(*center, *offset);
+
+        physics.collision_world()
+            .interferences_with_ray(&ray, &CollisionGroups::new())
+            .any(|(object, intersection)| shields.contains(&object.handle()) && intersection.toi < 1.0)
+    }
+}
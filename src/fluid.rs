@@ -0,0 +1,179 @@
+/**
+ *
+ * Buoyancy in fluid volumes.
+ *
+ * A `FluidVolume` is a rectangle of fluid. Every dynamic body overlapping
+ * it is pushed against gravity by the weight of the fluid it displaces,
+ * at the centroid of its submerged part, and slowed down by the fluid's
+ * drag. Bodies lighter than the fluid float. Water is around the density
+ * of the default material, air a lot less but still enough to lift a
+ * balloon.
+ *
+ * The submerged part is found by clipping a polygon outline of the
+ * collider to the rectangle. Balls, cuboids, capsules, convex polygons and
+ * compounds of them are supported, other shapes don't float.
+ *
+ */
+
+use ncollide2d::shape::{Ball, Capsule, Compound, ConvexPolygon, Cuboid, Shape};
+use nphysics2d::object::BodyStatus;
+
+use specs;
+use specs::prelude::*;
+
+use crate::geometry::{self, Point};
+use crate::system::{Collider, RigidBody};
+use crate::world::PhysicsWorld;
+
+/// Segments of the polygon standing in for a ball
+const BALL_SEGMENTS: usize = 16;
+
+#[derive(Clone, Debug, Component)]
+#[storage(HashMapStorage)]
+pub struct FluidVolume {
+    pub center: nalgebra::Point2<f32>,
+    pub half_extents: nalgebra::Vector2<f32>,
+    pub density: f32,
+
+    /// Fraction of the linear velocity lost per second when fully submerged
+    pub linear_drag: f32,
+
+    /// Fraction of the angular velocity lost per second when fully submerged
+    pub angular_drag: f32,
+}
+
+impl FluidVolume {
+    pub fn new(center: nalgebra::Point2<f32>, half_extents: nalgebra::Vector2<f32>, density: f32) -> FluidVolume {
+        FluidVolume {
+            center: center,
+            half_extents: half_extents,
+            density: density,
+            linear_drag: 1.0,
+            angular_drag: 1.0,
+        }
+    }
+
+    fn min(&self) -> Point {
+        [self.center.x - self.half_extents.x, self.center.y - self.half_extents.y]
+    }
+
+    fn max(&self) -> Point {
+        [self.center.x + self.half_extents.x, self.center.y + self.half_extents.y]
+    }
+
+    /**
+     * The submerged area of the polygons and its centroid, `None` when
+     * nothing is under the surface.
+     */
+    pub fn submerged(&self, polygons: &[Vec<Point>]) -> Option<(f32, Point)> {
+        let (min, max) = (self.min(), self.max());
+        let mut total = 0.0;
+        let mut moment = [0.0, 0.0];
+
+        for polygon in polygons {
+            let clipped = geometry::clip_to_rect(polygon, &min, &max);
+            if clipped.len() < 3 {
+                continue;
+            }
+
+            let area = geometry::area(&clipped);
+            let centroid = geometry::centroid(&clipped);
+            total += area;
+            moment[0] += centroid[0] * area;
+            moment[1] += centroid[1] * area;
+        }
+
+        match total > std::f32::EPSILON {
+            true => Some((total, [moment[0] / total, moment[1] / total])),
+            false => None,
+        }
+    }
+}
+
+
+
+/**
+ * Convex outlines of a collision shape placed at the pose, in world
+ * coordinates. Empty for shapes without an area.
+ */
+pub fn polygons(shape: &dyn Shape<f32>, pose: &nalgebra::Isometry2<f32>) -> Vec<Vec<Point>> {
+    let place = |points: Vec<Point>| -> Vec<Point> {
+        points.iter()
+            .map(|p| {
+                let world = pose * nalgebra::Point2::new(p[0], p[1]);
+                [world.x, world.y]
+            })
+            .collect()
+    };
+
+    if let Some(ball) = shape.as_shape::<Ball<f32>>() {
+        vec![place(geometry::circle(&[0.0, 0.0], ball.radius(), BALL_SEGMENTS))]
+    } else if let Some(cuboid) = shape.as_shape::<Cuboid<f32>>() {
+        let (x, y) = (cuboid.half_extents().x, cuboid.half_extents().y);
+        vec![place(vec![[-x, -y], [x, -y], [x, y], [-x, y]])]
+    } else if let Some(capsule) = shape.as_shape::<Capsule<f32>>() {
+        vec![place(geometry::capsule(capsule.half_height(), capsule.radius(), BALL_SEGMENTS))]
+    } else if let Some(polygon) = shape.as_shape::<ConvexPolygon<f32>>() {
+        vec![place(polygon.points().iter().map(|p| [p.x, p.y]).collect())]
+    } else if let Some(compound) = shape.as_shape::<Compound<f32>>() {
+        compound.shapes().iter()
+            .flat_map(|(part_pose, part)| polygons(part.as_ref(), &(pose * part_pose)))
+            .collect()
+    } else {
+        Vec::new()
+    }
+}
+
+
+
+/**
+ * Applies buoyancy and drag to the bodies in every fluid volume. Buoyancy
+ * follows the world gravity, so nothing floats while gravity is off.
+ */
+pub struct FluidSystem;
+
+impl<'a> specs::System<'a> for FluidSystem {
+    type SystemData = (
+        specs::ReadStorage<'a, FluidVolume>,
+        specs::ReadStorage<'a, Collider>,
+        specs::ReadStorage<'a, RigidBody>,
+        specs::WriteExpect<'a, PhysicsWorld>,
+    );
+
+    fn run(&mut self, (fluids, colliders, rigidBodies, mut physics_world): Self::SystemData) {
+        let gravity = *physics_world.gravity();
+
+        for fluid in fluids.join() {
+            for (c, r) in (&colliders, &rigidBodies).join() {
+                let outline = match physics_world.collision_world().collision_object(c.object_handle) {
+                    Some(object) => polygons(object.shape().as_ref(), object.position()),
+                    None => continue,
+                };
+
+                let (area, centroid) = match fluid.submerged(&outline) {
+                    Some(submerged) => submerged,
+                    None => continue,
+                };
+
+                let total: f32 = outline.iter().map(|polygon| geometry::area(polygon)).sum();
+                let fraction = (area / total).min(1.0);
+
+                let rb = match physics_world.rigid_body_mut(r.object_handle) {
+                    Some(rb) if rb.status() == BodyStatus::Dynamic => rb,
+                    _ => continue,
+                };
+
+                let inertia = rb.local_inertia().clone();
+                let velocity = rb.velocity().clone();
+                let arm = nalgebra::Point2::new(centroid[0], centroid[1]) - rb.center_of_mass();
+
+                let buoyancy = - gravity * fluid.density * area;
+                let drag = - velocity.linear * fluid.linear_drag * fraction * inertia.linear;
+                let torque = crate::system::cross(&arm, &buoyancy)
+                    - velocity.angular * fluid.angular_drag * fraction * inertia.angular;
+
+                rb.apply_force(&nphysics2d::math::Force::new(buoyancy + drag, torque));
+            }
+        }
+    }
+}
@@ -0,0 +1,406 @@
+/**
+ *
+ * Fracture of brittle bodies.
+ *
+ * The physics system estimates the impulse of every new contact on a
+ * `Breakable` body from how fast the two bodies were closing in at the
+ * contact point and the mass they have between them, and queues it as an
+ * `Impact`. Once the dispatcher is done, `shatter` splits
+ * every body hit harder than its threshold into convex fragments by
+ * slicing it radially around the point of impact. Fragments keep the
+ * material, colour and velocity of the body and fade away after a while:
+ *
+ * ```ignore
+ * sceneWorld.body(ShapeDesc::Cuboid { half_extents: [20.0, 20.0] })
+ *     .with(Breakable::new(20000.0))
+ *     .build();
+ * ```
+ *
+ * A `BreakableJoint` pins bodies together until it is pulled too hard.
+ *
+ */
+
+use std::collections::HashMap;
+
+use ncollide2d::world::CollisionObjectHandle;
+
+use specs;
+use specs::prelude::*;
+
+use log::*;
+
+use crate::builder::BodyBuilder;
+use crate::geometry::{self, Point};
+use crate::material::MaterialRef;
+use crate::render::Render;
+use crate::shape::ShapeDesc;
+use crate::system::{Anchor, Collider, RigidBody};
+use crate::world::PhysicsWorld;
+
+/// Fragments smaller than this are dropped as dust
+const MIN_FRAGMENT_AREA: f32 = 8.0;
+
+/// Seconds a fragment stays around
+const FRAGMENT_LIFETIME: f32 = 5.0;
+
+/// Fragments fade out over their last seconds
+const FADE_TIME: f32 = 1.0;
+
+/// Far enough for a slice to reach past any body
+const SLICE_LENGTH: f32 = 10000.0;
+
+/// Part of the gap between the two points of a joint closed every step
+const JOINT_STIFFNESS: f32 = 0.2;
+
+/// Part of the relative velocity of the two points of a joint taken out every step
+const JOINT_DAMPING: f32 = 0.5;
+
+/// Size of the dot drawn on a joint
+const JOINT_RADIUS: f32 = 2.5;
+
+/// Shatters into pieces when hit harder than the threshold
+#[derive(Clone, Copy, Debug, Component, Serialize, Deserialize)]
+#[storage(HashMapStorage)]
+pub struct Breakable {
+    /// Impulse needed to break it
+    pub threshold: f32,
+
+    /// Number of radial slices
+    #[serde(default = "default_pieces")]
+    pub pieces: usize,
+}
+
+fn default_pieces() -> usize {
+    5
+}
+
+impl Breakable {
+    pub fn new(threshold: f32) -> Breakable {
+        Breakable {
+            threshold: threshold,
+            pieces: default_pieces(),
+        }
+    }
+}
+
+
+
+/// Removes the entity and its body once the time runs out
+#[derive(Clone, Copy, Debug, Component)]
+#[storage(HashMapStorage)]
+pub struct Lifetime {
+    pub remaining: f32,
+}
+
+
+
+/**
+ * Pins two bodies together, or a body to the world, at a point until it is
+ * pulled harder than its threshold. nphysics doesn't report how hard its
+ * joints pull, so this is a soft constraint that measures its own tension:
+ * every step it pushes the two points with the force that takes out a set
+ * part of their gap and of their relative velocity, given how easily the
+ * bodies move at those points.
+ */
+#[derive(Clone, Copy, Debug, Component)]
+#[storage(HashMapStorage)]
+pub struct BreakableJoint {
+    pub a: Anchor,
+    pub b: Anchor,
+
+    /// Tension that breaks it
+    pub threshold: f32,
+}
+
+impl BreakableJoint {
+    /// Pins the two bodies, or the world where there is no body, at a point in the world
+    pub fn at(specsWorld: &specs::World, a: Option<specs::Entity>, b: Option<specs::Entity>, point: nalgebra::Point2<f32>, threshold: f32) -> BreakableJoint {
+        BreakableJoint {
+            a: Anchor::at(specsWorld, a, point),
+            b: Anchor::at(specsWorld, b, point),
+            threshold: threshold,
+        }
+    }
+}
+
+
+
+/// A hit on a breakable body, waiting for `shatter`
+#[derive(Clone, Copy, Debug)]
+pub struct Impact {
+    pub entity: specs::Entity,
+    pub impulse: f32,
+    pub point: nalgebra::Point2<f32>,
+}
+
+/// Impacts of the last steps, drained by `shatter`
+#[derive(Debug, Default)]
+pub struct Impacts {
+    pub impacts: Vec<Impact>,
+}
+
+
+
+/**
+ * Where two colliders touch the deepest and the contact normal pointing
+ * from the first towards the second, `None` when they don't touch.
+ */
+pub fn contact(physics: &PhysicsWorld, h1: CollisionObjectHandle, h2: CollisionObjectHandle) -> Option<(nalgebra::Point2<f32>, nalgebra::Vector2<f32>)> {
+    let mut manifolds = Vec::new();
+
+    for (a, b, algorithm) in physics.collision_world().contact_pairs() {
+        let pair = (a.handle(), b.handle());
+        let flip = match pair {
+            _ if pair == (h1, h2) => 1.0,
+            _ if pair == (h2, h1) => -1.0,
+            _ => continue,
+        };
+
+        algorithm.contacts(&mut manifolds);
+        return manifolds.iter()
+            .flat_map(|manifold| manifold.contacts())
+            .max_by(|x, y| x.contact.depth.partial_cmp(&y.contact.depth).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|tracked| {
+                let point = nalgebra::Point2::from_coordinates((tracked.contact.world1.coords + tracked.contact.world2.coords) * 0.5);
+                (point, tracked.contact.normal.unwrap() * flip)
+            });
+    }
+
+    None
+}
+
+
+
+/**
+ * Splits the body into fragments by slicing it into `pieces` wedges
+ * around `point`, the first slice pointing from the body's center of mass
+ * through the point. Each convex part of the body is cut on its own so
+ * compounds work too. The body is removed and the fragments returned.
+ */
+pub fn fracture(specsWorld: &mut specs::World, entity: specs::Entity, point: &nalgebra::Point2<f32>, pieces: usize) -> Vec<specs::Entity> {
+    let (outline, velocity, centerOfMass) = {
+        let physics = specsWorld.read_resource::<PhysicsWorld>();
+        let colliders = specsWorld.read_storage::<Collider>();
+        let rigidBodies = specsWorld.read_storage::<RigidBody>();
+
+        let object = colliders.get(entity).and_then(|c| physics.collision_world().collision_object(c.object_handle));
+        let rb = rigidBodies.get(entity).and_then(|r| physics.rigid_body(r.object_handle));
+
+        match (object, rb) {
+            (Some(object), Some(rb)) => (
+                crate::fluid::polygons(object.shape().as_ref(), object.position()),
+                rb.velocity().clone(),
+                rb.center_of_mass(),
+            ),
+            _ => return Vec::new(),
+        }
+    };
+
+    let material = specsWorld.read_storage::<MaterialRef>().get(entity).map(|m| m.name.clone());
+    let render = specsWorld.read_storage::<Render>().get(entity).cloned().unwrap_or_default();
+
+    let pieces = pieces.max(3);
+    let start = (point.y - centerOfMass.y).atan2(point.x - centerOfMass.x);
+    let wedges: Vec<Vec<Point>> = (0..pieces)
+        .map(|i| {
+            let angle = |k: usize| start + k as f32 * 2.0 * std::f32::consts::PI / pieces as f32;
+            let (a0, a1) = (angle(i), angle(i + 1));
+            vec![
+                [point.x, point.y],
+                [point.x + a0.cos() * SLICE_LENGTH, point.y + a0.sin() * SLICE_LENGTH],
+                [point.x + a1.cos() * SLICE_LENGTH, point.y + a1.sin() * SLICE_LENGTH],
+            ]
+        })
+        .collect();
+
+    crate::world::destroy_entity(specsWorld, entity);
+
+    let mut fragments = Vec::new();
+    for polygon in outline.iter() {
+        for wedge in wedges.iter() {
+            let part = geometry::clip_convex(polygon, wedge);
+            if part.len() < 3 || geometry::area(&part) < MIN_FRAGMENT_AREA {
+                continue;
+            }
+
+            let center = geometry::centroid(&part);
+            let local: Vec<Point> = part.iter().map(|p| [p[0] - center[0], p[1] - center[1]]).collect();
+            let shape = match ShapeDesc::convex(&local) {
+                Some(shape) => shape,
+                None => continue,
+            };
+
+            // the fragment moves the way its part of the body was moving
+            let arm = nalgebra::Point2::new(center[0], center[1]) - centerOfMass;
+            let linear = crate::system::point_velocity(&velocity, &arm);
+
+            let mut builder = BodyBuilder::new(specsWorld, shape)
+                .position(center[0], center[1])
+                .velocity(linear, velocity.angular)
+                .render(render.style, render.color)
+                .with(Lifetime { remaining: FRAGMENT_LIFETIME });
+
+            if let Some(material) = material.as_ref() {
+                builder = builder.material(material);
+            }
+
+            fragments.push(builder.build());
+        }
+    }
+
+    debug!("Fractured {:?} into {} fragments", entity, fragments.len());
+    fragments
+}
+
+/**
+ * Fractures every breakable body that took an impact above its threshold
+ * since the last call. Needs the whole world to create the fragments, so
+ * it runs after the dispatcher rather than as a system.
+ */
+pub fn shatter(specsWorld: &mut specs::World) {
+    let impacts = std::mem::replace(&mut specsWorld.write_resource::<Impacts>().impacts, Vec::new());
+
+    // only the hardest hit on each body counts
+    let mut hardest: HashMap<specs::Entity, Impact> = HashMap::new();
+    for impact in impacts {
+        let entry = hardest.entry(impact.entity).or_insert(impact);
+        if impact.impulse > entry.impulse {
+            *entry = impact;
+        }
+    }
+
+    for (entity, impact) in hardest {
+        let breakable = match specsWorld.read_storage::<Breakable>().get(entity) {
+            Some(breakable) if impact.impulse > breakable.threshold => *breakable,
+            _ => continue,
+        };
+
+        debug!("Impact of {:.0} on {:?}", impact.impulse, entity);
+        fracture(specsWorld, entity, &impact.point, breakable.pieces);
+    }
+}
+
+
+
+/**
+ * Holds every breakable joint together and removes the ones pulled past
+ * their threshold or whose bodies are gone.
+ */
+pub struct BreakableJointSystem;
+
+impl<'a> specs::System<'a> for BreakableJointSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        specs::ReadStorage<'a, BreakableJoint>,
+        specs::ReadStorage<'a, RigidBody>,
+        specs::WriteExpect<'a, PhysicsWorld>,
+    );
+
+    fn run(&mut self, (entities, joints, rigidBodies, mut physics_world): Self::SystemData) {
+        let dt = physics_world.timestep();
+        let mut broken = Vec::new();
+
+        for (entity, joint) in (&entities, &joints).join() {
+            let (a, b) = match (joint.a.resolve(&physics_world, &rigidBodies), joint.b.resolve(&physics_world, &rigidBodies)) {
+                (Some(a), Some(b)) => (a, b),
+                _ => {
+                    broken.push(entity);
+                    continue;
+                },
+            };
+
+            let correction = crate::system::spring(&a, &b, JOINT_STIFFNESS / (dt * dt), JOINT_DAMPING / dt);
+            let direction = match correction.try_normalize(std::f32::EPSILON) {
+                Some(direction) => direction,
+                None => continue,
+            };
+
+            let inverse = a.inverse_mass(&direction) + b.inverse_mass(&direction);
+            if inverse <= 0.0 {
+                continue;
+            }
+
+            let force = correction / inverse;
+            if force.norm() > joint.threshold {
+                debug!("Joint {:?} broke under {:.0}", entity, force.norm());
+                broken.push(entity);
+                continue;
+            }
+
+            a.push(&mut physics_world, &force);
+            b.push(&mut physics_world, &- force);
+        }
+
+        for entity in broken {
+            if let Err(e) = entities.delete(entity) {
+                warn!("Could not delete entity {:?}: {:?}", entity, e);
+            }
+        }
+    }
+}
+
+/// Draws every breakable joint as a short line between its two points
+pub fn draw_joints(context: &mut ggez::Context, specsWorld: &specs::World) -> ggez::GameResult<()> {
+    let joints = specsWorld.read_storage::<BreakableJoint>();
+    let rigidBodies = specsWorld.read_storage::<RigidBody>();
+    let physics_world = specsWorld.read_resource::<PhysicsWorld>();
+
+    ggez::graphics::set_color(context, ggez::graphics::Color::new(1.0, 0.6, 0.2, 1.0))?;
+
+    for joint in joints.join() {
+        if let (Some(a), Some(b)) = (joint.a.resolve(&physics_world, &rigidBodies), joint.b.resolve(&physics_world, &rigidBodies)) {
+            ggez::graphics::circle(context, ggez::graphics::DrawMode::Fill, ggez::graphics::Point2::new(a.position.x, a.position.y), JOINT_RADIUS, 0.5)?;
+            if a.position != b.position {
+                let line = [ggez::graphics::Point2::new(a.position.x, a.position.y), ggez::graphics::Point2::new(b.position.x, b.position.y)];
+                ggez::graphics::line(context, &line, 1.0)?;
+            }
+        }
+    }
+
+    ggez::graphics::set_color(context, ggez::graphics::WHITE)
+}
+
+
+
+/**
+ * Counts down lifetimes in simulated time, fades out what is about to go
+ * and removes what ran out along with its body.
+ */
+pub struct LifetimeSystem;
+
+impl<'a> specs::System<'a> for LifetimeSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        specs::WriteStorage<'a, Lifetime>,
+        specs::WriteStorage<'a, Render>,
+        specs::ReadStorage<'a, RigidBody>,
+        specs::WriteExpect<'a, PhysicsWorld>,
+    );
+
+    fn run(&mut self, (entities, mut lifetimes, mut renders, rigidBodies, mut physics_world): Self::SystemData) {
+        let dt = physics_world.timestep();
+        let mut expired = Vec::new();
+
+        for (entity, lifetime) in (&entities, &mut lifetimes).join() {
+            lifetime.remaining -= dt;
+
+            if let Some(render) = renders.get_mut(entity) {
+                render.color[3] = render.color[3].min(lifetime.remaining / FADE_TIME).max(0.0);
+            }
+
+            if lifetime.remaining <= 0.0 {
+                expired.push(entity);
+            }
+        }
+
+        for entity in expired {
+            if let Some(r) = rigidBodies.get(entity) {
+                physics_world.remove_bodies(&[r.object_handle]);
+            }
+
+            if let Err(e) = entities.delete(entity) {
+                warn!("Could not delete entity {:?}: {:?}", entity, e);
+            }
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use ggez;
-use ggez::event::{Events, EventHandler, MouseButton, Keycode, Mod};
+use ggez::event::{Events, EventHandler, MouseButton, MouseState, Keycode, Mod};
 use ggez_goodies::scene::{SceneStack, Scene};
 use ggez_goodies::input;
 
@@ -11,6 +11,7 @@ use crate::scene::menu::MenuScene;
 use crate::scene::physicstest::PhysicsTest;
 
 use std::boxed::Box;
+use std::collections::HashMap;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Button {
@@ -18,12 +19,50 @@ pub enum Button {
     Rotate,
     Gravity,
     Quit,
+    Tune,
+    Increase,
+    Decrease,
+    Preset(usize),
+    NextMaterial,
+    LoadScene,
+    NextShape,
+    NextTool,
+    Editor,
+    Turn,
+    Grow,
+    Shrink,
+    NextStatus,
+    NextColor,
+    Duplicate,
+    Place,
+    Delete,
+    Snap,
+    Save,
+    Load,
+    Undo,
+    Redo,
+    PreviousField,
+    NextField,
+    Jump,
+    NextBall,
+    PushAll,
+    Implode,
+    NextFalloff,
+    LineOfSight,
+    Weaker,
+    Stronger,
+    NextForce,
+    Debug,
+    Fluid,
+    Terrain,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Axis {
     Forward,
     Backward,
+    Left,
+    Right,
 }
 
 
@@ -35,10 +74,51 @@ pub fn bind_inputs() -> input::InputBinding<Axis, Button> {
     input::InputBinding::new()
         .bind_key_to_axis(Keycode::Up, Axis::Forward, true)
         .bind_key_to_axis(Keycode::Down, Axis::Backward, false)
+        .bind_key_to_axis(Keycode::A, Axis::Left, false)
+        .bind_key_to_axis(Keycode::D, Axis::Right, true)
         .bind_key_to_button(Keycode::G, Button::Gravity)
         .bind_key_to_button(Keycode::Z, Button::Rotate)
         .bind_key_to_button(Keycode::Space, Button::Shoot)
         .bind_key_to_button(Keycode::Escape, Button::Quit)
+        .bind_key_to_button(Keycode::T, Button::Tune)
+        .bind_key_to_button(Keycode::Right, Button::Increase)
+        .bind_key_to_button(Keycode::Left, Button::Decrease)
+        .bind_key_to_button(Keycode::Num1, Button::Preset(0))
+        .bind_key_to_button(Keycode::Num2, Button::Preset(1))
+        .bind_key_to_button(Keycode::Num3, Button::Preset(2))
+        .bind_key_to_button(Keycode::Num4, Button::Preset(3))
+        .bind_key_to_button(Keycode::Num5, Button::Preset(4))
+        .bind_key_to_button(Keycode::Num6, Button::Preset(5))
+        .bind_key_to_button(Keycode::M, Button::NextMaterial)
+        .bind_key_to_button(Keycode::L, Button::LoadScene)
+        .bind_key_to_button(Keycode::X, Button::NextShape)
+        .bind_key_to_button(Keycode::Q, Button::NextTool)
+        .bind_key_to_button(Keycode::E, Button::Editor)
+        .bind_key_to_button(Keycode::R, Button::Turn)
+        .bind_key_to_button(Keycode::Equals, Button::Grow)
+        .bind_key_to_button(Keycode::Minus, Button::Shrink)
+        .bind_key_to_button(Keycode::B, Button::NextStatus)
+        .bind_key_to_button(Keycode::K, Button::NextColor)
+        .bind_key_to_button(Keycode::C, Button::Duplicate)
+        .bind_key_to_button(Keycode::Insert, Button::Place)
+        .bind_key_to_button(Keycode::Delete, Button::Delete)
+        .bind_key_to_button(Keycode::N, Button::Snap)
+        .bind_key_to_button(Keycode::F5, Button::Save)
+        .bind_key_to_button(Keycode::F9, Button::Load)
+        .bind_key_to_button(Keycode::PageUp, Button::PreviousField)
+        .bind_key_to_button(Keycode::PageDown, Button::NextField)
+        .bind_key_to_button(Keycode::W, Button::Jump)
+        .bind_key_to_button(Keycode::Tab, Button::NextBall)
+        .bind_key_to_button(Keycode::P, Button::PushAll)
+        .bind_key_to_button(Keycode::I, Button::Implode)
+        .bind_key_to_button(Keycode::F, Button::NextFalloff)
+        .bind_key_to_button(Keycode::O, Button::LineOfSight)
+        .bind_key_to_button(Keycode::LeftBracket, Button::Weaker)
+        .bind_key_to_button(Keycode::RightBracket, Button::Stronger)
+        .bind_key_to_button(Keycode::V, Button::NextForce)
+        .bind_key_to_button(Keycode::F3, Button::Debug)
+        .bind_key_to_button(Keycode::H, Button::Fluid)
+        .bind_key_to_button(Keycode::U, Button::Terrain)
 }
 
 /**
@@ -48,6 +128,25 @@ pub fn bind_inputs() -> input::InputBinding<Axis, Button> {
 pub struct Game {
     sceneStack: SceneStack<SceneWorld, InputEvent>,
     input_binding: InputBinding,
+
+    /// The effect each held key resolved to when it went down, released as
+    /// is so letting go of a modifier first can't leave an effect stuck
+    pressed: HashMap<Keycode, InputEvent>,
+}
+
+/**
+ * Resolves a key to its input effect. Ctrl+Z and Ctrl+Shift+Z are undo and
+ * redo, which the key bindings can't express since they ignore modifiers.
+ */
+fn resolve_key(binding: &InputBinding, keycode: Keycode, keymod: Mod) -> Option<InputEvent> {
+    let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+    let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+
+    match (keycode, ctrl, shift) {
+        (Keycode::Z, true, false) => Some(input::InputEffect::Button(Button::Undo)),
+        (Keycode::Z, true, true) => Some(input::InputEffect::Button(Button::Redo)),
+        _ => binding.resolve(keycode),
+    }
 }
 
 impl Game {
@@ -66,6 +165,7 @@ impl Game {
         Game {
             sceneStack: sceneStack,
             input_binding: bind_inputs(),
+            pressed: HashMap::new(),
         }
     }
 }
@@ -88,6 +188,7 @@ impl EventHandler for Game {
 
         while ggez::timer::check_update_time(context, DESIRED_FPS) {
             self.sceneStack.update();
+            self.sceneStack.world.mouse.clear_edges();
         }
 
         if self.sceneStack.world.quit {
@@ -98,25 +199,45 @@ impl EventHandler for Game {
         Ok(())
     }
 
-    fn mouse_button_down_event(&mut self, _ctx: &mut ggez::Context, button: MouseButton, x: i32, y: i32) {
-        if let Some(ev) = self.input_binding.resolve(keycode) {
-            self.sceneStack.world.input.update_effect(ev, true);
-            self.sceneStack.input(ev, true);
-        }
+    fn mouse_button_down_event(&mut self, context: &mut ggez::Context, button: MouseButton, x: i32, y: i32) {
+        let world = &mut self.sceneStack.world;
+        world.mouse.position = world.screen_to_world(context, x, y);
+        world.mouse.update_button(button, true);
+    }
+
+    fn mouse_button_up_event(&mut self, context: &mut ggez::Context, button: MouseButton, x: i32, y: i32) {
+        let world = &mut self.sceneStack.world;
+        world.mouse.position = world.screen_to_world(context, x, y);
+        world.mouse.update_button(button, false);
     }
 
-    fn mouse_button_up_event(&mut self, _ctx: &mut ggez::Context, button: MouseButton, x: i32, y: i32) {
-        println!("Mouse button released: {:?}, x: {}, y: {}", button, x, y);
+    fn mouse_motion_event(
+        &mut self,
+        context: &mut ggez::Context,
+        _state: MouseState,
+        x: i32,
+        y: i32,
+        _xrel: i32,
+        _yrel: i32
+    ) {
+        let world = &mut self.sceneStack.world;
+        world.mouse.position = world.screen_to_world(context, x, y);
     }
 
     fn key_down_event(
         self: &mut Self,
         context: &mut ggez::Context,
         keycode: Keycode,
-        _keymod: Mod,
+        keymod: Mod,
         _repeat: bool
     ) {
-        if let Some(ev) = self.input_binding.resolve(keycode) {
+        let resolved = match self.pressed.get(&keycode) {
+            Some(ev) => Some(*ev),
+            None => resolve_key(&self.input_binding, keycode, keymod),
+        };
+
+        if let Some(ev) = resolved {
+            self.pressed.insert(keycode, ev);
             self.sceneStack.world.input.update_effect(ev, true);
             self.sceneStack.input(ev, true);
         }
@@ -129,7 +250,7 @@ impl EventHandler for Game {
         _keymod: Mod,
         _repeat: bool
     ) {
-        if let Some(ev) = self.input_binding.resolve(keycode) {
+        if let Some(ev) = self.pressed.remove(&keycode) {
             self.sceneStack.world.input.update_effect(ev, false);
             self.sceneStack.input(ev, false);
         }
@@ -0,0 +1,553 @@
+/**
+ *
+ * 2D polygon helpers used to build collider shapes.
+ *
+ * Points are plain `[f32; 2]` so they can be shared with the serializable
+ * shape descriptions.
+ *
+ */
+
+pub type Point = [f32; 2];
+
+fn cross(o: &Point, a: &Point, b: &Point) -> f32 {
+    (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+}
+
+/**
+ * Convex hull of a set of points (Andrew's monotone chain). The result is in
+ * counter clockwise order (in a y up frame) without duplicate or collinear
+ * points. Fewer than three distinct points give a degenerate hull.
+ */
+pub fn convex_hull(points: &[Point]) -> Vec<Point> {
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| {
+        a[0].partial_cmp(&b[0])
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a[1].partial_cmp(&b[1]).unwrap_or(std::cmp::Ordering::Equal))
+    });
+    sorted.dedup();
+
+    if sorted.len() < 3 {
+        return sorted;
+    }
+
+    let mut hull: Vec<Point> = Vec::with_capacity(sorted.len() * 2);
+
+    // lower hull
+    for p in sorted.iter() {
+        while hull.len() >= 2 && cross(&hull[hull.len() - 2], &hull[hull.len() - 1], p) <= 0.0 {
+            hull.pop();
+        }
+        hull.push(*p);
+    }
+
+    // upper hull
+    let lower_len = hull.len() + 1;
+    for p in sorted.iter().rev().skip(1) {
+        while hull.len() >= lower_len && cross(&hull[hull.len() - 2], &hull[hull.len() - 1], p) <= 0.0 {
+            hull.pop();
+        }
+        hull.push(*p);
+    }
+
+    hull.pop();
+    hull
+}
+
+/// Transforms a local point by a position and rotation
+pub fn transform(p: &Point, position: &Point, rotation: f32) -> Point {
+    let (sin, cos) = rotation.sin_cos();
+    [
+        position[0] + p[0] * cos - p[1] * sin,
+        position[1] + p[0] * sin + p[1] * cos,
+    ]
+}
+
+/// Points around a circle, used to draw and approximate round shapes
+pub fn circle(center: &Point, radius: f32, segments: usize) -> Vec<Point> {
+    (0..segments)
+        .map(|i| {
+            let angle = i as f32 / segments as f32 * std::f32::consts::PI * 2.0;
+            [center[0] + radius * angle.cos(), center[1] + radius * angle.sin()]
+        })
+        .collect()
+}
+
+/// Outline of a capsule aligned with the y axis
+pub fn capsule(half_height: f32, radius: f32, segments: usize) -> Vec<Point> {
+    let half = segments / 2;
+    let mut points = Vec::with_capacity(segments + 2);
+
+    for i in 0..=half {
+        let angle = i as f32 / half as f32 * std::f32::consts::PI;
+        points.push([radius * angle.cos(), half_height + radius * angle.sin()]);
+    }
+
+    for i in 0..=half {
+        let angle = std::f32::consts::PI + i as f32 / half as f32 * std::f32::consts::PI;
+        points.push([radius * angle.cos(), - half_height + radius * angle.sin()]);
+    }
+
+    points
+}
+
+/// Twice the signed area, positive for counter clockwise points in a y up frame
+fn signed_area2(points: &[Point]) -> f32 {
+    let n = points.len();
+    (0..n)
+        .map(|i| {
+            let a = &points[i];
+            let b = &points[(i + 1) % n];
+            a[0] * b[1] - b[0] * a[1]
+        })
+        .sum()
+}
+
+pub fn area(points: &[Point]) -> f32 {
+    signed_area2(points).abs() * 0.5
+}
+
+/// Area weighted centroid of a simple polygon
+pub fn centroid(points: &[Point]) -> Point {
+    let n = points.len();
+    let area2 = signed_area2(points);
+    if area2.abs() < std::f32::EPSILON {
+        let sum = points.iter().fold([0.0, 0.0], |acc, p| [acc[0] + p[0], acc[1] + p[1]]);
+        return [sum[0] / n as f32, sum[1] / n as f32];
+    }
+
+    let mut c = [0.0, 0.0];
+    for i in 0..n {
+        let a = &points[i];
+        let b = &points[(i + 1) % n];
+        let f = a[0] * b[1] - b[0] * a[1];
+        c[0] += (a[0] + b[0]) * f;
+        c[1] += (a[1] + b[1]) * f;
+    }
+
+    [c[0] / (3.0 * area2), c[1] / (3.0 * area2)]
+}
+
+/**
+ * Clips a polygon to an axis aligned rectangle (Sutherland-Hodgman). The
+ * polygon is assumed convex, concave ones may leave zero width bridges
+ * along the rectangle's edges which don't change the area. Returns an
+ * empty list when nothing is left.
+ */
+pub fn clip_to_rect(points: &[Point], min: &Point, max: &Point) -> Vec<Point> {
+    // each edge as (axis, bound, keep points below the bound)
+    let edges = [(0, min[0], false), (0, max[0], true), (1, min[1], false), (1, max[1], true)];
+    let mut output = points.to_vec();
+
+    for (axis, bound, below) in edges.iter() {
+        let input = std::mem::replace(&mut output, Vec::new());
+        let inside = |p: &Point| if *below { p[*axis] <= *bound } else { p[*axis] >= *bound };
+
+        for i in 0..input.len() {
+            let current = input[i];
+            let previous = input[(i + input.len() - 1) % input.len()];
+
+            if inside(&current) != inside(&previous) {
+                let t = (bound - previous[*axis]) / (current[*axis] - previous[*axis]);
+                output.push([
+                    previous[0] + (current[0] - previous[0]) * t,
+                    previous[1] + (current[1] - previous[1]) * t,
+                ]);
+            }
+
+            if inside(&current) {
+                output.push(current);
+            }
+        }
+    }
+
+    output
+}
+
+/**
+ * Clips a convex polygon to another convex polygon of either winding
+ * (Sutherland-Hodgman). Returns an empty list when they don't overlap.
+ */
+pub fn clip_convex(points: &[Point], clip: &[Point]) -> Vec<Point> {
+    let winding = signed_area2(clip).signum();
+    let mut output = points.to_vec();
+
+    for j in 0..clip.len() {
+        let (a, b) = (clip[j], clip[(j + 1) % clip.len()]);
+        let side = |p: &Point| ((b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])) * winding;
+        let input = std::mem::replace(&mut output, Vec::new());
+
+        for i in 0..input.len() {
+            let current = input[i];
+            let previous = input[(i + input.len() - 1) % input.len()];
+            let (sc, sp) = (side(&current), side(&previous));
+
+            if (sc >= 0.0) != (sp >= 0.0) {
+                let t = sp / (sp - sc);
+                output.push([
+                    previous[0] + (current[0] - previous[0]) * t,
+                    previous[1] + (current[1] - previous[1]) * t,
+                ]);
+            }
+
+            if sc >= 0.0 {
+                output.push(current);
+            }
+        }
+    }
+
+    output
+}
+
+/// Whether the points wind counter clockwise in a y up frame
+pub fn is_ccw(points: &[Point]) -> bool {
+    signed_area2(points) > 0.0
+}
+
+/// Reverses the points if needed so they wind counter clockwise
+pub fn make_ccw(points: &mut Vec<Point>) {
+    if signed_area2(points) < 0.0 {
+        points.reverse();
+    }
+}
+
+fn distance_to_segment(p: &Point, a: &Point, b: &Point) -> f32 {
+    let ab = [b[0] - a[0], b[1] - a[1]];
+    let ap = [p[0] - a[0], p[1] - a[1]];
+    let length2 = ab[0] * ab[0] + ab[1] * ab[1];
+    let t = match length2 > 0.0 {
+        true => ((ap[0] * ab[0] + ap[1] * ab[1]) / length2).max(0.0).min(1.0),
+        false => 0.0,
+    };
+    let closest = [a[0] + ab[0] * t, a[1] + ab[1] * t];
+    ((p[0] - closest[0]).powi(2) + (p[1] - closest[1]).powi(2)).sqrt()
+}
+
+/**
+ * Douglas-Peucker simplification of an open line. Points closer than
+ * `tolerance` to the simplified line are dropped. The end points are kept.
+ */
+pub fn simplify(points: &[Point], tolerance: f32) -> Vec<Point> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let last = points.len() - 1;
+    let (index, distance) = (1..last)
+        .map(|i| (i, distance_to_segment(&points[i], &points[0], &points[last])))
+        .fold((0, 0.0), |best, current| if current.1 > best.1 { current } else { best });
+
+    if distance <= tolerance {
+        return vec![points[0], points[last]];
+    }
+
+    let mut left = simplify(&points[..=index], tolerance);
+    let right = simplify(&points[index..], tolerance);
+    left.pop();
+    left.extend(right);
+    left
+}
+
+/**
+ * Douglas-Peucker simplification of a closed polygon. The polygon is split at
+ * the point furthest from the first so both halves keep their shape.
+ */
+pub fn simplify_closed(points: &[Point], tolerance: f32) -> Vec<Point> {
+    if points.len() < 4 {
+        return points.to_vec();
+    }
+
+    let first = &points[0];
+    let far = (1..points.len())
+        .max_by(|a, b| {
+            let da = (points[*a][0] - first[0]).powi(2) + (points[*a][1] - first[1]).powi(2);
+            let db = (points[*b][0] - first[0]).powi(2) + (points[*b][1] - first[1]).powi(2);
+            da.partial_cmp(&db).unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .unwrap_or(1);
+
+    let mut closing = points[far..].to_vec();
+    closing.push(points[0]);
+
+    let mut result = simplify(&points[..=far], tolerance);
+    result.pop();
+    result.extend(simplify(&closing, tolerance));
+    result.pop();
+    result
+}
+
+/// Drops repeated points and points that lie on the line through their neighbours
+pub fn remove_collinear(points: &[Point]) -> Vec<Point> {
+    let mut points = points.to_vec();
+    points.dedup();
+    while points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+
+    let n = points.len();
+    (0..n)
+        .filter(|&i| cross(&points[(i + n - 1) % n], &points[i], &points[(i + 1) % n]).abs() > 1e-3)
+        .map(|i| points[i])
+        .collect()
+}
+
+fn is_convex_corner(a: &Point, b: &Point, c: &Point) -> bool {
+    cross(a, b, c) > 0.0
+}
+
+fn in_triangle(p: &Point, a: &Point, b: &Point, c: &Point) -> bool {
+    cross(a, b, p) >= 0.0 && cross(b, c, p) >= 0.0 && cross(c, a, p) >= 0.0
+}
+
+/// Whether two edges that don't share a point cross each other
+fn crosses_itself(points: &[Point]) -> bool {
+    let n = points.len();
+    (0..n).any(|i| {
+        let (p, q) = (&points[i], &points[(i + 1) % n]);
+        ((i + 2)..n)
+            .filter(|&j| (j + 1) % n != i)
+            .any(|j| {
+                let (r, s) = (&points[j], &points[(j + 1) % n]);
+                cross(p, q, r) * cross(p, q, s) < 0.0 && cross(r, s, p) * cross(r, s, q) < 0.0
+            })
+    })
+}
+
+/**
+ * Ear clipping triangulation of a simple counter clockwise polygon.
+ * Returns `None` when the polygon intersects itself.
+ */
+pub fn triangulate(points: &[Point]) -> Option<Vec<[usize; 3]>> {
+    if crosses_itself(points) {
+        return None;
+    }
+
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len());
+
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|&i| {
+            let (a, b, c) = (remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]);
+            is_convex_corner(&points[a], &points[b], &points[c])
+                && !remaining.iter()
+                    .filter(|&&j| j != a && j != b && j != c)
+                    .any(|&j| in_triangle(&points[j], &points[a], &points[b], &points[c]))
+        })?;
+
+        triangles.push([remaining[(ear + n - 1) % n], remaining[ear], remaining[(ear + 1) % n]]);
+        remaining.remove(ear);
+    }
+
+    if remaining.len() == 3 {
+        triangles.push([remaining[0], remaining[1], remaining[2]]);
+    }
+
+    Some(triangles)
+}
+
+fn is_convex(polygon: &[usize], points: &[Point]) -> bool {
+    let n = polygon.len();
+    (0..n).all(|i| {
+        cross(&points[polygon[(i + n - 1) % n]], &points[polygon[i]], &points[polygon[(i + 1) % n]]) >= 0.0
+    })
+}
+
+/// Merges two polygons sharing the edge `a`-`b`, `a` then `b` in `first`
+fn merge(first: &[usize], second: &[usize], a: usize, b: usize) -> Vec<usize> {
+    let start = first.iter().position(|&i| i == b).unwrap();
+    let mut merged: Vec<usize> = first[start..].iter().chain(first[..start].iter()).cloned().collect();
+
+    let from = second.iter().position(|&i| i == a).unwrap();
+    let n = second.len();
+    let mut i = (from + 1) % n;
+    while second[i] != b {
+        merged.push(second[i]);
+        i = (i + 1) % n;
+    }
+
+    merged
+}
+
+/**
+ * Splits a simple polygon into convex pieces (Hertel-Mehlhorn). The polygon
+ * is triangulated and neighbouring pieces are merged for as long as the
+ * result stays convex. Returns `None` when the polygon intersects itself or
+ * has no area.
+ */
+pub fn convex_decomposition(points: &[Point]) -> Option<Vec<Vec<Point>>> {
+    let mut points = remove_collinear(points);
+    if points.len() < 3 {
+        return None;
+    }
+    make_ccw(&mut points);
+
+    let mut pieces: Vec<Vec<usize>> = triangulate(&points)?
+        .into_iter()
+        .map(|t| t.to_vec())
+        .collect();
+
+    let mut merged_any = true;
+    while merged_any {
+        merged_any = false;
+
+        'search: for i in 0..pieces.len() {
+            for j in (i + 1)..pieces.len() {
+                let n = pieces[i].len();
+                for k in 0..n {
+                    let (a, b) = (pieces[i][k], pieces[i][(k + 1) % n]);
+                    let m = pieces[j].len();
+                    let shared = (0..m).any(|l| pieces[j][l] == b && pieces[j][(l + 1) % m] == a);
+                    if !shared {
+                        continue;
+                    }
+
+                    let candidate = merge(&pieces[i], &pieces[j], a, b);
+                    if is_convex(&candidate, &points) {
+                        pieces[i] = candidate;
+                        pieces.remove(j);
+                        merged_any = true;
+                        break 'search;
+                    }
+                }
+            }
+        }
+    }
+
+    Some(pieces.into_iter()
+        .map(|piece| piece.into_iter().map(|i| points[i]).collect())
+        .collect())
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An L made of two unit squares side by side with one on top
+    const L_SHAPE: [Point; 6] = [[0.0, 0.0], [2.0, 0.0], [2.0, 1.0], [1.0, 1.0], [1.0, 2.0], [0.0, 2.0]];
+
+    /// Corners of a unit square in a y up frame, crossed into a bow tie
+    const BOW_TIE: [Point; 4] = [[0.0, 0.0], [1.0, 1.0], [1.0, 0.0], [0.0, 1.0]];
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    fn square(size: f32) -> Vec<Point> {
+        vec![[0.0, 0.0], [size, 0.0], [size, size], [0.0, size]]
+    }
+
+    /// Whether every corner turns left in a y up frame
+    fn is_convex_ccw(piece: &[Point]) -> bool {
+        let n = piece.len();
+        n >= 3 && (0..n).all(|i| cross(&piece[(i + n - 1) % n], &piece[i], &piece[(i + 1) % n]) >= 0.0)
+    }
+
+    #[test]
+    fn convex_polygons_stay_whole() {
+        let pieces = convex_decomposition(&square(2.0)).unwrap();
+        assert_eq!(pieces.len(), 1);
+        assert_eq!(pieces[0].len(), 4);
+    }
+
+    #[test]
+    fn concave_polygons_split_into_convex_pieces() {
+        let pieces = convex_decomposition(&L_SHAPE).unwrap();
+
+        assert!(pieces.len() >= 2);
+        assert!(pieces.iter().all(|piece| is_convex_ccw(piece)));
+        assert!(close(pieces.iter().map(|piece| area(piece)).sum(), 3.0));
+    }
+
+    #[test]
+    fn decomposition_takes_either_winding() {
+        let mut clockwise = L_SHAPE.to_vec();
+        clockwise.reverse();
+
+        let pieces = convex_decomposition(&clockwise).unwrap();
+        assert!(pieces.iter().all(|piece| is_convex_ccw(piece)));
+        assert!(close(pieces.iter().map(|piece| area(piece)).sum(), 3.0));
+    }
+
+    #[test]
+    fn decomposition_drops_collinear_and_repeated_points() {
+        let points = [[0.0, 0.0], [1.0, 0.0], [2.0, 0.0], [2.0, 2.0], [2.0, 2.0], [0.0, 2.0], [0.0, 0.0]];
+        let pieces = convex_decomposition(&points).unwrap();
+
+        assert_eq!(pieces.len(), 1);
+        assert_eq!(pieces[0].len(), 4);
+    }
+
+    #[test]
+    fn decomposition_rejects_crossing_and_degenerate_polygons() {
+        assert!(convex_decomposition(&BOW_TIE).is_none());
+        assert!(convex_decomposition(&[[0.0, 0.0], [1.0, 1.0], [2.0, 2.0], [3.0, 3.0]]).is_none());
+        assert!(convex_decomposition(&[[0.0, 0.0], [1.0, 0.0]]).is_none());
+        assert!(convex_decomposition(&[]).is_none());
+    }
+
+    #[test]
+    fn triangulate_covers_the_polygon() {
+        let triangles = triangulate(&L_SHAPE).unwrap();
+        assert_eq!(triangles.len(), L_SHAPE.len() - 2);
+
+        let total: f32 = triangles.iter()
+            .map(|t| {
+                let triangle = [L_SHAPE[t[0]], L_SHAPE[t[1]], L_SHAPE[t[2]]];
+                assert!(is_ccw(&triangle));
+                area(&triangle)
+            })
+            .sum();
+        assert!(close(total, 3.0));
+    }
+
+    #[test]
+    fn triangulate_rejects_crossing_polygons() {
+        assert!(triangulate(&BOW_TIE).is_none());
+        assert_eq!(triangulate(&[[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]), Some(vec![[0, 1, 2]]));
+    }
+
+    #[test]
+    fn remove_collinear_keeps_the_corners() {
+        let points = [[0.0, 0.0], [1.0, 0.0], [2.0, 0.0], [2.0, 1.0], [2.0, 2.0], [0.0, 2.0], [0.0, 2.0]];
+        assert_eq!(remove_collinear(&points), vec![[0.0, 0.0], [2.0, 0.0], [2.0, 2.0], [0.0, 2.0]]);
+
+        assert!(remove_collinear(&[[0.0, 0.0], [1.0, 1.0], [2.0, 2.0]]).is_empty());
+    }
+
+    #[test]
+    fn simplify_closed_drops_small_bumps() {
+        let points = [[0.0, 0.0], [5.0, 0.05], [10.0, 0.0], [10.0, 10.0], [5.0, 9.95], [0.0, 10.0]];
+        let simplified = simplify_closed(&points, 0.1);
+        assert_eq!(simplified, vec![[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]]);
+
+        // bumps over the tolerance stay
+        assert_eq!(simplify_closed(&points, 0.01).len(), points.len());
+    }
+
+    #[test]
+    fn simplify_closed_leaves_triangles_alone() {
+        let triangle = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]];
+        assert_eq!(simplify_closed(&triangle, 10.0), triangle.to_vec());
+    }
+
+    #[test]
+    fn clip_convex_keeps_the_overlap() {
+        let clip: Vec<Point> = square(10.0).iter().map(|p| [p[0] + 5.0, p[1] + 5.0]).collect();
+        assert!(close(area(&clip_convex(&square(10.0), &clip)), 25.0));
+
+        let mut clockwise = clip.clone();
+        clockwise.reverse();
+        assert!(close(area(&clip_convex(&square(10.0), &clockwise)), 25.0));
+
+        assert!(close(area(&clip_convex(&square(10.0), &square(20.0))), 100.0));
+    }
+
+    #[test]
+    fn clip_convex_of_disjoint_polygons_is_empty() {
+        let clip: Vec<Point> = square(10.0).iter().map(|p| [p[0] + 20.0, p[1]]).collect();
+        assert!(clip_convex(&square(10.0), &clip).is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+/**
+ *
+ * Undo and redo of world edits.
+ *
+ * Every undoable operation is recorded as a `Command` holding enough state
+ * to apply it in both directions. Bodies are referred to by their `Uid`
+ * rather than their entity, since undoing a delete spawns a new entity.
+ *
+ */
+
+use std::collections::VecDeque;
+
+use specs;
+use specs::prelude::*;
+
+use log::*;
+
+use crate::scenefile::BodyDesc;
+use crate::tuning::Tuning;
+
+/// Oldest commands are dropped past this many
+pub const HISTORY_LIMIT: usize = 100;
+
+/// Stable id of a body across deletes and respawns
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Component)]
+#[storage(VecStorage)]
+pub struct Uid(pub u64);
+
+#[derive(Clone, Debug)]
+pub enum Command {
+    Spawn { uid: Uid, desc: BodyDesc },
+    Delete { uid: Uid, desc: BodyDesc },
+    Move { uid: Uid, from: nalgebra::Isometry2<f32>, to: nalgebra::Isometry2<f32> },
+    Edit { uid: Uid, before: BodyDesc, after: BodyDesc },
+    Settings { before: Tuning, after: Tuning },
+
+    /// Several commands undone and redone together
+    Batch(Vec<Command>),
+}
+
+impl Command {
+    fn inverse(self) -> Command {
+        match self {
+            Command::Spawn { uid, desc } => Command::Delete { uid, desc },
+            Command::Delete { uid, desc } => Command::Spawn { uid, desc },
+            Command::Move { uid, from, to } => Command::Move { uid, from: to, to: from },
+            Command::Edit { uid, before, after } => Command::Edit { uid, before: after, after: before },
+            Command::Settings { before, after } => Command::Settings { before: after, after: before },
+            Command::Batch(commands) => Command::Batch(commands.into_iter().rev().map(Command::inverse).collect()),
+        }
+    }
+
+    /// Does the command's change. Undoing is applying the inverse.
+    fn apply(&self, specsWorld: &mut specs::World) {
+        match self {
+            Command::Spawn { uid, desc } => {
+                if let Some(entity) = desc.spawn(specsWorld) {
+                    specsWorld.write_storage::<Uid>().insert(entity, *uid).ok();
+                }
+            },
+
+            Command::Delete { uid, .. } => {
+                if let Some(entity) = find(specsWorld, *uid) {
+                    crate::world::destroy_entity(specsWorld, entity);
+                }
+            },
+
+            Command::Move { uid, to, .. } => {
+                if let Some(entity) = find(specsWorld, *uid) {
+                    crate::world::set_pose(specsWorld, entity, *to);
+                }
+            },
+
+            Command::Edit { uid, after, .. } => {
+                if let Some(entity) = find(specsWorld, *uid) {
+                    after.rebuild(specsWorld, entity);
+                }
+            },
+
+            Command::Settings { after, .. } => {
+                let mut tuning = specsWorld.write_resource::<Tuning>();
+                let walls_dirty = tuning.wall_size != after.wall_size || tuning.ground != after.ground;
+                *tuning = after.clone();
+                tuning.walls_dirty = walls_dirty;
+                tuning.apply(specsWorld);
+            },
+
+            Command::Batch(commands) => {
+                for command in commands {
+                    command.apply(specsWorld);
+                }
+            },
+        }
+    }
+}
+
+
+
+/// The undo and redo stacks. Stored as a specs resource.
+pub struct History {
+    undo: VecDeque<Command>,
+    redo: Vec<Command>,
+    limit: usize,
+    next_uid: u64,
+}
+
+impl Default for History {
+    fn default() -> History {
+        History::new(HISTORY_LIMIT)
+    }
+}
+
+impl History {
+    pub fn new(limit: usize) -> History {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            limit: limit,
+            next_uid: 0,
+        }
+    }
+
+    pub fn push(&mut self, command: Command) {
+        self.redo.clear();
+        self.undo.push_back(command);
+        while self.undo.len() > self.limit {
+            self.undo.pop_front();
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+
+
+/// The entity with the given uid, if it is still in the world
+pub fn find(specsWorld: &specs::World, uid: Uid) -> Option<specs::Entity> {
+    let entities = specsWorld.entities();
+    let uids = specsWorld.read_storage::<Uid>();
+    (&entities, &uids).join().find(|(_, u)| **u == uid).map(|(e, _)| e)
+}
+
+/// The uid of an entity, giving it one if it doesn't have one yet
+pub fn uid(specsWorld: &specs::World, entity: specs::Entity) -> Uid {
+    let mut uids = specsWorld.write_storage::<Uid>();
+    if let Some(uid) = uids.get(entity) {
+        return *uid;
+    }
+
+    let mut history = specsWorld.write_resource::<History>();
+    let uid = Uid(history.next_uid);
+    history.next_uid += 1;
+    uids.insert(entity, uid).ok();
+    uid
+}
+
+pub fn record(specsWorld: &specs::World, command: Command) {
+    specsWorld.write_resource::<History>().push(command);
+}
+
+/// Records the spawn of an entity that was just built
+pub fn record_spawn(specsWorld: &specs::World, entity: specs::Entity) {
+    if let Some(desc) = BodyDesc::describe(specsWorld, entity) {
+        record(specsWorld, Command::Spawn { uid: uid(specsWorld, entity), desc: desc });
+    }
+}
+
+/// Records and performs the delete of an entity
+pub fn delete(specsWorld: &mut specs::World, entity: specs::Entity) {
+    if let Some(desc) = BodyDesc::describe(specsWorld, entity) {
+        let command = Command::Delete { uid: uid(specsWorld, entity), desc: desc };
+        record(specsWorld, command);
+    }
+
+    crate::world::destroy_entity(specsWorld, entity);
+}
+
+/// Records and performs an edit of an entity's description
+pub fn edit(specsWorld: &mut specs::World, entity: specs::Entity, after: BodyDesc) {
+    if let Some(before) = BodyDesc::describe(specsWorld, entity) {
+        let command = Command::Edit { uid: uid(specsWorld, entity), before: before, after: after.clone() };
+        record(specsWorld, command);
+    }
+
+    after.rebuild(specsWorld, entity);
+}
+
+pub fn undo(specsWorld: &mut specs::World) {
+    let command = specsWorld.write_resource::<History>().undo.pop_back();
+    match command {
+        Some(command) => {
+            debug!("Undo {:?}", command);
+            let inverse = command.clone().inverse();
+            inverse.apply(specsWorld);
+            specsWorld.write_resource::<History>().redo.push(command);
+        },
+
+        None => debug!("Nothing to undo"),
+    }
+}
+
+pub fn redo(specsWorld: &mut specs::World) {
+    let command = specsWorld.write_resource::<History>().redo.pop();
+    match command {
+        Some(command) => {
+            debug!("Redo {:?}", command);
+            command.apply(specsWorld);
+            specsWorld.write_resource::<History>().undo.push_back(command);
+        },
+
+        None => debug!("Nothing to redo"),
+    }
+}
@@ -0,0 +1,296 @@
+/**
+ *
+ * Entity inspector.
+ *
+ * Shows the live state of a single selected body: its pose, velocities,
+ * mass properties, sleeping state and material along with the game
+ * components it carries. The values are read from the physics world every
+ * frame and most of them can be edited in place while the simulation runs.
+ *
+ */
+
+use nphysics2d::object::BodyStatus;
+
+use specs;
+use specs::prelude::*;
+
+use log::*;
+
+use crate::builder::Status;
+use crate::history::{self, Command};
+use crate::material::{MaterialLibrary, MaterialRef};
+use crate::scene::physicstest::Boundary;
+use crate::scenefile::BodyDesc;
+use crate::system::RigidBody;
+use crate::world::PhysicsWorld;
+
+/// An editable value of the selected body
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Field {
+    X,
+    Y,
+    Rotation,
+    VelocityX,
+    VelocityY,
+    AngularVelocity,
+    Mass,
+    Status,
+    Material,
+    Sleeping,
+}
+
+impl Field {
+    pub const ALL: [Field; 10] = [
+        Field::X,
+        Field::Y,
+        Field::Rotation,
+        Field::VelocityX,
+        Field::VelocityY,
+        Field::AngularVelocity,
+        Field::Mass,
+        Field::Status,
+        Field::Material,
+        Field::Sleeping,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Field::X => "x",
+            Field::Y => "y",
+            Field::Rotation => "rotation",
+            Field::VelocityX => "velocity x",
+            Field::VelocityY => "velocity y",
+            Field::AngularVelocity => "angular",
+            Field::Mass => "mass",
+            Field::Status => "status",
+            Field::Material => "material",
+            Field::Sleeping => "sleeping",
+        }
+    }
+
+    /// How much a single Left/Right press changes the value
+    pub fn step(&self) -> f32 {
+        match self {
+            Field::X | Field::Y => 5.0,
+            Field::Rotation => std::f32::consts::PI / 12.0,
+            Field::VelocityX | Field::VelocityY => 10.0,
+            Field::AngularVelocity => 0.5,
+            Field::Mass => 10.0,
+            _ => 1.0,
+        }
+    }
+}
+
+
+
+#[derive(Debug, Default)]
+pub struct Inspector {
+    pub selected: Option<specs::Entity>,
+    cursor: usize,
+}
+
+impl Inspector {
+    /// Selects the body under the point, or clears the selection
+    pub fn select(&mut self, specsWorld: &specs::World, point: &nalgebra::Point2<f32>) {
+        self.selected = crate::world::entity_at(specsWorld, point);
+        debug!("Inspecting {:?}", self.selected);
+    }
+
+    /// Drops the selection once its entity is gone, e.g. after an undo
+    pub fn validate(&mut self, specsWorld: &specs::World) {
+        if let Some(entity) = self.selected {
+            if !specsWorld.is_alive(entity) || specsWorld.read_storage::<RigidBody>().get(entity).is_none() {
+                self.selected = None;
+            }
+        }
+    }
+
+    pub fn next_field(&mut self) {
+        self.cursor = (self.cursor + 1) % Field::ALL.len();
+    }
+
+    pub fn previous_field(&mut self) {
+        self.cursor = (self.cursor + Field::ALL.len() - 1) % Field::ALL.len();
+    }
+
+    /**
+     * Changes the field under the cursor. Numbers move by the field's step
+     * in the given direction, status and material cycle and sleeping
+     * toggles. Moves, mass, status and material changes can be undone and
+     * rebuild the body, except for moves. The mass is kept as a density
+     * override so it survives rebuilds and the tuning panel. The walls and
+     * ground keep their status and shapes without a mass are never dynamic.
+     */
+    pub fn adjust(&self, specsWorld: &mut specs::World, direction: f32) {
+        let entity = match self.selected {
+            Some(entity) => entity,
+            None => return,
+        };
+
+        let field = Field::ALL[self.cursor];
+        let step = field.step() * direction;
+
+        match field {
+            Field::X | Field::Y | Field::Rotation => {
+                let from = match crate::world::body_pose(specsWorld, entity) {
+                    Some(pose) => pose,
+                    None => return,
+                };
+
+                let mut to = from;
+                match field {
+                    Field::X => to.translation.vector.x += step,
+                    Field::Y => to.translation.vector.y += step,
+                    _ => to.rotation = nalgebra::UnitComplex::new(from.rotation.angle() + step),
+                }
+
+                crate::world::set_pose(specsWorld, entity, to);
+                let uid = history::uid(specsWorld, entity);
+                history::record(specsWorld, Command::Move { uid: uid, from: from, to: to });
+            },
+
+            Field::VelocityX | Field::VelocityY | Field::AngularVelocity => {
+                with_body(specsWorld, entity, |rb| {
+                    let mut velocity = rb.velocity().clone();
+                    match field {
+                        Field::VelocityX => velocity.linear.x += step,
+                        Field::VelocityY => velocity.linear.y += step,
+                        _ => velocity.angular += step,
+                    }
+                    rb.set_velocity(velocity);
+                    rb.activate();
+                });
+            },
+
+            // the mass grows with the density, which keeps the shape's mass
+            // distribution the same
+            Field::Mass => {
+                let mass = {
+                    let physics = specsWorld.read_resource::<PhysicsWorld>();
+                    specsWorld.read_storage::<RigidBody>().get(entity)
+                        .and_then(|r| physics.rigid_body(r.object_handle))
+                        .filter(|rb| rb.status() == BodyStatus::Dynamic)
+                        .map_or(0.0, |rb| rb.local_inertia().linear)
+                };
+
+                if !(mass > 0.0) {
+                    return;
+                }
+
+                if let Some(mut desc) = BodyDesc::describe(specsWorld, entity) {
+                    let density = desc.density
+                        .unwrap_or_else(|| specsWorld.read_resource::<MaterialLibrary>().get(&desc.material).density);
+                    desc.density = Some(density * (mass + step).max(field.step()) / mass);
+                    history::edit(specsWorld, entity, desc);
+                }
+            },
+
+            Field::Status => {
+                if specsWorld.read_storage::<Boundary>().contains(entity) {
+                    return;
+                }
+
+                if let Some(mut desc) = BodyDesc::describe(specsWorld, entity) {
+                    let next = |status: Status| match (status, direction > 0.0) {
+                        (Status::Dynamic, true) | (Status::Kinematic, false) => Status::Static,
+                        (Status::Static, true) | (Status::Dynamic, false) => Status::Kinematic,
+                        (Status::Kinematic, true) | (Status::Static, false) => Status::Dynamic,
+                    };
+
+                    desc.status = match next(desc.status) {
+                        Status::Dynamic if !desc.shape.is_solid() => next(Status::Dynamic),
+                        status => status,
+                    };
+                    history::edit(specsWorld, entity, desc);
+                }
+            },
+
+            Field::Material => {
+                if let Some(mut desc) = BodyDesc::describe(specsWorld, entity) {
+                    desc.material = specsWorld.read_resource::<MaterialLibrary>().next(&desc.material).to_owned();
+                    history::edit(specsWorld, entity, desc);
+                }
+            },
+
+            Field::Sleeping => {
+                with_body(specsWorld, entity, |rb| {
+                    match rb.is_active() {
+                        true => rb.deactivate(),
+                        false => rb.activate(),
+                    }
+                });
+            },
+        }
+    }
+
+    /// The inspector panel for the selection, `None` when nothing is selected
+    pub fn panel(&self, specsWorld: &specs::World) -> Option<crate::ui::Panel> {
+        let entity = self.selected?;
+
+        let physics = specsWorld.read_resource::<PhysicsWorld>();
+        let rigidBodies = specsWorld.read_storage::<RigidBody>();
+        let materials = specsWorld.read_storage::<MaterialRef>();
+        let rb = physics.rigid_body(rigidBodies.get(entity)?.object_handle)?;
+
+        let pose = rb.position();
+        let velocity = rb.velocity();
+        let inertia = rb.local_inertia();
+        let material = materials.get(entity).map(|m| m.name.as_str()).unwrap_or("-");
+
+        let mut panel = crate::ui::Panel::new(&format!("Inspector {:?}", entity))
+            .line(format!("{:<11} {:>8.1}", Field::X.label(), pose.translation.vector.x))
+            .line(format!("{:<11} {:>8.1}", Field::Y.label(), pose.translation.vector.y))
+            .line(format!("{:<11} {:>8.2}", Field::Rotation.label(), pose.rotation.angle()))
+            .line(format!("{:<11} {:>8.1}", Field::VelocityX.label(), velocity.linear.x))
+            .line(format!("{:<11} {:>8.1}", Field::VelocityY.label(), velocity.linear.y))
+            .line(format!("{:<11} {:>8.2}", Field::AngularVelocity.label(), velocity.angular))
+            .line(format!("{:<11} {:>8.1}", Field::Mass.label(), inertia.linear))
+            .line(format!("{:<11} {:?}", Field::Status.label(), Status::from(rb.status())))
+            .line(format!("{:<11} {}", Field::Material.label(), material))
+            .line(format!("{:<11} {}", Field::Sleeping.label(), !rb.is_active()))
+            .cursor(self.cursor)
+            .line(format!("inertia     {:.1}", inertia.angular));
+
+        if let Some(ball) = specsWorld.read_storage::<crate::scene::physicstest::Ball>().get(entity) {
+            panel = panel.line(format!("Ball        active: {}", ball.active));
+        }
+
+        if let Some(motion) = specsWorld.read_storage::<crate::system::Motion>().get(entity) {
+            panel = panel.line(format!(
+                "Motion      v ({:.1}, {:.1}) a ({:.1}, {:.1})",
+                motion.velocity.x, motion.velocity.y, motion.acceleration.x, motion.acceleration.y,
+            ));
+        }
+
+        if let Some(mass) = specsWorld.read_storage::<crate::system::Mass>().get(entity) {
+            panel = panel.line(format!("Mass        total: {:.1}", mass.total));
+        }
+
+        if let Some(gravity) = specsWorld.read_storage::<crate::system::Gravity>().get(entity) {
+            panel = panel.line(format!("Gravity     force: {:?}", gravity.force));
+        }
+
+        if let Some(charge) = specsWorld.read_storage::<crate::pairwise::Charge>().get(entity) {
+            panel = panel.line(format!("Charge      {:.1}", charge.charge));
+        }
+
+        if let Some(magnet) = specsWorld.read_storage::<crate::pairwise::Magnet>().get(entity) {
+            panel = panel.line(format!("Magnet      {:.1} at {:.2}", magnet.strength, magnet.angle));
+        }
+
+        Some(panel
+            .line("")
+            .line("PgUp/PgDn select, Left/Right edit"))
+    }
+}
+
+
+
+fn with_body<F: FnOnce(&mut nphysics2d::object::RigidBody<f32>)>(specsWorld: &specs::World, entity: specs::Entity, change: F) {
+    let mut physics = specsWorld.write_resource::<PhysicsWorld>();
+    let rigidBodies = specsWorld.read_storage::<RigidBody>();
+
+    if let Some(rb) = rigidBodies.get(entity).and_then(|r| physics.rigid_body_mut(r.object_handle)) {
+        change(rb);
+    }
+}
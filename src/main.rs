@@ -17,6 +17,14 @@ extern crate nalgebra;
 extern crate nphysics2d;
 extern crate ncollide2d;
 
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate ron;
+extern crate tiled;
+extern crate image;
+extern crate xml;
+
 use ggez::ContextBuilder;
 use ggez::conf::{WindowSetup, WindowMode};
 
@@ -24,6 +32,32 @@ mod scene;
 mod world;
 mod game;
 mod system;
+mod material;
+mod geometry;
+mod shape;
+mod render;
+mod builder;
+mod scenefile;
+mod tool;
+mod history;
+mod inspector;
+mod player;
+mod explosion;
+mod fluid;
+mod pairwise;
+mod softbody;
+mod rope;
+mod fracture;
+mod character;
+mod modifier;
+mod vehicle;
+mod ragdoll;
+mod terrain;
+mod tmx;
+mod sprite;
+mod svg;
+mod tuning;
+mod ui;
 
 use self::game::Game;
 
@@ -0,0 +1,418 @@
+/**
+ *
+ * Named physics materials.
+ *
+ * Every collider refers to a material in the `MaterialLibrary` by name
+ * through its `MaterialRef` component. The library holds restitution,
+ * friction and density along with the rule used to combine two materials
+ * when their colliders touch.
+ *
+ */
+
+use std::collections::{HashMap, HashSet};
+
+use specs;
+use specs::prelude::*;
+
+use ncollide2d::events::ContactEvent;
+use ncollide2d::world::CollisionObjectHandle;
+
+use log::*;
+
+use crate::world::PhysicsWorld;
+
+/// Used by the walls, the ground and anything that doesn't ask for a material.
+/// This is the material edited by the tuning panel.
+pub const DEFAULT_MATERIAL: &str = "default";
+
+
+
+/// How the values of two touching materials are combined. When the two
+/// materials disagree the rule further down the list wins.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum CombineRule {
+    Average,
+    Min,
+    Multiply,
+    Max,
+}
+
+impl CombineRule {
+    pub fn combine(&self, a: f32, b: f32) -> f32 {
+        match self {
+            CombineRule::Average => (a + b) * 0.5,
+            CombineRule::Min => a.min(b),
+            CombineRule::Multiply => a * b,
+            CombineRule::Max => a.max(b),
+        }
+    }
+}
+
+
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PhysicsMaterial {
+    pub name: String,
+    pub restitution: f32,
+    pub friction: f32,
+    pub density: f32,
+    pub combine: CombineRule,
+}
+
+impl PhysicsMaterial {
+    pub fn new(name: &str, restitution: f32, friction: f32, density: f32, combine: CombineRule) -> PhysicsMaterial {
+        PhysicsMaterial {
+            name: name.to_owned(),
+            restitution: restitution,
+            friction: friction,
+            density: density,
+            combine: combine,
+        }
+    }
+
+    pub fn material(&self) -> nphysics2d::object::Material<f32> {
+        nphysics2d::object::Material::new(self.restitution, self.friction)
+    }
+
+    /// The restitution and friction of a contact between `self` and `other`
+    pub fn combined(&self, other: &PhysicsMaterial) -> (f32, f32) {
+        let rule = self.combine.max(other.combine);
+        (rule.combine(self.restitution, other.restitution), rule.combine(self.friction, other.friction))
+    }
+}
+
+
+
+/// Registry of every material that can be referred to by name.
+pub struct MaterialLibrary {
+    materials: Vec<PhysicsMaterial>,
+}
+
+impl Default for MaterialLibrary {
+    fn default() -> MaterialLibrary {
+        let tuning = crate::tuning::Tuning::default();
+
+        MaterialLibrary {
+            materials: vec![
+                PhysicsMaterial::new(DEFAULT_MATERIAL, tuning.restitution, tuning.friction, tuning.density, CombineRule::Average),
+                PhysicsMaterial::new("rubber",  0.9,  2.0, 1.2, CombineRule::Max),
+                PhysicsMaterial::new("balloon", 0.5,  0.5, 0.1, CombineRule::Average),
+                PhysicsMaterial::new("ice",     0.1,  0.0, 0.9, CombineRule::Min),
+                PhysicsMaterial::new("rock",    0.1,  2.0, 2.6, CombineRule::Average),
+                PhysicsMaterial::new("velcro",  0.0, 50.0, 0.5, CombineRule::Max),
+                PhysicsMaterial::new("steel",   0.4,  0.6, 7.8, CombineRule::Average),
+                PhysicsMaterial::new("wood",    0.3,  1.0, 0.7, CombineRule::Average),
+            ],
+        }
+    }
+}
+
+impl MaterialLibrary {
+    /**
+     * Looks up a material by name. Unknown names fall back to the default
+     * material so a typo in a scene never fails to spawn.
+     */
+    pub fn get(&self, name: &str) -> &PhysicsMaterial {
+        match self.materials.iter().find(|m| m.name == name) {
+            Some(material) => material,
+            None => {
+                warn!("Unknown material {}, using {}", name, DEFAULT_MATERIAL);
+                &self.materials[0]
+            }
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.materials.iter().any(|m| m.name == name)
+    }
+
+    /// Adds a material or replaces the one with the same name
+    pub fn insert(&mut self, material: PhysicsMaterial) {
+        match self.materials.iter_mut().find(|m| m.name == material.name) {
+            Some(existing) => *existing = material,
+            None => self.materials.push(material),
+        }
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.materials.iter().map(|m| m.name.as_str()).collect()
+    }
+
+    /// The material after `name` in the library, wrapping around
+    pub fn next(&self, name: &str) -> &str {
+        let index = self.materials.iter().position(|m| m.name == name).unwrap_or(0);
+        &self.materials[(index + 1) % self.materials.len()].name
+    }
+}
+
+
+
+/// Picks the material an entity's collider uses
+#[derive(Clone, Debug, Component)]
+#[storage(VecStorage)]
+pub struct MaterialRef {
+    pub name: String,
+}
+
+impl MaterialRef {
+    pub fn new(name: &str) -> MaterialRef {
+        MaterialRef { name: name.to_owned() }
+    }
+}
+
+
+
+/// Density a body was built with in place of its material's
+#[derive(Clone, Copy, Debug, Component)]
+#[storage(HashMapStorage)]
+pub struct Density {
+    pub value: f32,
+}
+
+
+
+/// How much a collider's own material counts against the pairs it touches
+const OWN_WEIGHT: f32 = 0.1;
+
+/// Passes spent settling the materials of colliders touching each other
+const SWEEPS: usize = 4;
+
+
+
+/**
+ * Applies the combine rules to touching colliders.
+ *
+ * The solver only knows one material per collider and uses the average of
+ * the two colliders in contact. Every pair touching this step gets its
+ * combined material as a target and each collider is given the material
+ * that brings the averages of all its pairs closest to their targets. A
+ * collider touching a single other one comes out exact, and one that stops
+ * touching everything goes back to its own material.
+ */
+#[derive(Default)]
+pub struct ContactMaterialSystem {
+    /// Every pair of colliders in contact, in the order the event named them
+    pairs: HashSet<(CollisionObjectHandle, CollisionObjectHandle)>,
+    /// Colliders currently given a material other than their own
+    changed: HashSet<CollisionObjectHandle>,
+}
+
+impl<'a> specs::System<'a> for ContactMaterialSystem {
+    type SystemData = (
+        specs::ReadStorage<'a, crate::system::Collider>,
+        specs::ReadStorage<'a, MaterialRef>,
+        specs::ReadExpect<'a, MaterialLibrary>,
+        specs::WriteExpect<'a, PhysicsWorld>,
+    );
+
+    fn run(&mut self, (colliders, materials, library, mut physics_world): Self::SystemData) {
+        let names: HashMap<CollisionObjectHandle, &str> = (&colliders, &materials)
+            .join()
+            .map(|(c, m)| (c.object_handle, m.name.as_str()))
+            .collect();
+
+        let material = |handle: &CollisionObjectHandle| {
+            library.get(names.get(handle).cloned().unwrap_or(DEFAULT_MATERIAL))
+        };
+
+        for event in physics_world.contact_events() {
+            match *event {
+                ContactEvent::Started(h1, h2) => {
+                    self.pairs.insert((h1, h2));
+                },
+
+                ContactEvent::Stopped(h1, h2) => {
+                    self.pairs.remove(&(h1, h2));
+                    self.pairs.remove(&(h2, h1));
+                }
+            }
+        }
+
+        {
+            let collision_world = physics_world.collision_world();
+            self.pairs.retain(|(h1, h2)| {
+                collision_world.collision_object(*h1).is_some() && collision_world.collision_object(*h2).is_some()
+            });
+        }
+
+        for (handle, (restitution, friction)) in self.updates(material) {
+            if let Some(object) = physics_world.collision_world_mut().collision_object_mut(handle) {
+                *object.data_mut().material_mut() = nphysics2d::object::Material::new(restitution, friction);
+            }
+        }
+    }
+}
+
+
+
+impl ContactMaterialSystem {
+    /**
+     * The restitution and friction colliders take this step: the settled
+     * values of every collider in a pair and their own material for those
+     * that stopped touching everything.
+     */
+    fn updates<'m, F>(&mut self, material: F) -> Vec<(CollisionObjectHandle, (f32, f32))>
+        where F: Fn(&CollisionObjectHandle) -> &'m PhysicsMaterial
+    {
+        let mut partners: HashMap<CollisionObjectHandle, Vec<(CollisionObjectHandle, (f32, f32))>> = HashMap::new();
+        for &(h1, h2) in self.pairs.iter() {
+            let target = material(&h1).combined(material(&h2));
+            partners.entry(h1).or_insert_with(Vec::new).push((h2, target));
+            partners.entry(h2).or_insert_with(Vec::new).push((h1, target));
+        }
+
+        // Each pass moves every collider to the least squares fit of its
+        // pairs given the current values of the colliders it touches
+        let mut values: HashMap<CollisionObjectHandle, (f32, f32)> = partners
+            .keys()
+            .map(|handle| {
+                let own = material(handle);
+                (*handle, (own.restitution, own.friction))
+            })
+            .collect();
+        for _ in 0..SWEEPS {
+            for (handle, touching) in partners.iter() {
+                let own = material(handle);
+                let mut sum = (own.restitution * OWN_WEIGHT, own.friction * OWN_WEIGHT);
+                for (other, target) in touching {
+                    let value = values[other];
+                    sum.0 += 2.0 * target.0 - value.0;
+                    sum.1 += 2.0 * target.1 - value.1;
+                }
+
+                let weight = touching.len() as f32 + OWN_WEIGHT;
+                values.insert(*handle, ((sum.0 / weight).max(0.0), (sum.1 / weight).max(0.0)));
+            }
+        }
+
+        // Nothing else depends on a collider touching a single other one, so
+        // it can make up the rest of its pair exactly
+        for (handle, touching) in partners.iter() {
+            if let [(other, target)] = touching.as_slice() {
+                let value = values[other];
+                values.insert(*handle, ((2.0 * target.0 - value.0).max(0.0), (2.0 * target.1 - value.1).max(0.0)));
+            }
+        }
+
+        let mut updates: Vec<(CollisionObjectHandle, (f32, f32))> = self.changed
+            .iter()
+            .filter(|handle| !values.contains_key(handle))
+            .map(|handle| {
+                let own = material(handle);
+                (*handle, (own.restitution, own.friction))
+            })
+            .collect();
+        updates.extend(values.iter().map(|(handle, value)| (*handle, *value)));
+
+        self.changed = values.keys().cloned().collect();
+        updates
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    /// The value a collider was given by the updates
+    fn value(updates: &[(CollisionObjectHandle, (f32, f32))], handle: CollisionObjectHandle) -> (f32, f32) {
+        updates.iter().find(|(h, _)| *h == handle).expect("Collider wasn't updated").1
+    }
+
+    #[test]
+    fn later_rules_take_precedence() {
+        assert!(CombineRule::Average < CombineRule::Min);
+        assert!(CombineRule::Min < CombineRule::Multiply);
+        assert!(CombineRule::Multiply < CombineRule::Max);
+
+        let rubber = PhysicsMaterial::new("rubber", 0.9, 2.0, 1.2, CombineRule::Max);
+        let ice = PhysicsMaterial::new("ice", 0.1, 0.0, 0.9, CombineRule::Min);
+        assert_eq!(rubber.combined(&ice), (0.9, 2.0));
+        assert_eq!(ice.combined(&rubber), (0.9, 2.0));
+    }
+
+    #[test]
+    fn combined_applies_the_rule_to_both_values() {
+        let a = PhysicsMaterial::new("a", 0.2, 1.0, 1.0, CombineRule::Average);
+        let b = PhysicsMaterial::new("b", 0.6, 3.0, 1.0, CombineRule::Average);
+        let (restitution, friction) = a.combined(&b);
+        assert!(close(restitution, 0.4));
+        assert!(close(friction, 2.0));
+
+        let c = PhysicsMaterial::new("c", 0.5, 0.5, 1.0, CombineRule::Multiply);
+        let (restitution, friction) = b.combined(&c);
+        assert!(close(restitution, 0.3));
+        assert!(close(friction, 1.5));
+
+        let d = PhysicsMaterial::new("d", 0.5, 0.5, 1.0, CombineRule::Min);
+        assert_eq!(a.combined(&d), (0.2, 0.5));
+    }
+
+    #[test]
+    fn a_single_pair_comes_out_exact() {
+        let rubber = PhysicsMaterial::new("rubber", 0.9, 2.0, 1.2, CombineRule::Max);
+        let wood = PhysicsMaterial::new("wood", 0.3, 1.0, 0.7, CombineRule::Average);
+        let (a, b) = (CollisionObjectHandle(0), CollisionObjectHandle(1));
+        let material = |handle: &CollisionObjectHandle| if *handle == a { &rubber } else { &wood };
+
+        let mut system = ContactMaterialSystem::default();
+        system.pairs.insert((a, b));
+        let updates = system.updates(material);
+
+        // the solver uses the average of the two colliders
+        let (target, va, vb) = (rubber.combined(&wood), value(&updates, a), value(&updates, b));
+        assert!(close((va.0 + vb.0) / 2.0, target.0));
+        assert!(close((va.1 + vb.1) / 2.0, target.1));
+    }
+
+    #[test]
+    fn the_ends_of_a_chain_come_out_exact() {
+        let rubber = PhysicsMaterial::new("rubber", 0.9, 2.0, 1.2, CombineRule::Max);
+        let wood = PhysicsMaterial::new("wood", 0.3, 1.0, 0.7, CombineRule::Average);
+        let steel = PhysicsMaterial::new("steel", 0.6, 1.2, 7.8, CombineRule::Average);
+        let (a, b, c) = (CollisionObjectHandle(0), CollisionObjectHandle(1), CollisionObjectHandle(2));
+        let material = |handle: &CollisionObjectHandle| match handle.0 {
+            0 => &rubber,
+            1 => &wood,
+            _ => &steel,
+        };
+
+        let mut system = ContactMaterialSystem::default();
+        system.pairs.insert((a, b));
+        system.pairs.insert((b, c));
+        let updates = system.updates(material);
+
+        let (va, vb, vc) = (value(&updates, a), value(&updates, b), value(&updates, c));
+        let (ab, bc) = (rubber.combined(&wood), wood.combined(&steel));
+        assert!(close((va.0 + vb.0) / 2.0, ab.0));
+        assert!(close((va.1 + vb.1) / 2.0, ab.1));
+        assert!(close((vb.0 + vc.0) / 2.0, bc.0));
+        assert!(close((vb.1 + vc.1) / 2.0, bc.1));
+    }
+
+    #[test]
+    fn released_colliders_go_back_to_their_own_material() {
+        let rubber = PhysicsMaterial::new("rubber", 0.9, 2.0, 1.2, CombineRule::Max);
+        let ice = PhysicsMaterial::new("ice", 0.1, 0.0, 0.9, CombineRule::Min);
+        let (a, b) = (CollisionObjectHandle(0), CollisionObjectHandle(1));
+        let material = |handle: &CollisionObjectHandle| if *handle == a { &rubber } else { &ice };
+
+        let mut system = ContactMaterialSystem::default();
+        system.pairs.insert((a, b));
+        system.updates(material);
+
+        system.pairs.clear();
+        let updates = system.updates(material);
+        assert_eq!(updates.len(), 2);
+        assert_eq!(value(&updates, a), (rubber.restitution, rubber.friction));
+        assert_eq!(value(&updates, b), (ice.restitution, ice.friction));
+
+        // and are left alone after that
+        assert!(system.updates(material).is_empty());
+    }
+}
@@ -0,0 +1,323 @@
+/**
+ *
+ * Collider modifiers.
+ *
+ * Modifiers change how a collider treats what touches it, on top of its
+ * material. A `OneWay` collider only holds bodies coming from the side its
+ * normal points to and lets everything else pass through it. A `Conveyor`
+ * carries whatever rests on it along its surface. Both are set next to the
+ * material when building a body or in a scene file:
+ *
+ * ```ignore
+ * sceneWorld.body(ShapeDesc::Cuboid { half_extents: [40.0, 3.0] })
+ *     .status(Status::Static)
+ *     .material("rubber")
+ *     .one_way(OneWay::default())
+ *     .conveyor(Conveyor { speed: 50.0 })
+ *     .build();
+ * ```
+ *
+ * Passing through is decided per pair of platform and body by a broad
+ * phase filter. When a body first reaches a platform it is held if its
+ * center is on the side the normal points to and passes otherwise, and the
+ * decision stands until the two are apart again.
+ *
+ * Colliders that are `PartOf` the same whole, like the segments of a rope,
+ * pass through each other the same way while still colliding with the
+ * parts of every other whole. The links of a multibody, like the wheels
+ * and chassis of a car, are parts of their root.
+ *
+ */
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use ncollide2d::world::CollisionObjectHandle;
+use nphysics2d::object::BodyStatus;
+
+use specs;
+use specs::prelude::*;
+
+use crate::system::{Collider, Link, RigidBody};
+use crate::world::PhysicsWorld;
+
+/// How far apart the bounding boxes of a body and a platform must be
+/// before the body is checked again. Matches the margin of the broad phase.
+const ONE_WAY_MARGIN: f32 = 5.0;
+
+/// Fraction of the difference to the belt speed made up per second
+const CONVEYOR_GRIP: f32 = 10.0;
+
+fn up() -> (f32, f32) {
+    (0.0, -1.0)
+}
+
+/// Only holds bodies coming from the side the normal points to
+#[derive(Clone, Copy, Debug, Component, Serialize, Deserialize)]
+#[storage(HashMapStorage)]
+pub struct OneWay {
+    /// Direction bodies are held from, in the collider's frame. Up is -y.
+    #[serde(default = "up")]
+    pub normal: (f32, f32),
+}
+
+impl Default for OneWay {
+    fn default() -> OneWay {
+        OneWay { normal: up() }
+    }
+}
+
+impl OneWay {
+    /// The normal in world space for the collider's pose
+    pub fn world_normal(&self, pose: &nalgebra::Isometry2<f32>) -> nalgebra::Vector2<f32> {
+        pose.rotation * nalgebra::Vector2::new(self.normal.0, self.normal.1)
+    }
+}
+
+
+
+/// Carries resting bodies along its surface
+#[derive(Clone, Copy, Debug, Component, Serialize, Deserialize)]
+#[storage(HashMapStorage)]
+pub struct Conveyor {
+    /// Speed of the top surface towards +x, the bottom runs the other way
+    pub speed: f32,
+}
+
+
+
+/// Platforms and the pairs already decided, shared with the broad phase
+#[derive(Default)]
+struct OneWayState {
+    /// World normal of every one-way collider
+    platforms: HashMap<CollisionObjectHandle, nalgebra::Vector2<f32>>,
+    /// Whether each (platform, body) pair holds, until they are apart
+    decided: HashMap<(CollisionObjectHandle, CollisionObjectHandle), bool>,
+}
+
+/// Drops the broad phase pairs of a body passing through a platform
+struct OneWayFilter {
+    state: Arc<Mutex<OneWayState>>,
+}
+
+impl ncollide2d::broad_phase::BroadPhasePairFilter<f32, nphysics2d::object::ColliderData<f32>> for OneWayFilter {
+    fn is_pair_valid(&self, b1: &nphysics2d::object::Collider<f32>, b2: &nphysics2d::object::Collider<f32>) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        let (platform, body, normal) = match (state.platforms.get(&b1.handle()), state.platforms.get(&b2.handle())) {
+            (Some(normal), None) => (b1, b2, *normal),
+            (None, Some(normal)) => (b2, b1, *normal),
+            _ => return true,
+        };
+
+        let offset = body.position().translation.vector - platform.position().translation.vector;
+        *state.decided
+            .entry((platform.handle(), body.handle()))
+            .or_insert(offset.dot(&normal) > 0.0)
+    }
+}
+
+/**
+ * Keeps the one-way filter up to date. Every platform's normal is handed
+ * to the filter, which the system registers with the collision world the
+ * first time it runs, and the decisions of pairs that are apart again are
+ * forgotten. A platform that just appeared has its pairs checked again.
+ */
+#[derive(Default)]
+pub struct OneWaySystem {
+    state: Arc<Mutex<OneWayState>>,
+    registered: bool,
+}
+
+impl<'a> specs::System<'a> for OneWaySystem {
+    type SystemData = (
+        specs::ReadStorage<'a, OneWay>,
+        specs::ReadStorage<'a, Collider>,
+        specs::WriteExpect<'a, PhysicsWorld>,
+    );
+
+    fn run(&mut self, (oneWays, colliders, mut physics_world): Self::SystemData) {
+        use ncollide2d::bounding_volume::BoundingVolume;
+
+        if !self.registered {
+            let filter = OneWayFilter { state: self.state.clone() };
+            physics_world.collision_world_mut().register_broad_phase_pair_filter("one_way", filter);
+            self.registered = true;
+        }
+
+        let mut added = Vec::new();
+        {
+            let collision_world = physics_world.collision_world();
+            let mut state = self.state.lock().unwrap();
+
+            let mut platforms = HashMap::new();
+            for (oneWay, c) in (&oneWays, &colliders).join() {
+                if let Some(platform) = collision_world.collision_object(c.object_handle) {
+                    platforms.insert(c.object_handle, oneWay.world_normal(platform.position()));
+                    if !state.platforms.contains_key(&c.object_handle) {
+                        added.push((c.object_handle, platform.collision_groups().clone()));
+                    }
+                }
+            }
+            state.platforms = platforms;
+
+            state.decided.retain(|(platform, body), _| {
+                match (collision_world.collision_object(*platform), collision_world.collision_object(*body)) {
+                    (Some(platform), Some(body)) => {
+                        let bounds = body.shape().aabb(body.position()).loosened(ONE_WAY_MARGIN);
+                        bounds.intersects(&platform.shape().aabb(platform.position()))
+                    },
+                    _ => false,
+                }
+            });
+        }
+
+        // setting the groups again makes the broad phase filter their pairs
+        for (handle, groups) in added {
+            physics_world.collision_world_mut().set_collision_groups(handle, groups);
+        }
+    }
+}
+
+
+
+/// Doesn't collide with the other parts of the same whole
+#[derive(Clone, Copy, Debug, Component)]
+#[storage(HashMapStorage)]
+pub struct PartOf {
+    pub whole: specs::Entity,
+}
+
+/// Drops the broad phase pairs of two parts of the same whole
+struct SelfCollisionFilter {
+    wholes: Arc<Mutex<HashMap<CollisionObjectHandle, specs::Entity>>>,
+}
+
+impl ncollide2d::broad_phase::BroadPhasePairFilter<f32, nphysics2d::object::ColliderData<f32>> for SelfCollisionFilter {
+    fn is_pair_valid(&self, b1: &nphysics2d::object::Collider<f32>, b2: &nphysics2d::object::Collider<f32>) -> bool {
+        let wholes = self.wholes.lock().unwrap();
+        match (wholes.get(&b1.handle()), wholes.get(&b2.handle())) {
+            (Some(w1), Some(w2)) => w1 != w2,
+            _ => true,
+        }
+    }
+}
+
+/**
+ * Keeps the self collision filter up to date with the whole of every part
+ * and the root of every multibody link, registering the filter the first time it runs. Parts that just appeared
+ * have their pairs checked again.
+ */
+#[derive(Default)]
+pub struct SelfCollisionSystem {
+    wholes: Arc<Mutex<HashMap<CollisionObjectHandle, specs::Entity>>>,
+    registered: bool,
+}
+
+impl<'a> specs::System<'a> for SelfCollisionSystem {
+    type SystemData = (
+        specs::ReadStorage<'a, PartOf>,
+        specs::ReadStorage<'a, Link>,
+        specs::ReadStorage<'a, Collider>,
+        specs::WriteExpect<'a, PhysicsWorld>,
+    );
+
+    fn run(&mut self, (parts, links, colliders, mut physics_world): Self::SystemData) {
+        if !self.registered {
+            let filter = SelfCollisionFilter { wholes: self.wholes.clone() };
+            physics_world.collision_world_mut().register_broad_phase_pair_filter("self_collision", filter);
+            self.registered = true;
+        }
+
+        let wholes: HashMap<CollisionObjectHandle, specs::Entity> = (&parts, &colliders).join()
+            .map(|(part, c)| (c.object_handle, part.whole))
+            .chain((&links, &colliders).join().map(|(link, c)| (c.object_handle, link.root)))
+            .collect();
+
+        let added: Vec<(CollisionObjectHandle, ncollide2d::world::CollisionGroups)> = {
+            let collision_world = physics_world.collision_world();
+            let mut known = self.wholes.lock().unwrap();
+
+            let added = wholes.keys()
+                .filter(|handle| !known.contains_key(handle))
+                .filter_map(|handle| collision_world.collision_object(*handle))
+                .map(|object| (object.handle(), object.collision_groups().clone()))
+                .collect();
+            *known = wholes;
+            added
+        };
+
+        // setting the groups again makes the broad phase filter their pairs
+        for (handle, groups) in added {
+            physics_world.collision_world_mut().set_collision_groups(handle, groups);
+        }
+    }
+}
+
+
+
+/**
+ * Pushes the bodies touching a conveyor towards its belt speed along the
+ * contact surface, like friction against a moving belt.
+ */
+pub struct ConveyorSystem;
+
+impl<'a> specs::System<'a> for ConveyorSystem {
+    type SystemData = (
+        specs::ReadStorage<'a, Conveyor>,
+        specs::ReadStorage<'a, Collider>,
+        specs::ReadStorage<'a, RigidBody>,
+        specs::WriteExpect<'a, PhysicsWorld>,
+    );
+
+    fn run(&mut self, (conveyors, colliders, rigidBodies, mut physics_world): Self::SystemData) {
+        let belts: HashMap<CollisionObjectHandle, f32> = (&conveyors, &colliders).join()
+            .map(|(belt, c)| (c.object_handle, belt.speed))
+            .collect();
+
+        if belts.is_empty() {
+            return;
+        }
+
+        let bodies: HashMap<CollisionObjectHandle, nphysics2d::object::BodyHandle> = (&colliders, &rigidBodies).join()
+            .map(|(c, r)| (c.object_handle, r.object_handle))
+            .collect();
+
+        // the belt speed and the normal out of the belt for every body resting on one
+        let mut carried = Vec::new();
+        let mut manifolds = Vec::new();
+        for (a, b, algorithm) in physics_world.collision_world().contact_pairs() {
+            let (speed, other, direction) = match (belts.get(&a.handle()), belts.get(&b.handle())) {
+                (Some(speed), None) => (*speed, b.handle(), 1.0),
+                (None, Some(speed)) => (*speed, a.handle(), -1.0),
+                _ => continue,
+            };
+
+            manifolds.clear();
+            algorithm.contacts(&mut manifolds);
+
+            let normal = manifolds.iter()
+                .flat_map(|manifold| manifold.contacts())
+                .next()
+                .map(|tracked| tracked.contact.normal.unwrap() * direction);
+
+            if let (Some(normal), Some(handle)) = (normal, bodies.get(&other)) {
+                carried.push((*handle, speed, normal));
+            }
+        }
+
+        for (handle, speed, normal) in carried {
+            let rb = match physics_world.rigid_body_mut(handle) {
+                Some(rb) if rb.status() == BodyStatus::Dynamic => rb,
+                _ => continue,
+            };
+
+            // along the surface, +x on top of a level belt
+            let tangent = nalgebra::Vector2::new(- normal.y, normal.x);
+            let slip = speed - rb.velocity().linear.dot(&tangent);
+            let mass = rb.local_inertia().linear;
+
+            rb.apply_force(&nphysics2d::math::Force::linear(tangent * slip * CONVEYOR_GRIP * mass));
+        }
+    }
+}
@@ -0,0 +1,207 @@
+/**
+ *
+ * Forces between pairs of bodies.
+ *
+ * Electric charges, magnets and n-body gravity all act between every pair
+ * of bodies carrying the same kind of component. Each of them implements
+ * `PairForce` and gets a `PairwiseSystem` that visits every pair once and
+ * applies equal and opposite forces. Torques come from the law itself, so
+ * a magnet turns to line up with its neighbours.
+ *
+ * Static and kinematic bodies take part as sources but are never moved.
+ *
+ */
+
+use std::marker::PhantomData;
+
+use nphysics2d::object::BodyStatus;
+
+use specs;
+use specs::prelude::*;
+
+use crate::system::{Gravity, RigidBody};
+use crate::world::PhysicsWorld;
+
+/// Scales the charge product to a force
+const COULOMB_CONSTANT: f32 = 1.0e6;
+
+/// Scales the dipole moments to a force
+const MAGNETIC_CONSTANT: f32 = 1.0e9;
+
+/// Added to every distance so touching bodies don't get huge forces
+const SOFTENING: f32 = 10.0;
+
+/// Where a body is for a pairwise force
+#[derive(Debug, Clone, Copy)]
+pub struct PairBody {
+    pub position: nalgebra::Point2<f32>,
+    pub rotation: f32,
+    pub mass: f32,
+}
+
+/// The result of one pair. The force on the second body is the opposite.
+#[derive(Debug, Clone, Copy)]
+pub struct Interaction {
+    pub force: nalgebra::Vector2<f32>,
+    pub torque_a: f32,
+    pub torque_b: f32,
+}
+
+pub trait PairForce: specs::Component + Send + Sync {
+    /// The force `b` exerts on `a` and the torques on both
+    fn interact(&self, a: &PairBody, other: &Self, b: &PairBody) -> Option<Interaction>;
+}
+
+/// Offset from `b` to `a` and its softened length
+fn separation(a: &PairBody, b: &PairBody) -> (nalgebra::Vector2<f32>, f32) {
+    let offset = a.position - b.position;
+    let distance = (offset.norm_squared() + SOFTENING * SOFTENING).sqrt();
+    (offset, distance)
+}
+
+
+
+/// Signed electric charge. Like charges repel, opposite charges attract.
+#[derive(Clone, Copy, Debug, Component, Serialize, Deserialize)]
+#[storage(HashMapStorage)]
+pub struct Charge {
+    pub charge: f32,
+}
+
+impl PairForce for Charge {
+    fn interact(&self, a: &PairBody, other: &Charge, b: &PairBody) -> Option<Interaction> {
+        let (offset, distance) = separation(a, b);
+        let force = offset / distance * COULOMB_CONSTANT * self.charge * other.charge / (distance * distance);
+
+        Some(Interaction { force: force, torque_a: 0.0, torque_b: 0.0 })
+    }
+}
+
+
+
+/// A magnetic dipole. Its north pole points along `angle` in the body's frame.
+#[derive(Clone, Copy, Debug, Component, Serialize, Deserialize)]
+#[storage(HashMapStorage)]
+pub struct Magnet {
+    pub strength: f32,
+
+    #[serde(default)]
+    pub angle: f32,
+}
+
+impl Magnet {
+    /// The dipole moment in world space
+    pub fn moment(&self, body: &PairBody) -> nalgebra::Vector2<f32> {
+        let angle = body.rotation + self.angle;
+        nalgebra::Vector2::new(angle.cos(), angle.sin()) * self.strength
+    }
+
+    /// The field of the dipole at `offset` from it
+    fn field(moment: &nalgebra::Vector2<f32>, offset: &nalgebra::Vector2<f32>, distance: f32) -> nalgebra::Vector2<f32> {
+        let direction = offset / distance;
+        (direction * 3.0 * moment.dot(&direction) - moment) * MAGNETIC_CONSTANT / distance.powi(3)
+    }
+}
+
+impl PairForce for Magnet {
+    fn interact(&self, a: &PairBody, other: &Magnet, b: &PairBody) -> Option<Interaction> {
+        let (offset, distance) = separation(a, b);
+        let direction = offset / distance;
+        let (ma, mb) = (self.moment(a), other.moment(b));
+        let (ra, rb) = (ma.dot(&direction), mb.dot(&direction));
+
+        // force between two point dipoles, `direction` pointing from b to a
+        let force = (mb * ra + ma * rb + direction * ma.dot(&mb) - direction * 5.0 * ra * rb)
+            * 3.0 * MAGNETIC_CONSTANT / distance.powi(4);
+
+        Some(Interaction {
+            force: force,
+            torque_a: crate::system::cross(&ma, &Magnet::field(&mb, &offset, distance)),
+            torque_b: crate::system::cross(&mb, &Magnet::field(&ma, &(- offset), distance)),
+        })
+    }
+}
+
+
+
+/**
+ * N-body gravity. Only bodies with a gravity strength attract each other,
+ * a pair uses the geometric mean of their two strengths.
+ */
+impl PairForce for Gravity {
+    fn interact(&self, a: &PairBody, other: &Gravity, b: &PairBody) -> Option<Interaction> {
+        let strength = (self.force? * other.force?).sqrt();
+        let (offset, distance) = separation(a, b);
+        let force = - offset / distance * strength * a.mass * b.mass / (distance * distance);
+
+        Some(Interaction { force: force, torque_a: 0.0, torque_b: 0.0 })
+    }
+}
+
+
+
+/**
+ * Applies a pairwise force between every two bodies carrying `C`. The
+ * number of pairs grows with the square of the bodies, which is fine for
+ * the few dozen bodies of a test scene.
+ */
+pub struct PairwiseSystem<C> {
+    marker: PhantomData<fn() -> C>,
+}
+
+impl<C> Default for PairwiseSystem<C> {
+    fn default() -> PairwiseSystem<C> {
+        PairwiseSystem { marker: PhantomData }
+    }
+}
+
+impl<'a, C: PairForce> specs::System<'a> for PairwiseSystem<C> {
+    type SystemData = (
+        specs::ReadStorage<'a, C>,
+        specs::ReadStorage<'a, RigidBody>,
+        specs::WriteExpect<'a, PhysicsWorld>,
+    );
+
+    fn run(&mut self, (sources, rigidBodies, mut physics_world): Self::SystemData) {
+        let bodies: Vec<(nphysics2d::object::BodyHandle, &C, PairBody, bool)> = (&sources, &rigidBodies).join()
+            .filter_map(|(source, r)| {
+                let rb = physics_world.rigid_body(r.object_handle)?;
+                let body = PairBody {
+                    position: rb.center_of_mass(),
+                    rotation: rb.position().rotation.angle(),
+                    mass: rb.local_inertia().linear,
+                };
+                Some((r.object_handle, source, body, rb.status() == BodyStatus::Dynamic))
+            })
+            .collect();
+
+        if bodies.len() < 2 {
+            return;
+        }
+
+        let mut totals = vec![(nalgebra::Vector2::zeros(), 0.0); bodies.len()];
+        for i in 0..bodies.len() {
+            for j in (i + 1)..bodies.len() {
+                let (_, a, bodyA, _) = &bodies[i];
+                let (_, b, bodyB, _) = &bodies[j];
+
+                if let Some(interaction) = a.interact(bodyA, b, bodyB) {
+                    totals[i].0 += interaction.force;
+                    totals[i].1 += interaction.torque_a;
+                    totals[j].0 -= interaction.force;
+                    totals[j].1 += interaction.torque_b;
+                }
+            }
+        }
+
+        for ((handle, _, _, dynamic), (force, torque)) in bodies.iter().zip(totals) {
+            if !dynamic {
+                continue;
+            }
+
+            if let Some(rb) = physics_world.rigid_body_mut(*handle) {
+                rb.apply_force(&nphysics2d::math::Force::new(force, torque));
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+/**
+ *
+ * Player controlled ball.
+ *
+ * The `Ball` marked active is driven by the keyboard. Left and right roll
+ * it and push it sideways, forward and backward thrust it up and down and
+ * jump kicks it upwards, but only while it rests on something.
+ *
+ */
+
+use nphysics2d::object::BodyStatus;
+use ncollide2d::world::CollisionObjectHandle;
+
+use specs;
+use specs::prelude::*;
+
+use crate::scene::physicstest::{ActiveBall, Ball};
+use crate::system::{Collider, RigidBody};
+use crate::world::PhysicsWorld;
+
+/// Sideways acceleration while grounded
+const MOVE_ACCELERATION: f32 = 300.0;
+
+/// Fraction of the sideways acceleration left while in the air
+const AIR_CONTROL: f32 = 0.3;
+
+/// Angular acceleration of the roll
+const ROLL_ACCELERATION: f32 = 20.0;
+
+/// Up and down acceleration of the forward and backward axes
+const THRUST_ACCELERATION: f32 = 150.0;
+
+/// Upwards speed given by a jump
+const JUMP_SPEED: f32 = 150.0;
+
+/// How flat a contact has to be to stand on. 1.0 is level ground.
+const GROUND_NORMAL: f32 = 0.5;
+
+
+
+/**
+ * The player's input for the next step. Axes go from -1.0 to 1.0 with
+ * right and up positive. Stored as a specs resource and filled in by the
+ * scene before dispatching.
+ */
+#[derive(Debug, Default)]
+pub struct PlayerInput {
+    pub horizontal: f32,
+    pub vertical: f32,
+
+    /// Cleared after the next step, grounded or not
+    pub jump: bool,
+}
+
+
+
+/**
+ * Whether the collider touches something below it. Contact normals point
+ * from the first object of the pair to the second and y points down.
+ */
+pub fn grounded(physics: &PhysicsWorld, handle: CollisionObjectHandle) -> bool {
+    let mut manifolds = Vec::new();
+
+    for (a, b, algorithm) in physics.collision_world().contact_pairs() {
+        let direction = if a.handle() == handle {
+            1.0
+        } else if b.handle() == handle {
+            -1.0
+        } else {
+            continue;
+        };
+
+        manifolds.clear();
+        algorithm.contacts(&mut manifolds);
+
+        let standing = manifolds.iter()
+            .flat_map(|manifold| manifold.contacts())
+            .any(|tracked| tracked.contact.normal.y * direction > GROUND_NORMAL);
+
+        if standing {
+            return true;
+        }
+    }
+
+    false
+}
+
+
+
+/// Applies the player input to the active ball
+pub struct PlayerSystem;
+
+impl<'a> specs::System<'a> for PlayerSystem {
+    type SystemData = (
+        specs::ReadStorage<'a, Ball>,
+        specs::ReadStorage<'a, Collider>,
+        specs::ReadStorage<'a, RigidBody>,
+        specs::ReadExpect<'a, PlayerInput>,
+        specs::WriteExpect<'a, ActiveBall>,
+        specs::WriteExpect<'a, PhysicsWorld>,
+    );
+
+    fn run(&mut self, (balls, colliders, rigidBodies, input, mut active, mut physics_world): Self::SystemData) {
+        let jump = input.jump;
+
+        let player = (&balls, &colliders, &rigidBodies).join()
+            .find(|(b, _, _)| b.active)
+            .map(|(_, c, r)| (c.object_handle, r.object_handle));
+
+        active.object_handle = player.map(|(c, _)| c);
+
+        let (collideHandle, bodyHandle) = match player {
+            Some(handles) => handles,
+            None => return,
+        };
+
+        let onGround = grounded(&physics_world, collideHandle);
+
+        let rb = match physics_world.rigid_body_mut(bodyHandle) {
+            Some(rb) => rb,
+            None => return,
+        };
+
+        if rb.status() != BodyStatus::Dynamic {
+            return;
+        }
+
+        let inertia = rb.local_inertia().clone();
+        let control = if onGround { 1.0 } else { AIR_CONTROL };
+
+        let linear = nalgebra::Vector2::new(
+            input.horizontal * MOVE_ACCELERATION * control,
+            - input.vertical * THRUST_ACCELERATION,
+        ) * inertia.linear;
+        let angular = input.horizontal * ROLL_ACCELERATION * inertia.angular;
+
+        rb.apply_force(&nphysics2d::math::Force::new(linear, angular));
+
+        if jump && onGround {
+            let mut velocity = rb.velocity().clone();
+            velocity.linear.y = - JUMP_SPEED;
+            rb.set_velocity(velocity);
+        }
+
+        if input.horizontal != 0.0 || input.vertical != 0.0 || jump {
+            rb.activate();
+        }
+    }
+}
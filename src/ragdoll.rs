@@ -0,0 +1,227 @@
+/**
+ *
+ * Ragdolls.
+ *
+ * A ragdoll is a torso with a head, two arms and two legs, each limb in
+ * two parts. The parts are the links of a multibody rooted at the torso,
+ * held together by revolute joints with limits roughly where a body stops
+ * bending. The parts of a ragdoll don't collide with each other, which
+ * would fight the joints where the parts overlap, but do collide with the
+ * parts of other ragdolls. The `SelfCollisionSystem` filters them by the
+ * root of their multibody:
+ *
+ * ```ignore
+ * RagdollBuilder::new(nalgebra::Point2::new(0.0, -100.0))
+ *     .scale(1.5)
+ *     .material("wood")
+ *     .build(&mut sceneWorld.specs);
+ * ```
+ *
+ */
+
+use nphysics2d::joint::{FreeJoint, RevoluteJoint};
+
+use specs;
+
+use crate::builder::BodyBuilder;
+use crate::material::DEFAULT_MATERIAL;
+use crate::render::RenderStyle;
+use crate::shape::ShapeDesc;
+
+/// A limb part hanging straight down from its joint
+struct Limb {
+    /// The joint at the top, in the frame of the ragdoll at scale 1.0
+    joint: [f32; 2],
+
+    half_height: f32,
+    radius: f32,
+
+    /// How far the limb turns either way from hanging straight down
+    limits: (f32, f32),
+}
+
+/// Upper and lower part of the left arm, the right arm and the two legs are mirrored
+const ARM: [Limb; 2] = [
+    Limb { joint: [-10.0, -14.0], half_height: 6.0, radius: 3.0, limits: (-2.5, 1.0) },
+    Limb { joint: [-10.0, 4.0], half_height: 6.0, radius: 3.0, limits: (-2.2, 0.0) },
+];
+
+const LEG: [Limb; 2] = [
+    Limb { joint: [-5.0, 16.0], half_height: 7.0, radius: 4.0, limits: (-0.5, 1.5) },
+    Limb { joint: [-5.0, 38.0], half_height: 7.0, radius: 4.0, limits: (-2.2, 0.0) },
+];
+
+const TORSO: [f32; 2] = [8.0, 16.0];
+const HEAD_RADIUS: f32 = 7.0;
+
+/// How far the head turns either way
+const NECK_LIMIT: f32 = 0.6;
+
+pub struct RagdollBuilder {
+    position: nalgebra::Point2<f32>,
+    scale: f32,
+    material: String,
+    color: [f32; 4],
+}
+
+impl RagdollBuilder {
+    /// A ragdoll standing with its torso centered on the point
+    pub fn new(position: nalgebra::Point2<f32>) -> RagdollBuilder {
+        RagdollBuilder {
+            position: position,
+            scale: 1.0,
+            material: DEFAULT_MATERIAL.to_owned(),
+            color: [0.9, 0.75, 0.6, 1.0],
+        }
+    }
+
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Name of a material in the `MaterialLibrary`, used by every part
+    pub fn material(mut self, name: &str) -> Self {
+        self.material = name.to_owned();
+        self
+    }
+
+    pub fn color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    fn vector(&self, local: [f32; 2]) -> nalgebra::Vector2<f32> {
+        nalgebra::Vector2::new(local[0], local[1]) * self.scale
+    }
+
+    /// Links a part to `parent` with the joint `parent_shift` from the parent's center, at scale 1.0
+    fn part<J>(&self, specsWorld: &mut specs::World, shape: ShapeDesc, parent: Option<specs::Entity>, joint: J, parent_shift: [f32; 2], body_shift: [f32; 2]) -> specs::Entity
+        where J: nphysics2d::joint::Joint<f32>
+    {
+        BodyBuilder::new(specsWorld, shape.scaled(self.scale))
+            .material(&self.material)
+            .render(RenderStyle::Fill, self.color)
+            .link(parent, joint, self.vector(parent_shift), self.vector(body_shift))
+            .build()
+    }
+
+    /// Hangs the two parts of a limb under the torso, `mirror` gives the right side
+    fn limb(&self, specsWorld: &mut specs::World, torso: specs::Entity, parts: &[Limb; 2], mirror: bool) {
+        let side = if mirror { -1.0 } else { 1.0 };
+        let mut parent = torso;
+        let mut center = [0.0, 0.0];
+
+        for limb in parts.iter() {
+            let joint = [limb.joint[0] * side, limb.joint[1]];
+            let length = limb.half_height + limb.radius;
+            let shape = ShapeDesc::Capsule { half_height: limb.half_height, radius: limb.radius };
+
+            // the right side bends the other way round
+            let limits = if mirror { (- limb.limits.1, - limb.limits.0) } else { limb.limits };
+            let shift = [joint[0] - center[0], joint[1] - center[1]];
+
+            parent = self.part(specsWorld, shape, Some(parent), limited(limits), shift, [0.0, length]);
+            center = [joint[0], joint[1] + length];
+        }
+    }
+
+    /// Creates every part and joint, returns the torso
+    pub fn build(self, specsWorld: &mut specs::World) -> specs::Entity {
+        let root = FreeJoint::new(nalgebra::Isometry2::new(self.position.coords, 0.0));
+        let torso = self.part(specsWorld, ShapeDesc::Cuboid { half_extents: TORSO }, None, root, [0.0, 0.0], [0.0, 0.0]);
+
+        let neck = limited((- NECK_LIMIT, NECK_LIMIT));
+        self.part(specsWorld, ShapeDesc::Ball { radius: HEAD_RADIUS }, Some(torso), neck, [0.0, - TORSO[1]], [0.0, - HEAD_RADIUS]);
+
+        for mirror in [false, true].iter() {
+            self.limb(specsWorld, torso, &ARM, *mirror);
+            self.limb(specsWorld, torso, &LEG, *mirror);
+        }
+
+        torso
+    }
+}
+
+
+
+/// A revolute joint turning from `limits.0` to `limits.1` away from the pose it was built in
+fn limited(limits: (f32, f32)) -> RevoluteJoint<f32> {
+    let mut joint = RevoluteJoint::new(0.0);
+    joint.enable_min_angle(limits.0);
+    joint.enable_max_angle(limits.1);
+    joint
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nphysics2d::object::BodyHandle;
+    use specs::{Join, RunNow};
+
+    use crate::system::{Link, RigidBody};
+    use crate::world::PhysicsWorld;
+
+    /// How far past its limits a joint may be pushed
+    const TOLERANCE: f32 = 0.05;
+
+    /// Angular acceleration every part is twisted with
+    const TWIST: f32 = 200.0;
+
+    fn world() -> specs::World {
+        let mut specsWorld = specs::World::new();
+        specsWorld.register::<crate::system::Collider>();
+        specsWorld.register::<RigidBody>();
+        specsWorld.register::<Link>();
+        specsWorld.register::<crate::modifier::PartOf>();
+        specsWorld.register::<crate::material::MaterialRef>();
+        specsWorld.register::<crate::material::Density>();
+        specsWorld.register::<crate::shape::Shape>();
+        specsWorld.register::<crate::render::Render>();
+
+        let physicsWorld: PhysicsWorld = nphysics2d::world::World::new();
+        specsWorld.add_resource(physicsWorld);
+        specsWorld.add_resource(crate::material::MaterialLibrary::default());
+        specsWorld
+    }
+
+    #[test]
+    fn joints_stay_within_their_limits() {
+        let mut specsWorld = world();
+        RagdollBuilder::new(nalgebra::Point2::origin()).build(&mut specsWorld);
+
+        let handles: Vec<BodyHandle> = (&specsWorld.read_storage::<RigidBody>(), &specsWorld.read_storage::<Link>()).join()
+            .map(|(r, _)| r.object_handle)
+            .collect();
+        assert_eq!(handles.len(), 10);
+
+        // keeps the overlapping parts from colliding with each other
+        crate::modifier::SelfCollisionSystem::default().run_now(&specsWorld.res);
+
+        let mut physics = specsWorld.write_resource::<PhysicsWorld>();
+        for _ in 0..240 {
+            // twist every part hard, half of them each way
+            for (i, handle) in handles.iter().enumerate() {
+                let torque = physics.body_part(*handle).local_inertia().angular * TWIST;
+                let torque = if i % 2 == 0 { torque } else { - torque };
+                physics.body_part_mut(*handle).apply_force(&nphysics2d::math::Force::torque(torque));
+            }
+            physics.step();
+        }
+
+        let mut checked = 0;
+        for handle in handles {
+            let link = physics.multibody_link(handle).expect("Ragdoll part is not a multibody link");
+            if let Some(joint) = link.joint().downcast_ref::<RevoluteJoint<f32>>() {
+                let (min, max) = (joint.min_angle().unwrap(), joint.max_angle().unwrap());
+                assert!(joint.angle() >= min - TOLERANCE && joint.angle() <= max + TOLERANCE,
+                    "Joint at {} left its limits {} to {}", joint.angle(), min, max);
+                checked += 1;
+            }
+        }
+        assert_eq!(checked, 9);
+    }
+}
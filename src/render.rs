@@ -0,0 +1,268 @@
+/**
+ *
+ * Rendering of physics bodies.
+ *
+ * Bodies are spawned with a `Render` component describing how they look.
+ * The matching `Mesh` needs a graphics context so it is built lazily the
+ * next time the scene draws. This lets bodies be spawned from input and
+ * update handlers, which don't get a context.
+ *
+ */
+
+use ggez::graphics;
+
+use specs;
+use specs::prelude::*;
+
+use log::*;
+
+use crate::shape::Shape;
+use crate::system::{Collider, Mesh};
+use crate::world::PhysicsWorld;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RenderStyle {
+    Fill,
+    Line(f32),
+    Hidden,
+}
+
+impl RenderStyle {
+    pub fn draw_mode(&self) -> Option<graphics::DrawMode> {
+        match self {
+            RenderStyle::Fill => Some(graphics::DrawMode::Fill),
+            RenderStyle::Line(width) => Some(graphics::DrawMode::Line(*width)),
+            RenderStyle::Hidden => None,
+        }
+    }
+}
+
+
+
+/// How a body is drawn. Changing it requires removing the `Mesh` so it is rebuilt.
+#[derive(Clone, Debug, Component, Serialize, Deserialize)]
+#[storage(VecStorage)]
+pub struct Render {
+    pub style: RenderStyle,
+    pub color: [f32; 4],
+}
+
+impl Default for Render {
+    fn default() -> Render {
+        Render {
+            style: RenderStyle::Fill,
+            color: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+}
+
+impl Render {
+    pub fn new(style: RenderStyle, color: [f32; 4]) -> Render {
+        Render {
+            style: style,
+            color: color,
+        }
+    }
+
+    pub fn color(&self) -> graphics::Color {
+        graphics::Color::new(self.color[0], self.color[1], self.color[2], self.color[3])
+    }
+}
+
+
+
+/**
+ * Builds the missing meshes of every entity that has a shape and a render
+ * style.
+ */
+pub fn build_meshes(context: &mut ggez::Context, specsWorld: &mut specs::World) {
+    let pending: Vec<(specs::Entity, Shape, Render)> = {
+        let entities = specsWorld.entities();
+        let shapes = specsWorld.read_storage::<Shape>();
+        let renders = specsWorld.read_storage::<Render>();
+        let meshes = specsWorld.read_storage::<Mesh>();
+
+        (&entities, &shapes, &renders, !&meshes)
+            .join()
+            .map(|(e, s, r, _)| (e, s.clone(), r.clone()))
+            .collect()
+    };
+
+    let mut meshes = specsWorld.write_storage::<Mesh>();
+    for (entity, shape, render) in pending {
+        match shape.desc.mesh(context, render.style) {
+            Ok(Some(mesh)) => {
+                meshes.insert(entity, Mesh { mesh: mesh });
+            },
+
+            Ok(None) => {},
+
+            Err(e) => warn!("Could not build mesh for {:?}: {}", entity, e),
+        }
+    }
+}
+
+
+
+/**
+ * Draws every entity that is both a collider and has a mesh at the
+ * collider's position.
+ */
+pub fn draw_bodies(context: &mut ggez::Context, specsWorld: &specs::World) -> ggez::GameResult<()> {
+    let mesh = specsWorld.read_storage::<Mesh>();
+    let collider = specsWorld.read_storage::<Collider>();
+    let render = specsWorld.read_storage::<Render>();
+    let physics_world = specsWorld.read_resource::<PhysicsWorld>();
+    let ncollide_world = physics_world.collision_world();
+
+    for (c, m, r) in (&collider, &mesh, render.maybe()).join() {
+        let collision_object = ncollide_world
+            .collision_object(c.object_handle)
+            .expect("Invalid collision object; was it removed from ncollide but not specs?");
+
+        let isometry = collision_object.position();
+        let point = ggez::nalgebra::Point2::new(isometry.translation.vector.x, isometry.translation.vector.y);
+        let angle = isometry.rotation.angle();
+        let color = r.map(|r| r.color()).unwrap_or(graphics::WHITE);
+
+        let drawParam = graphics::DrawParam {
+            dest: point,
+            rotation: angle,
+            color: Some(color),
+            ..graphics::DrawParam::default()
+        };
+
+        graphics::draw_ex(context, &m.mesh, drawParam)?;
+    }
+
+    Ok(())
+}
+
+
+
+
+/**
+ * Outlines the bounding box of an entity's collider, used to show the
+ * current selection.
+ */
+pub fn draw_highlight(context: &mut ggez::Context, specsWorld: &specs::World, entity: specs::Entity) -> ggez::GameResult<()> {
+    let collider = specsWorld.read_storage::<Collider>();
+    let physics_world = specsWorld.read_resource::<PhysicsWorld>();
+
+    let object = match collider.get(entity).and_then(|c| physics_world.collision_world().collision_object(c.object_handle)) {
+        Some(object) => object,
+        None => return Ok(()),
+    };
+
+    let aabb = object.shape().aabb(object.position());
+    let (mins, maxs) = (aabb.mins(), aabb.maxs());
+    let rect = graphics::Rect::new(mins.x - 2.0, mins.y - 2.0, maxs.x - mins.x + 4.0, maxs.y - mins.y + 4.0);
+
+    graphics::set_color(context, graphics::Color::new(1.0, 1.0, 0.0, 1.0))?;
+    graphics::rectangle(context, graphics::DrawMode::Line(1.0), rect)?;
+    graphics::set_color(context, graphics::WHITE)
+}
+
+
+
+/**
+ * Outlines the region of every force field. Wind also shows its direction
+ * and the other kinds a marker at their center.
+ */
+pub fn draw_fields(context: &mut ggez::Context, specsWorld: &specs::World) -> ggez::GameResult<()> {
+    use crate::system::{ForceGenerator, ForceKind, Region};
+
+    let generators = specsWorld.read_storage::<ForceGenerator>();
+
+    for g in generators.join() {
+        let center = graphics::Point2::new(g.center.x, g.center.y);
+        let color = match g.kind {
+            ForceKind::Wind { .. } => graphics::Color::new(0.4, 0.8, 1.0, 0.6),
+            ForceKind::Vortex => graphics::Color::new(0.8, 0.4, 1.0, 0.6),
+            ForceKind::Attractor => graphics::Color::new(1.0, 0.4, 0.4, 0.6),
+            ForceKind::Drag => graphics::Color::new(0.4, 1.0, 0.4, 0.6),
+        };
+        graphics::set_color(context, color)?;
+
+        match g.region {
+            Region::Box { half_extents } => {
+                let rect = graphics::Rect::new(center.x - half_extents.x, center.y - half_extents.y, half_extents.x * 2.0, half_extents.y * 2.0);
+                graphics::rectangle(context, graphics::DrawMode::Line(1.0), rect)?;
+            },
+
+            Region::Circle { radius } => {
+                graphics::circle(context, graphics::DrawMode::Line(1.0), center, radius, 0.5)?;
+            },
+        }
+
+        match g.kind {
+            ForceKind::Wind { direction } => {
+                let length = if g.strength < 0.0 { -20.0 } else { 20.0 };
+                let tip = graphics::Point2::new(center.x + direction.x * length, center.y + direction.y * length);
+                graphics::line(context, &[center, tip], 2.0)?;
+            },
+
+            _ => {
+                graphics::circle(context, graphics::DrawMode::Fill, center, 3.0, 0.5)?;
+            },
+        }
+    }
+
+    graphics::set_color(context, graphics::WHITE)
+}
+
+
+
+/// Fills the rectangle of every fluid volume, denser fluids more opaque
+pub fn draw_fluids(context: &mut ggez::Context, specsWorld: &specs::World) -> ggez::GameResult<()> {
+    let fluids = specsWorld.read_storage::<crate::fluid::FluidVolume>();
+
+    for fluid in fluids.join() {
+        let rect = graphics::Rect::new(
+            fluid.center.x - fluid.half_extents.x,
+            fluid.center.y - fluid.half_extents.y,
+            fluid.half_extents.x * 2.0,
+            fluid.half_extents.y * 2.0,
+        );
+
+        graphics::set_color(context, graphics::Color::new(0.2, 0.4, 1.0, (fluid.density * 0.3).min(0.5)))?;
+        graphics::rectangle(context, graphics::DrawMode::Fill, rect)?;
+    }
+
+    graphics::set_color(context, graphics::WHITE)
+}
+
+
+
+/// Draws the axis of every magnet, red towards its north pole
+pub fn draw_magnets(context: &mut ggez::Context, specsWorld: &specs::World) -> ggez::GameResult<()> {
+    use crate::pairwise::{Magnet, PairBody};
+
+    let magnets = specsWorld.read_storage::<Magnet>();
+    let rigidBodies = specsWorld.read_storage::<crate::system::RigidBody>();
+    let physics_world = specsWorld.read_resource::<PhysicsWorld>();
+
+    for (magnet, r) in (&magnets, &rigidBodies).join() {
+        let rb = match physics_world.rigid_body(r.object_handle) {
+            Some(rb) => rb,
+            None => continue,
+        };
+
+        let body = PairBody {
+            position: rb.center_of_mass(),
+            rotation: rb.position().rotation.angle(),
+            mass: 0.0,
+        };
+        let axis = magnet.moment(&body).try_normalize(std::f32::EPSILON).unwrap_or(nalgebra::Vector2::x()) * 12.0;
+        let center = graphics::Point2::new(body.position.x, body.position.y);
+        let north = graphics::Point2::new(body.position.x + axis.x, body.position.y + axis.y);
+        let south = graphics::Point2::new(body.position.x - axis.x, body.position.y - axis.y);
+
+        graphics::set_color(context, graphics::Color::new(1.0, 0.2, 0.2, 1.0))?;
+        graphics::line(context, &[center, north], 3.0)?;
+        graphics::set_color(context, graphics::Color::new(0.2, 0.4, 1.0, 1.0))?;
+        graphics::line(context, &[center, south], 3.0)?;
+    }
+
+    graphics::set_color(context, graphics::WHITE)
+}
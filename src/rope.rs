@@ -0,0 +1,377 @@
+/**
+ *
+ * Ropes and chains.
+ *
+ * A rope is a chain of thin capsule bodies. The end of every segment is
+ * tied to the start of the next by a stiff damped spring of zero length,
+ * and the two ends of the rope can be tied the same way to a point in the
+ * world or to a point on any body. A breakable rope lets go wherever the
+ * tension gets above its limit. The `Rope` component sits on its own
+ * entity and refers to the segment entities:
+ *
+ * ```ignore
+ * RopeBuilder::new(top, bottom, 10)
+ *     .segment_length(15.0)
+ *     .start(Anchor::Point(top))
+ *     .end(Anchor::at(&sceneWorld.specs, Some(crate), bottom))
+ *     .breakable(50000.0)
+ *     .build(&mut sceneWorld.specs);
+ * ```
+ *
+ */
+
+use ggez::graphics;
+
+use specs;
+use specs::prelude::*;
+
+use log::*;
+
+use crate::builder::BodyBuilder;
+use crate::material::DEFAULT_MATERIAL;
+use crate::modifier::PartOf;
+use crate::render::RenderStyle;
+use crate::shape::ShapeDesc;
+use crate::system::{Anchor, RigidBody};
+use crate::world::PhysicsWorld;
+
+pub const ROPE_RADIUS: f32 = 2.0;
+
+/// Curve points drawn between two joints of the rope
+const CURVE_STEPS: usize = 4;
+
+/**
+ * Springs tying the segments of a rope together and to its anchors.
+ * Stiffness and damping are per unit of segment mass. A point at the end of
+ * a segment moves about four times as easily as the segment's center and a
+ * tie pulls on two of them, so the spring acts as if about eight times as
+ * strong. The defaults keep that well inside what a 1/60 s step can
+ * integrate without blowing up.
+ */
+#[derive(Clone, Debug, Component)]
+#[storage(HashMapStorage)]
+pub struct Rope {
+    pub segments: Vec<specs::Entity>,
+
+    /// Half the distance between the two ends of a segment
+    pub half_length: f32,
+
+    /// Where the start of the first segment is tied, `None` once it broke
+    pub start: Option<Anchor>,
+
+    /// Where the end of the last segment is tied, `None` for a free end
+    pub end: Option<Anchor>,
+
+    /// Whether each segment is still tied to the next
+    pub joined: Vec<bool>,
+
+    pub stiffness: f32,
+    pub damping: f32,
+
+    /// Ties pulled harder than this break
+    pub break_tension: Option<f32>,
+}
+
+impl Rope {
+    /// Both ends of segment `i`, as anchors on the segment
+    fn segment_ends(&self, i: usize) -> (Anchor, Anchor) {
+        (
+            Anchor::Body(self.segments[i], nalgebra::Point2::new(0.0, - self.half_length)),
+            Anchor::Body(self.segments[i], nalgebra::Point2::new(0.0, self.half_length)),
+        )
+    }
+
+    /// Every tie of the rope as the two anchors it joins
+    fn ties(&self) -> Vec<(Anchor, Anchor)> {
+        let mut ties = Vec::new();
+        let last = self.segments.len() - 1;
+
+        if let Some(start) = self.start {
+            ties.push((start, self.segment_ends(0).0));
+        }
+
+        for i in 0..last {
+            if self.joined[i] {
+                ties.push((self.segment_ends(i).1, self.segment_ends(i + 1).0));
+            }
+        }
+
+        if let Some(end) = self.end {
+            ties.push((self.segment_ends(last).1, end));
+        }
+
+        ties
+    }
+
+    /// Cuts the tie between two anchors, as returned by `ties`
+    fn cut(&mut self, tie: &(Anchor, Anchor)) {
+        if self.start.map(|s| s == tie.0).unwrap_or(false) {
+            self.start = None;
+        } else if self.end.map(|e| e == tie.1).unwrap_or(false) {
+            self.end = None;
+        } else if let Some(i) = (0..self.joined.len()).find(|i| self.segment_ends(*i).1 == tie.0) {
+            self.joined[i] = false;
+        }
+    }
+}
+
+
+
+pub struct RopeBuilder {
+    from: nalgebra::Point2<f32>,
+    to: nalgebra::Point2<f32>,
+    segments: usize,
+    segment_length: Option<f32>,
+    start: Option<Anchor>,
+    end: Option<Anchor>,
+    stiffness: f32,
+    damping: f32,
+    break_tension: Option<f32>,
+    material: String,
+}
+
+impl RopeBuilder {
+    /// A loose rope laid out straight between two points
+    pub fn new(from: nalgebra::Point2<f32>, to: nalgebra::Point2<f32>, segments: usize) -> RopeBuilder {
+        RopeBuilder {
+            from: from,
+            to: to,
+            segments: segments.max(1),
+            segment_length: None,
+            start: None,
+            end: None,
+            stiffness: 800.0,
+            damping: 4.0,
+            break_tension: None,
+            material: DEFAULT_MATERIAL.to_owned(),
+        }
+    }
+
+    /**
+     * Length of each segment. The segments split the distance between the
+     * two points by default, longer ones make a slack rope that starts out
+     * hanging down between them.
+     */
+    pub fn segment_length(mut self, length: f32) -> Self {
+        self.segment_length = Some(length);
+        self
+    }
+
+    pub fn start(mut self, anchor: Anchor) -> Self {
+        self.start = Some(anchor);
+        self
+    }
+
+    pub fn end(mut self, anchor: Anchor) -> Self {
+        self.end = Some(anchor);
+        self
+    }
+
+    pub fn stiffness(mut self, stiffness: f32) -> Self {
+        self.stiffness = stiffness;
+        self
+    }
+
+    pub fn damping(mut self, damping: f32) -> Self {
+        self.damping = damping;
+        self
+    }
+
+    pub fn breakable(mut self, tension: f32) -> Self {
+        self.break_tension = Some(tension);
+        self
+    }
+
+    /// Name of a material in the `MaterialLibrary`
+    pub fn material(mut self, name: &str) -> Self {
+        self.material = name.to_owned();
+        self
+    }
+
+    pub fn build(self, specsWorld: &mut specs::World) -> specs::Entity {
+        let offset = self.to - self.from;
+        let distance = offset.norm();
+        let length = self.segment_length
+            .unwrap_or(distance / self.segments as f32)
+            .max(ROPE_RADIUS * 2.0);
+
+        // slack hangs down to a point under the middle, each side half the rope
+        let half = length * self.segments as f32 / 2.0;
+        let sag = (half * half - distance * distance / 4.0).max(0.0).sqrt();
+        let middle = self.from + offset / 2.0 + nalgebra::Vector2::y() * sag;
+        let along = |s: f32| match s <= half {
+            true => self.from + (middle - self.from) * (s / half),
+            false => middle + (self.to - middle) * ((s - half) / half),
+        };
+
+        // the segments of a rope don't collide with each other, only with
+        // everything else, other ropes included
+        let rope = specsWorld.create_entity().build();
+
+        let segments: Vec<specs::Entity> = (0..self.segments)
+            .map(|i| {
+                let (top, bottom) = (along(length * i as f32), along(length * (i + 1) as f32));
+                let center = nalgebra::center(&top, &bottom);
+                let direction = (bottom - top).try_normalize(std::f32::EPSILON).unwrap_or(nalgebra::Vector2::y());
+
+                // turns the segment's y axis along the rope
+                let rotation = (- direction.x).atan2(direction.y);
+                let shape = ShapeDesc::Capsule {
+                    half_height: (length / 2.0 - ROPE_RADIUS).max(0.5),
+                    radius: ROPE_RADIUS,
+                };
+
+                BodyBuilder::new(specsWorld, shape)
+                    .position(center.x, center.y)
+                    .rotation(rotation)
+                    .material(&self.material)
+                    .render(RenderStyle::Hidden, [0.9, 0.8, 0.6, 1.0])
+                    .with(PartOf { whole: rope })
+                    .build()
+            })
+            .collect();
+
+        specsWorld.write_storage::<Rope>()
+            .insert(rope, Rope {
+                joined: vec![true; segments.len() - 1],
+                segments: segments,
+                half_length: length / 2.0,
+                start: self.start,
+                end: self.end,
+                stiffness: self.stiffness,
+                damping: self.damping,
+                break_tension: self.break_tension,
+            })
+            .expect("Rope was deleted while being built");
+
+        rope
+    }
+}
+
+
+
+/**
+ * Pulls the tied points of every rope together and breaks the ties that
+ * are pulled too hard.
+ */
+pub struct RopeSystem;
+
+impl<'a> specs::System<'a> for RopeSystem {
+    type SystemData = (
+        specs::WriteStorage<'a, Rope>,
+        specs::ReadStorage<'a, RigidBody>,
+        specs::WriteExpect<'a, PhysicsWorld>,
+    );
+
+    fn run(&mut self, (mut ropes, rigidBodies, mut physics_world): Self::SystemData) {
+        for rope in (&mut ropes).join() {
+            let mut broken = Vec::new();
+
+            for tie in rope.ties() {
+                let (a, b) = match (tie.0.resolve(&physics_world, &rigidBodies), tie.1.resolve(&physics_world, &rigidBodies)) {
+                    (Some(a), Some(b)) => (a, b),
+                    _ => {
+                        broken.push(tie);
+                        continue;
+                    },
+                };
+
+                let mass = match (a.mass(), b.mass()) {
+                    (Some(ma), Some(mb)) => ma.min(mb),
+                    (Some(m), None) | (None, Some(m)) => m,
+                    (None, None) => continue,
+                };
+
+                let force = crate::system::spring(&a, &b, rope.stiffness, rope.damping) * mass;
+
+                if rope.break_tension.map(|limit| force.norm() > limit).unwrap_or(false) {
+                    broken.push(tie);
+                    continue;
+                }
+
+                a.push(&mut physics_world, &force);
+                b.push(&mut physics_world, &- force);
+            }
+
+            for tie in broken.iter() {
+                debug!("Rope tie broke");
+                rope.cut(tie);
+            }
+        }
+    }
+}
+
+
+
+/// Catmull-Rom curve through the points
+fn smooth(points: &[nalgebra::Point2<f32>]) -> Vec<graphics::Point2> {
+    if points.len() < 3 {
+        return points.iter().map(|p| graphics::Point2::new(p.x, p.y)).collect();
+    }
+
+    let mut curve = Vec::new();
+    for i in 0..points.len() - 1 {
+        let p0 = points[i.saturating_sub(1)].coords;
+        let p1 = points[i].coords;
+        let p2 = points[i + 1].coords;
+        let p3 = points[(i + 2).min(points.len() - 1)].coords;
+
+        for step in 0..CURVE_STEPS {
+            let t = step as f32 / CURVE_STEPS as f32;
+            let (t2, t3) = (t * t, t * t * t);
+            let p = (p1 * 2.0 + (p2 - p0) * t + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2 + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3) * 0.5;
+            curve.push(graphics::Point2::new(p.x, p.y));
+        }
+    }
+
+    let last = points[points.len() - 1];
+    curve.push(graphics::Point2::new(last.x, last.y));
+    curve
+}
+
+/// Draws every rope as a smooth curve through its joints, broken where its ties broke
+pub fn draw_ropes(context: &mut ggez::Context, specsWorld: &specs::World) -> ggez::GameResult<()> {
+    let ropes = specsWorld.read_storage::<Rope>();
+    let rigidBodies = specsWorld.read_storage::<RigidBody>();
+    let physics_world = specsWorld.read_resource::<PhysicsWorld>();
+
+    graphics::set_color(context, graphics::Color::new(0.9, 0.8, 0.6, 1.0))?;
+
+    for rope in ropes.join() {
+        let position = |anchor: &Anchor| anchor.resolve(&physics_world, &rigidBodies).map(|point| point.position);
+        let mut pieces: Vec<Vec<nalgebra::Point2<f32>>> = vec![Vec::new()];
+
+        if let Some(start) = rope.start.as_ref().and_then(|a| position(a)) {
+            pieces[0].push(start);
+        }
+
+        for i in 0..rope.segments.len() {
+            let (top, bottom) = rope.segment_ends(i);
+            match (position(&top), position(&bottom)) {
+                (Some(top), Some(bottom)) => {
+                    let piece = pieces.last_mut().expect("There is always a piece");
+                    if piece.is_empty() {
+                        piece.push(top);
+                    }
+                    piece.push(bottom);
+                },
+
+                _ => pieces.push(Vec::new()),
+            }
+
+            if i + 1 < rope.segments.len() && !rope.joined[i] {
+                pieces.push(Vec::new());
+            }
+        }
+
+        if let Some(end) = rope.end.as_ref().and_then(|a| position(a)) {
+            pieces.last_mut().expect("There is always a piece").push(end);
+        }
+
+        for piece in pieces.iter().filter(|p| p.len() > 1) {
+            graphics::line(context, &smooth(piece), ROPE_RADIUS * 2.0)?;
+        }
+    }
+
+    graphics::set_color(context, graphics::WHITE)
+}
@@ -0,0 +1,458 @@
+use ggez::graphics;
+use ggez_goodies::input::InputEffect;
+use ggez_goodies::scene::{Scene, SceneSwitch};
+
+use specs;
+use specs::prelude::*;
+
+use log::*;
+
+use crate::builder::Status;
+use crate::fracture::{BreakableJoint, Lifetime};
+use crate::game::{Button, InputEvent};
+use crate::history::{self, Command};
+use crate::material::{MaterialLibrary, DEFAULT_MATERIAL};
+use crate::modifier::PartOf;
+use crate::scene::physicstest::{Boundary, PhysicsTest, SpawnShape};
+use crate::scenefile::{BodyDesc, JointDesc, SceneFile, VehicleDesc};
+use crate::softbody::SoftParticle;
+use crate::system::{Anchor, Link};
+use crate::vehicle::Vehicle;
+use crate::world::SceneWorld;
+
+pub const EDITOR_SCENE_FILE: &str = "scenes/editor.ron";
+
+const GRID_SIZE: f32 = 10.0;
+const TURN_STEP: f32 = std::f32::consts::PI / 12.0;
+const SCALE_STEP: f32 = 1.1;
+const DUPLICATE_OFFSET: f32 = 20.0;
+
+const PALETTE: [[f32; 4]; 6] = [
+    [1.0, 1.0, 1.0, 1.0],
+    [0.9, 0.3, 0.3, 1.0],
+    [0.3, 0.9, 0.3, 1.0],
+    [0.3, 0.5, 1.0, 1.0],
+    [1.0, 0.8, 0.2, 1.0],
+    [0.7, 0.4, 0.9, 1.0],
+];
+
+/**
+ * Level editor drawn over the physics test. The simulation is paused while
+ * it is open. Bodies are picked and dragged with the left mouse button and
+ * edited with the keyboard. The walls and ground follow the tuning panel
+ * and can't be edited here.
+ */
+pub struct EditorScene {
+    selected: Option<specs::Entity>,
+    shape: SpawnShape,
+    drag_offset: Option<nalgebra::Vector2<f32>>,
+    drag_start: Option<nalgebra::Isometry2<f32>>,
+    snap: bool,
+    done: bool,
+}
+
+impl EditorScene {
+    pub fn new () -> EditorScene {
+        debug!("Create EditorScene");
+
+        EditorScene {
+            selected: None,
+            shape: SpawnShape::Box,
+            drag_offset: None,
+            drag_start: None,
+            snap: false,
+            done: false,
+        }
+    }
+
+    fn snapped(&self, value: f32) -> f32 {
+        match self.snap {
+            true => (value / GRID_SIZE).round() * GRID_SIZE,
+            false => value,
+        }
+    }
+
+    /// The entity under the mouse, ignoring the walls and ground
+    fn pick(&self, world: &SceneWorld) -> Option<specs::Entity> {
+        let entity = crate::world::entity_at(&world.specs, &world.mouse.position)?;
+        match world.specs.read_storage::<Boundary>().contains(entity) {
+            true => None,
+            false => Some(entity),
+        }
+    }
+
+    fn selected_desc(&self, world: &SceneWorld) -> Option<(specs::Entity, BodyDesc)> {
+        let entity = self.selected?;
+        BodyDesc::describe(&world.specs, entity).map(|desc| (entity, desc))
+    }
+
+    /// Applies a change to the selected body's description and rebuilds it
+    fn edit<F: FnOnce(&mut BodyDesc, &specs::World)>(&mut self, world: &mut SceneWorld, change: F) {
+        if let Some((entity, mut desc)) = self.selected_desc(world) {
+            change(&mut desc, &world.specs);
+            desc.velocity = (0.0, 0.0);
+            history::edit(&mut world.specs, entity, desc);
+        }
+    }
+
+    fn drag(&mut self, world: &mut SceneWorld) {
+        let mouse = world.mouse.clone();
+
+        if mouse.left_pressed {
+            self.selected = self.pick(world);
+            self.drag_start = self.selected_desc(world).map(|(_, desc)| desc.pose());
+            self.drag_offset = self.selected_desc(world).map(|(_, desc)| {
+                nalgebra::Vector2::new(desc.position.0 - mouse.position.x, desc.position.1 - mouse.position.y)
+            });
+        }
+
+        if !mouse.left {
+            self.drag_offset = None;
+            self.finish_drag(world);
+        }
+
+        if let (Some(entity), Some(offset)) = (self.selected, self.drag_offset) {
+            let target = mouse.position + offset;
+            let rotation = self.selected_desc(world).map(|(_, desc)| desc.rotation).unwrap_or(0.0);
+            let pose = nalgebra::Isometry2::new(
+                nalgebra::Vector2::new(self.snapped(target.x), self.snapped(target.y)),
+                rotation,
+            );
+            crate::world::set_pose(&world.specs, entity, pose);
+        }
+    }
+
+    /// Records the finished drag as a single move
+    fn finish_drag(&mut self, world: &mut SceneWorld) {
+        let from = match self.drag_start.take() {
+            Some(from) => from,
+            None => return,
+        };
+
+        if let Some((entity, desc)) = self.selected_desc(world) {
+            let to = desc.pose();
+            if to != from {
+                let uid = history::uid(&world.specs, entity);
+                history::record(&world.specs, Command::Move { uid: uid, from: from, to: to });
+            }
+        }
+    }
+
+    fn delete(&mut self, world: &mut SceneWorld) {
+        if let Some(entity) = self.selected.take() {
+            history::delete(&mut world.specs, entity);
+        }
+    }
+
+    fn duplicate(&mut self, world: &mut SceneWorld) {
+        if let Some((_, mut desc)) = self.selected_desc(world) {
+            desc.position.0 += DUPLICATE_OFFSET;
+            desc.position.1 += DUPLICATE_OFFSET;
+            desc.velocity = (0.0, 0.0);
+            if let Some(entity) = desc.spawn(&mut world.specs) {
+                history::record_spawn(&world.specs, entity);
+                self.selected = Some(entity);
+            }
+        }
+    }
+
+    /// Picks the next spawn tool shape that is a single body
+    fn next_shape(&mut self) {
+        self.shape = self.shape.next();
+        while !placeable(self.shape) {
+            self.shape = self.shape.next();
+        }
+    }
+
+    /// Adds a body of the picked shape under the mouse and selects it
+    fn place(&mut self, world: &mut SceneWorld) {
+        let position = world.mouse.position;
+        let position = nalgebra::Point2::new(self.snapped(position.x), self.snapped(position.y));
+        let entity = PhysicsTest::create_shape(&mut world.specs, self.shape, DEFAULT_MATERIAL, position);
+        history::record_spawn(&world.specs, entity);
+        self.selected = Some(entity);
+    }
+
+    fn undo(&mut self, world: &mut SceneWorld, redo: bool) {
+        self.drag_offset = None;
+        self.drag_start = None;
+
+        match redo {
+            true => history::redo(&mut world.specs),
+            false => history::undo(&mut world.specs),
+        }
+
+        // the selected body may have been deleted or respawned
+        if let Some(entity) = self.selected {
+            if !world.specs.is_alive(entity) {
+                self.selected = None;
+            }
+        }
+    }
+
+    /**
+     * Every editable body in the world. The walls and ground are left out,
+     * and so are the parts of soft bodies, ropes and multibodies and the
+     * fragments of broken bodies, which only make sense as a whole.
+     */
+    fn bodies(world: &SceneWorld) -> Vec<specs::Entity> {
+        let entities = world.specs.entities();
+        let shapes = world.specs.read_storage::<crate::shape::Shape>();
+        let boundaries = world.specs.read_storage::<Boundary>();
+        let particles = world.specs.read_storage::<SoftParticle>();
+        let fragments = world.specs.read_storage::<Lifetime>();
+        let links = world.specs.read_storage::<Link>();
+        let parts = world.specs.read_storage::<PartOf>();
+
+        (&entities, &shapes, !&boundaries, !&particles, !&fragments, !&links, !&parts).join()
+            .map(|(e, ..)| e)
+            .collect()
+    }
+
+    /// The breakable joints holding saved bodies, by their index in `saved`
+    fn joints(world: &SceneWorld, saved: &[specs::Entity]) -> Vec<JointDesc> {
+        let joints = world.specs.read_storage::<BreakableJoint>();
+
+        // the saved body an anchor is on, `None` for the world, and where it is
+        let end = |anchor: &Anchor| -> Option<(Option<usize>, nalgebra::Point2<f32>)> {
+            match anchor {
+                Anchor::Point(point) => Some((None, *point)),
+                Anchor::Body(entity, local) => {
+                    let index = saved.iter().position(|e| e == entity)?;
+                    let pose = crate::world::body_pose(&world.specs, *entity)?;
+                    Some((Some(index), pose * local))
+                },
+            }
+        };
+
+        joints.join()
+            .filter_map(|joint| {
+                let ((a, point), (b, _)) = (end(&joint.a)?, end(&joint.b)?);
+                Some(JointDesc {
+                    a: a,
+                    b: b,
+                    point: (point.x, point.y),
+                    threshold: joint.threshold,
+                })
+            })
+            .collect()
+    }
+
+    fn vehicles(world: &SceneWorld) -> Vec<VehicleDesc> {
+        let entities = world.specs.entities();
+        let vehicles = world.specs.read_storage::<Vehicle>();
+
+        (&entities, &vehicles).join()
+            .filter_map(|(chassis, vehicle)| {
+                let pose = crate::world::body_pose(&world.specs, chassis)?;
+                Some(VehicleDesc {
+                    position: (pose.translation.vector.x, pose.translation.vector.y),
+                    torque: Some(vehicle.torque),
+                })
+            })
+            .collect()
+    }
+
+    fn save(&self, world: &SceneWorld) {
+        let (saved, bodies): (Vec<specs::Entity>, Vec<BodyDesc>) = EditorScene::bodies(world).into_iter()
+            .filter_map(|entity| BodyDesc::describe(&world.specs, entity).map(|desc| (entity, desc)))
+            .unzip();
+
+        let scene = SceneFile {
+            joints: EditorScene::joints(world, &saved),
+            vehicles: EditorScene::vehicles(world),
+            bodies: bodies,
+            ..SceneFile::default()
+        };
+
+        match scene.save(EDITOR_SCENE_FILE) {
+            Ok(()) => info!("Saved {} bodies, {} joints and {} vehicles to {}", scene.bodies.len(), scene.joints.len(), scene.vehicles.len(), EDITOR_SCENE_FILE),
+            Err(e) => warn!("{}", e),
+        }
+    }
+
+    /**
+     * Replaces every editable body, the joints holding them and every
+     * vehicle with those in the editor scene file. Only the bodies can be
+     * undone, like spawned vehicles.
+     */
+    fn load(&mut self, world: &mut SceneWorld) {
+        let scene = match SceneFile::load(EDITOR_SCENE_FILE) {
+            Ok(scene) => scene,
+            Err(e) => {
+                warn!("{}", e);
+                return;
+            }
+        };
+
+        self.selected = None;
+        let bodies = EditorScene::bodies(world);
+
+        let doomed: Vec<specs::Entity> = {
+            let entities = world.specs.entities();
+            let joints = world.specs.read_storage::<BreakableJoint>();
+            let vehicles = world.specs.read_storage::<Vehicle>();
+            let held = |anchor: &Anchor| match anchor {
+                Anchor::Body(entity, _) => bodies.contains(entity),
+                Anchor::Point(_) => false,
+            };
+
+            (&entities, &joints).join()
+                .filter(|(_, joint)| held(&joint.a) || held(&joint.b))
+                .map(|(e, _)| e)
+                .chain((&entities, &vehicles).join().map(|(e, _)| e))
+                .collect()
+        };
+        for entity in doomed {
+            crate::world::destroy_entity(&mut world.specs, entity);
+        }
+
+        let mut commands = Vec::new();
+        for entity in bodies {
+            if let Some(desc) = BodyDesc::describe(&world.specs, entity) {
+                commands.push(Command::Delete { uid: history::uid(&world.specs, entity), desc: desc });
+            }
+            crate::world::destroy_entity(&mut world.specs, entity);
+        }
+
+        for entity in scene.spawn(&mut world.specs) {
+            if let Some(desc) = BodyDesc::describe(&world.specs, entity) {
+                commands.push(Command::Spawn { uid: history::uid(&world.specs, entity), desc: desc });
+            }
+        }
+
+        history::record(&world.specs, Command::Batch(commands));
+    }
+
+    fn draw_grid(&self, world: &SceneWorld, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        let screen = world.screen_rect();
+        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 0.1))?;
+
+        let mut x = (screen.x / GRID_SIZE).floor() * GRID_SIZE;
+        while x < screen.x + screen.w {
+            graphics::line(ctx, &[graphics::Point2::new(x, screen.y), graphics::Point2::new(x, screen.y + screen.h)], 1.0)?;
+            x += GRID_SIZE;
+        }
+
+        let mut y = (screen.y / GRID_SIZE).floor() * GRID_SIZE;
+        while y < screen.y + screen.h {
+            graphics::line(ctx, &[graphics::Point2::new(screen.x, y), graphics::Point2::new(screen.x + screen.w, y)], 1.0)?;
+            y += GRID_SIZE;
+        }
+
+        graphics::set_color(ctx, graphics::WHITE)
+    }
+}
+
+/// Cars, ragdolls and soft bodies are made of several bodies the editor can't describe
+fn placeable(shape: SpawnShape) -> bool {
+    match shape {
+        SpawnShape::Car | SpawnShape::Ragdoll | SpawnShape::Jelly | SpawnShape::JellyGrid => false,
+        _ => true,
+    }
+}
+
+/// The status after `status`, skipping dynamic for shapes without a mass
+fn next_status(status: Status, solid: bool) -> Status {
+    let next = match status {
+        Status::Dynamic => Status::Static,
+        Status::Static => Status::Kinematic,
+        Status::Kinematic => Status::Dynamic,
+    };
+
+    match next {
+        Status::Dynamic if !solid => next_status(next, solid),
+        _ => next,
+    }
+}
+
+fn next_color(color: [f32; 4]) -> [f32; 4] {
+    let index = PALETTE.iter().position(|c| *c == color).unwrap_or(PALETTE.len() - 1);
+    PALETTE[(index + 1) % PALETTE.len()]
+}
+
+impl Scene<SceneWorld, InputEvent> for EditorScene {
+    fn update(&mut self, world: &mut SceneWorld) -> SceneSwitch<SceneWorld, InputEvent> {
+        self.drag(world);
+
+        if self.done {
+            SceneSwitch::Pop
+        } else {
+            SceneSwitch::None
+        }
+    }
+
+    fn draw(&mut self, world: &mut SceneWorld, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        if self.snap {
+            self.draw_grid(world, ctx)?;
+        }
+
+        if let Some(entity) = self.selected {
+            crate::render::draw_highlight(ctx, &world.specs, entity)?;
+        }
+
+        let mut panel = crate::ui::Panel::new("Editor (E to close, simulation paused)");
+        match self.selected_desc(world) {
+            Some((_, desc)) => {
+                panel = panel
+                    .line(format!("shape:    {}", desc.shape.name()))
+                    .line(format!("position: {:.1}, {:.1}", desc.position.0, desc.position.1))
+                    .line(format!("rotation: {:.2} (R)", desc.rotation))
+                    .line(format!("material: {} (M)", desc.material))
+                    .line(format!("status:   {:?} (B)", desc.status))
+                    .line(format!("color:    {:?} (K)", desc.render.color));
+            },
+
+            None => {
+                panel = panel.line("click a body to select it");
+            },
+        }
+
+        panel
+            .line("")
+            .line(format!("grid snap: {} (N)", self.snap))
+            .line(format!("place: {:?} (X to change, Insert at cursor)", self.shape))
+            .line("+/- scale, C duplicate, Delete remove")
+            .line("Ctrl+Z undo, Ctrl+Shift+Z redo")
+            .line(format!("F5 save, F9 load {}", EDITOR_SCENE_FILE))
+            .draw(ctx, graphics::Point2::new(10.0, 10.0))
+    }
+
+    fn name(&self) -> &str {
+        "EditorScene"
+    }
+
+    fn draw_previous(&self) -> bool {
+        true
+    }
+
+    fn input(&mut self, world: &mut SceneWorld, ev: InputEvent, started: bool) {
+        if !started {
+            return;
+        }
+
+        match ev {
+            InputEffect::Button(Button::Turn) => self.edit(world, |desc, _| desc.rotation += TURN_STEP),
+            InputEffect::Button(Button::Grow) => self.edit(world, |desc, _| desc.scale(SCALE_STEP)),
+            InputEffect::Button(Button::Shrink) => self.edit(world, |desc, _| desc.scale(1.0 / SCALE_STEP)),
+            InputEffect::Button(Button::NextStatus) => self.edit(world, |desc, _| desc.status = next_status(desc.status, desc.shape.is_solid())),
+            InputEffect::Button(Button::NextColor) => self.edit(world, |desc, _| desc.render.color = next_color(desc.render.color)),
+            InputEffect::Button(Button::NextMaterial) => self.edit(world, |desc, specs| {
+                desc.material = specs.read_resource::<MaterialLibrary>().next(&desc.material).to_owned();
+            }),
+
+            InputEffect::Button(Button::Duplicate) => self.duplicate(world),
+            InputEffect::Button(Button::NextShape) => self.next_shape(),
+            InputEffect::Button(Button::Place) => self.place(world),
+            InputEffect::Button(Button::Delete) => self.delete(world),
+            InputEffect::Button(Button::Snap) => self.snap = !self.snap,
+            InputEffect::Button(Button::Undo) => self.undo(world, false),
+            InputEffect::Button(Button::Redo) => self.undo(world, true),
+            InputEffect::Button(Button::Save) => self.save(world),
+            InputEffect::Button(Button::Load) => self.load(world),
+            InputEffect::Button(Button::Editor) | InputEffect::Button(Button::Quit) => self.done = true,
+
+            _ => {}
+        }
+    }
+}
@@ -34,9 +34,32 @@ impl Scene<SceneWorld, InputEvent> for MenuScene {
         let t = ggez::graphics::TextCached::new(r#"
 This is a collision test to verify ggez and nphysics2d.
 
-- use spacebar to apply force to all balls
-- use z to add more balls
+- use p to apply force to all balls
+- use z to add more bodies, x to change their shape, pick a jelly,
+  a crate that shatters when hit hard, a character, a one-way platform
+  a conveyor, a car, a ragdoll or a boulder traced from its image
+- use m to pick the material of new bodies
+- use q to switch the mouse tool: click to spawn, drag to draw a shape,
+  click to inspect a body (pgup/pgdn and left/right to edit it)
+  drag back from a ball and release to shoot it, click to explode
+  or click to place a force field (v picks wind, vortex, attractor or drag,
+  r turns the wind)
+  or drag out a rope tied to the bodies at its ends (o makes it breakable)
+  or drag a body around and let go to throw it
+- use f3 to show force fields with any tool
+- use h to fill the box with water, then water and air
 - use g to toggle gravity (default off)
+- use u to switch the ground between flat and a few seeds of rolling hills
+- use a/d to roll the player ball, up/down to thrust and w to jump;
+  tab or a right click picks another ball, characters walk and jump too,
+  a right click on a car drives it with up and brakes or reverses with down
+- use ctrl+z to undo and ctrl+shift+z to redo, spawned jellies, cars
+  and ragdolls can't be undone
+- use t to open the tuning panel, e to open the level editor
+- use l to load the bodies in scenes/default.ron, again for
+  scenes/electrostatics.ron with charges and magnets and once more
+  for scenes/terrain.ron with a car to drive, then scenes/level.tmx,
+  a map made in Tiled, and scenes/arena.svg, a vector drawing
 
 Press SPACEBAR to continue.
 "#)?;
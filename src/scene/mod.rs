@@ -1,3 +1,4 @@
+pub mod editor;
 pub mod menu;
 pub mod physicstest;
-
+pub mod tuning;
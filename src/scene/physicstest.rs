@@ -1,49 +1,200 @@
 use ggez::graphics::DrawParam;
 use ggez_goodies::scene::{Scene, SceneSwitch};
 
-use nphysics2d::volumetric::Volumetric;
-
 use specs;
 use specs::prelude::*;
 
 use log::*;
 
 use crate::game::{Axis, Button, InputEvent};
-use crate::world::SceneWorld;
+use crate::explosion::Explosion;
+use crate::fluid::FluidVolume;
+use crate::fracture::Breakable;
+use crate::history::{self, Command};
+use crate::inspector::Inspector;
+use crate::builder::{BodyBuilder, Status};
+use crate::character::CharacterController;
+use crate::material::{MaterialLibrary, DEFAULT_MATERIAL};
+use crate::modifier::{Conveyor, OneWay};
+use crate::ragdoll::RagdollBuilder;
+use crate::render::RenderStyle;
+use crate::scenefile::{BodyDesc, SceneFile};
+use crate::softbody::SoftBodyBuilder;
+use crate::sprite::SpriteBuilder;
+use crate::shape::{Part, ShapeDesc};
+use crate::tool::{MouseDrag, RopeDrag, Sketch, Slingshot, Tool};
+use crate::vehicle::{Vehicle, VehicleBuilder};
+use crate::system::{ForceGenerator, ForceKind, Region};
+use crate::tuning::Tuning;
+use crate::world::{SceneWorld, CAMERA_HEIGHT, CAMERA_WIDTH};
+
+
+/// Scene files loaded in turn with L
+const SCENE_FILES: [&str; 5] = ["scenes/default.ron", "scenes/electrostatics.ron", "scenes/terrain.ron", "scenes/level.tmx", "scenes/arena.svg"];
+
+/// World units per pixel of SVG drawings
+const SVG_SCALE: f32 = 1.0;
+
+const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+const EXPLOSION_STRENGTH: f32 = 40000.0;
+
+/// Explosion and force field strengths are multiplied or divided by this with [ and ]
+const STRENGTH_STEP: f32 = 1.5;
+
+const FIELD_SIZE: f32 = 80.0;
+
+/// Wind fields are turned by this with R
+const WIND_TURN: f32 = std::f32::consts::PI / 4.0;
+
+/// Impulse that shatters a spawned crate
+const CRATE_STRENGTH: f32 = 20000.0;
+
+const CONVEYOR_SPEED: f32 = 60.0;
+
+/// Traced into the collider of a spawned sprite
+const SPRITE_IMAGE: &str = "images/boulder.png";
+
+const WATER_DENSITY: f32 = 1.0;
+const AIR_DENSITY: f32 = 0.2;
+
+/// Fluids cycled through with H
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Fluids {
+    None,
+    Water,
+    WaterAndAir,
+}
+
+impl Fluids {
+    pub fn next(&self) -> Fluids {
+        match self {
+            Fluids::None => Fluids::Water,
+            Fluids::Water => Fluids::WaterAndAir,
+            Fluids::WaterAndAir => Fluids::None,
+        }
+    }
+}
+
+#[derive(Component)]
+#[storage(VecStorage)]
+pub struct Ball {
+    pub active: bool,
+}
 
-const GRAVITY: f32 = 2.0;
+/// The walls and ground around the test area
+#[derive(Component)]
+#[storage(VecStorage)]
+pub struct Boundary;
 
-// Restitution:
-// 0.9 bouncy ball
-// 0.5 baloon
-// 0.0 wet toilet paper
-const BALL_RESTITUTION: f32 = 0.0;
+/// Shapes the spawn tool cycles through
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SpawnShape {
+    Ball,
+    Box,
+    Capsule,
+    Polygon,
+    Compound,
 
-// Friction
-// 50.0 velcro
-// 2.0 rocks
-// 0.0 ice
-const BALL_FRICTION: f32 = 50.0;
-const BALL_DENSITY: f32 = 1.0;
+    /// A box that shatters when hit hard
+    Crate,
 
-const CAMERA_WIDTH: f32 = 800.0;
-const CAMERA_HEIGHT: f32 = 600.0;
+    /// A kinematic capsule walked around with the player keys
+    Character,
 
-const WALL_SIZE: f32 = 200.0;
+    /// A static ledge that only holds from above
+    Platform,
 
-#[derive(Component)]
-#[storage(VecStorage)]
-pub struct Ball {
-    active: bool,
+    /// A static belt carrying what rests on it to the right
+    Conveyor,
+
+    /// A car driven with forward and backward
+    Car,
+
+    /// Limbs on angle-limited joints
+    Ragdoll,
+
+    /// An image with a collider traced around its opaque pixels
+    Sprite,
+    Jelly,
+    JellyGrid,
+}
+
+impl SpawnShape {
+    pub fn next(&self) -> SpawnShape {
+        match self {
+            SpawnShape::Ball => SpawnShape::Box,
+            SpawnShape::Box => SpawnShape::Capsule,
+            SpawnShape::Capsule => SpawnShape::Polygon,
+            SpawnShape::Polygon => SpawnShape::Compound,
+            SpawnShape::Compound => SpawnShape::Crate,
+            SpawnShape::Crate => SpawnShape::Character,
+            SpawnShape::Character => SpawnShape::Platform,
+            SpawnShape::Platform => SpawnShape::Conveyor,
+            SpawnShape::Conveyor => SpawnShape::Car,
+            SpawnShape::Car => SpawnShape::Ragdoll,
+            SpawnShape::Ragdoll => SpawnShape::Sprite,
+            SpawnShape::Sprite => SpawnShape::Jelly,
+            SpawnShape::Jelly => SpawnShape::JellyGrid,
+            SpawnShape::JellyGrid => SpawnShape::Ball,
+        }
+    }
+
+    /**
+     * The shape of a spawned body, `None` for the spawns that aren't a
+     * single body described by its shape: soft bodies, cars and ragdolls
+     * are made of many bodies and a sprite's shape is traced from its image.
+     */
+    pub fn desc(&self) -> Option<ShapeDesc> {
+        let desc = match self {
+            SpawnShape::Car | SpawnShape::Ragdoll | SpawnShape::Sprite | SpawnShape::Jelly | SpawnShape::JellyGrid => return None,
+
+            SpawnShape::Ball => ShapeDesc::Ball { radius: 10.0 },
+            SpawnShape::Box => ShapeDesc::Cuboid { half_extents: [10.0, 10.0] },
+            SpawnShape::Crate => ShapeDesc::Cuboid { half_extents: [15.0, 15.0] },
+            SpawnShape::Character => ShapeDesc::Capsule { half_height: 8.0, radius: 6.0 },
+            SpawnShape::Platform | SpawnShape::Conveyor => ShapeDesc::Cuboid { half_extents: [40.0, 3.0] },
+            SpawnShape::Capsule => ShapeDesc::Capsule { half_height: 10.0, radius: 6.0 },
+            SpawnShape::Polygon => {
+                ShapeDesc::convex(&[[0.0, -14.0], [13.0, -4.0], [8.0, 12.0], [-8.0, 12.0], [-13.0, -4.0]])
+                    .expect("Spawn polygon has an area")
+            },
+
+            // A dumbbell: two balls joined by a bar
+            SpawnShape::Compound => ShapeDesc::Compound {
+                parts: vec![
+                    Part { position: [-14.0, 0.0], rotation: 0.0, shape: ShapeDesc::Ball { radius: 7.0 } },
+                    Part { position: [  0.0, 0.0], rotation: 0.0, shape: ShapeDesc::Cuboid { half_extents: [10.0, 2.0] } },
+                    Part { position: [ 14.0, 0.0], rotation: 0.0, shape: ShapeDesc::Ball { radius: 7.0 } },
+                ],
+            },
+        };
+
+        Some(desc)
+    }
 }
 
 pub struct PhysicsTest {
     dispatcher: specs::Dispatcher<'static, 'static>,
-    drawBall: bool,
-    gravity: bool,
+    tool: Tool,
+    sketch: Sketch,
+    slingshot: Slingshot,
+    rope: RopeDrag,
+    drag: MouseDrag,
+    explosion: Explosion,
+    force: ForceKind,
+    fluids: Fluids,
+    scene: usize,
+    debug: bool,
+    shape: SpawnShape,
+    material: String,
+    inspector: Inspector,
+    push: Option<Box<dyn Scene<SceneWorld, InputEvent>>>,
     done: bool,
 }
 
+/// Collider of the player controlled ball, kept up to date by the `PlayerSystem`
+#[derive(Debug, Default)]
 pub struct ActiveBall {
     pub object_handle: Option<ncollide2d::world::CollisionObjectHandle>,
 }
@@ -52,15 +203,59 @@ impl PhysicsTest {
     pub fn new () -> PhysicsTest {
         debug!("Create CollisionTest");
 
+        let player = crate::player::PlayerSystem{};
+        let characters = crate::character::CharacterSystem{};
+        let oneWays = crate::modifier::OneWaySystem::default();
+        let conveyors = crate::modifier::ConveyorSystem{};
+        let selfCollisions = crate::modifier::SelfCollisionSystem::default();
+        let vehicles = crate::vehicle::VehicleSystem{};
+        let fields = crate::system::ForceFieldSystem{};
+        let fluid = crate::fluid::FluidSystem{};
+        let charges = crate::pairwise::PairwiseSystem::<crate::pairwise::Charge>::default();
+        let magnets = crate::pairwise::PairwiseSystem::<crate::pairwise::Magnet>::default();
+        let gravity = crate::pairwise::PairwiseSystem::<crate::system::Gravity>::default();
+        let springs = crate::softbody::SoftBodySystem{};
+        let ropes = crate::rope::RopeSystem{};
+        let breakableJoints = crate::fracture::BreakableJointSystem{};
         let physics = crate::system::PhysicsSystem{};
+        let lifetimes = crate::fracture::LifetimeSystem{};
+        let materials = crate::material::ContactMaterialSystem::default();
         let dispatcher = specs::DispatcherBuilder::new()
-            .with(physics, "sys_physics", &[])
+            .with(player, "sys_player", &[])
+            .with(characters, "sys_characters", &[])
+            .with(oneWays, "sys_one_way", &[])
+            .with(conveyors, "sys_conveyors", &[])
+            .with(selfCollisions, "sys_self_collisions", &[])
+            .with(vehicles, "sys_vehicles", &[])
+            .with(fields, "sys_fields", &[])
+            .with(fluid, "sys_fluid", &[])
+            .with(charges, "sys_charges", &[])
+            .with(magnets, "sys_magnets", &[])
+            .with(gravity, "sys_gravity", &[])
+            .with(springs, "sys_springs", &[])
+            .with(ropes, "sys_ropes", &[])
+            .with(breakableJoints, "sys_breakable_joints", &[])
+            .with(physics, "sys_physics", &["sys_player", "sys_characters", "sys_one_way", "sys_conveyors", "sys_self_collisions", "sys_vehicles", "sys_fields", "sys_fluid", "sys_charges", "sys_magnets", "sys_gravity", "sys_springs", "sys_ropes", "sys_breakable_joints"])
+            .with(materials, "sys_materials", &["sys_physics"])
+            .with(lifetimes, "sys_lifetimes", &["sys_physics"])
             .build();
 
         PhysicsTest {
             dispatcher: dispatcher,
-            drawBall: false,
-            gravity: false,
+            tool: Tool::Spawn,
+            sketch: Sketch::default(),
+            slingshot: Slingshot::default(),
+            rope: RopeDrag::default(),
+            drag: MouseDrag::default(),
+            explosion: Explosion::new(EXPLOSION_STRENGTH),
+            force: ForceKind::Wind { direction: nalgebra::Vector2::x() },
+            fluids: Fluids::None,
+            scene: 0,
+            debug: false,
+            shape: SpawnShape::Ball,
+            material: DEFAULT_MATERIAL.to_owned(),
+            inspector: Inspector::default(),
+            push: None,
             done: false,
         }
     }
@@ -69,16 +264,27 @@ impl PhysicsTest {
 
     /**
      *
-     * Creates an entity as a collection of features including graphical
-     * representation. Adds that entity to the physics world, and collider.
-     * Then writes the entity coupled with the physics world handler.
+     * Creates a ball as a collection of features including graphical
+     * representation, and adds it to the physics world.
+     * The material is looked up by name in the `MaterialLibrary`.
      *
      */
-    pub fn create_ball(context: &mut ggez::Context, specsWorld: &mut specs::World) {
+    pub fn create_ball(specsWorld: &mut specs::World, material: &str, position: nalgebra::Point2<f32>) -> specs::Entity {
         debug!("Create Ball");
         const RADIUS: f32 = 10.0;
 
-        let entity = specsWorld.create_entity()
+        let builder = BodyBuilder::new(specsWorld, ShapeDesc::Ball { radius: RADIUS })
+            .position(position.x, position.y)
+            .material(material);
+
+        PhysicsTest::ball_components(builder).build()
+    }
+
+
+
+    /// Adds the components that make a body one of the player's balls
+    pub fn ball_components<'a>(builder: BodyBuilder<'a>) -> BodyBuilder<'a> {
+        builder
             .with(Ball { active: false })
             .with(crate::system::Motion {
                 velocity: nalgebra::Vector2::new(1.5, -1.0),
@@ -88,136 +294,459 @@ impl PhysicsTest {
             .with(crate::system::Gravity {
                 force: None,
             })
-            .with(crate::system::Mesh {
-                mesh: ggez::graphics::MeshBuilder::default()
-                    .circle(
-                        ggez::graphics::DrawMode::Fill,
-                        ggez::graphics::Point2::new(0.0, 0.0),
-                        RADIUS,
-                        0.1,
-                    ).build(context)
-                    .unwrap(),
-            }).build();
-
-        // Pull the physics world and add a shape
-        let mut physics = specsWorld.write_resource::<crate::world::PhysicsWorld>();
-        let shape = ncollide2d::shape::ShapeHandle::new(ncollide2d::shape::Ball::new(RADIUS));
-        let position = nalgebra::Isometry2::new(nalgebra::Vector2::new(0.0, 0.0), 0.0);
-        let inertia = shape.inertia(BALL_DENSITY);
-        let centerOfMass = shape.center_of_mass();
-        let bodyHandle = physics.add_rigid_body(position, inertia, centerOfMass);
-
-        // Add the rigid body with status from shape
-        let mut rigidBody = physics.rigid_body_mut(bodyHandle).unwrap();
-        rigidBody.set_status(nphysics2d::object::BodyStatus::Dynamic);
-        rigidBody.activation_status_mut().set_deactivation_threshold(None);
-
-        // Add the rigid body to the collider
-        let collideHandle = physics.add_collider(
-            0.0,
-            shape,
-            bodyHandle,
-            nalgebra::one(),
-            nphysics2d::object::Material::new(BALL_RESTITUTION, BALL_FRICTION),
-        );
-
-        specsWorld
-            .write_storage::<crate::system::Collider>()
-            .insert(entity, crate::system::Collider {
-                object_handle: collideHandle
-            });
-
-         specsWorld
-            .write_storage::<crate::system::RigidBody>()
-            .insert(entity, crate::system::RigidBody {
-                object_handle: bodyHandle
-            });
     }
 
 
 
-    pub fn create_walls(context: &mut ggez::Context, specsWorld: &mut specs::World) {
+    /**
+     * Adds a force field of the given kind. Wind and drag cover a square,
+     * vortices and attractors a circle.
+     */
+    pub fn create_field(specsWorld: &mut specs::World, kind: ForceKind, center: nalgebra::Point2<f32>) -> specs::Entity {
+        debug!("Create {:?} field", kind);
+
+        let (region, strength) = match kind {
+            ForceKind::Wind { .. } => (Region::Box { half_extents: nalgebra::Vector2::new(FIELD_SIZE, FIELD_SIZE) }, 200.0),
+            ForceKind::Vortex => (Region::Circle { radius: FIELD_SIZE }, 150.0),
+            ForceKind::Attractor => (Region::Circle { radius: FIELD_SIZE }, 200.0),
+            ForceKind::Drag => (Region::Box { half_extents: nalgebra::Vector2::new(FIELD_SIZE, FIELD_SIZE) }, 2.0),
+        };
 
-        let entity = specsWorld
-            .create_entity()
-            .with(crate::system::Mesh {
-                mesh: ggez::graphics::MeshBuilder::default()
-                    .polygon(
-                        ggez::graphics::DrawMode::Line(2.0),
-                        &[
-                            ggez::graphics::Point2::new(- WALL_SIZE,   WALL_SIZE),
-                            ggez::graphics::Point2::new(- WALL_SIZE, - WALL_SIZE),
-                            ggez::graphics::Point2::new(  WALL_SIZE, - WALL_SIZE),
-                            ggez::graphics::Point2::new(  WALL_SIZE,   WALL_SIZE),
-                        ],
-                    )
-                    .build(context).unwrap(),
+        specsWorld.create_entity()
+            .with(ForceGenerator {
+                center: center,
+                region: region,
+                kind: kind,
+                strength: strength,
             })
-            .build();
+            .build()
+    }
+
 
-        let mut physics = specsWorld.write_resource::<crate::world::PhysicsWorld>();
-
-        let shape = ncollide2d::shape::ShapeHandle::new(
-            ncollide2d::shape::Polyline::new(vec![
-                nalgebra::Point2::new(- WALL_SIZE,   WALL_SIZE),
-                nalgebra::Point2::new(- WALL_SIZE, - WALL_SIZE),
-                nalgebra::Point2::new(  WALL_SIZE, - WALL_SIZE),
-                nalgebra::Point2::new(  WALL_SIZE,   WALL_SIZE),
-            ])
-        );
-
-        let position = nalgebra::one();
-        let inertia =  nphysics2d::math::Inertia::zero();
-        let center_of_mass = nalgebra::Point2::new(0.0, 0.0);
-        let body_status = nphysics2d::object::BodyStatus::Static;
-        let body_handle = physics.add_rigid_body(position, inertia, center_of_mass);
-        let mut rigid_body = physics.rigid_body_mut(body_handle).unwrap();
-
-        rigid_body
-            .set_status(body_status);
-
-        rigid_body
-            .activation_status_mut()
-            .set_deactivation_threshold(None);
-
-        // bodies_handle.insert(entity, RigidBody(body_handle));
-
-        let collideHandle = physics.add_collider(
-            0.0,
-            shape,
-            body_handle,
-            nalgebra::one(),
-            nphysics2d::object::Material::new(BALL_RESTITUTION, BALL_FRICTION),
-        );
-
-        let collider = crate::system::Collider { object_handle: collideHandle };
-
-        specsWorld
-            .write_storage::<crate::system::Collider>()
-            .insert(entity, collider);
+
+    /// The force field under the point, the last placed one if they overlap
+    fn field_at(specsWorld: &specs::World, point: &nalgebra::Point2<f32>) -> Option<specs::Entity> {
+        let entities = specsWorld.entities();
+        let generators = specsWorld.read_storage::<ForceGenerator>();
+
+        (&entities, &generators).join()
+            .filter(|(_, g)| g.region.contains(&(point - g.center)))
+            .map(|(e, _)| e)
+            .last()
     }
 
 
 
-    pub fn create_ground(context: &mut ggez::Context, specsWorld: &mut specs::World) {
-        let entity = specsWorld.create_entity()
-            .build();
+    /// Scales the strength of the force field under the mouse
+    fn scale_field(world: &SceneWorld, factor: f32) {
+        if let Some(entity) = PhysicsTest::field_at(&world.specs, &world.mouse.position) {
+            if let Some(g) = world.specs.write_storage::<ForceGenerator>().get_mut(entity) {
+                g.strength *= factor;
+            }
+        }
+    }
+
+
+
+    /// Turns the wind field under the mouse
+    fn turn_field(world: &SceneWorld, angle: f32) {
+        if let Some(entity) = PhysicsTest::field_at(&world.specs, &world.mouse.position) {
+            if let Some(g) = world.specs.write_storage::<ForceGenerator>().get_mut(entity) {
+                g.kind = g.kind.turned(angle);
+            }
+        }
+    }
+
+
+
+    /**
+     * Replaces the fluid volumes. Water fills the lower half of the walls,
+     * air the upper half.
+     */
+    pub fn create_fluids(specsWorld: &mut specs::World, fluids: Fluids) {
+        let existing: Vec<specs::Entity> = {
+            let entities = specsWorld.entities();
+            let volumes = specsWorld.read_storage::<FluidVolume>();
+            (&entities, &volumes).join().map(|(e, _)| e).collect()
+        };
+
+        for entity in existing {
+            crate::world::destroy_entity(specsWorld, entity);
+        }
+
+        let wall_size = specsWorld.read_resource::<Tuning>().wall_size;
+        let half = nalgebra::Vector2::new(wall_size, wall_size / 2.0);
+
+        if fluids != Fluids::None {
+            let water = FluidVolume::new(nalgebra::Point2::new(0.0, wall_size / 2.0), half, WATER_DENSITY);
+            specsWorld.create_entity().with(water).build();
+        }
+
+        if fluids == Fluids::WaterAndAir {
+            let air = FluidVolume {
+                linear_drag: 0.1,
+                angular_drag: 0.1,
+                ..FluidVolume::new(nalgebra::Point2::new(0.0, - wall_size / 2.0), half, AIR_DENSITY)
+            };
+            specsWorld.create_entity().with(air).build();
+        }
+    }
+
+
+
+    /**
+     * Makes the ball or car the player controlled one, any part of a car
+     * picks its chassis. The others stop following the player input. Other
+     * entities are ignored.
+     */
+    pub fn activate(specsWorld: &specs::World, entity: specs::Entity) {
+        let entity = specsWorld.read_storage::<crate::system::Link>().get(entity).map_or(entity, |link| link.root);
+
+        let mut balls = specsWorld.write_storage::<Ball>();
+        let mut vehicles = specsWorld.write_storage::<Vehicle>();
+        if !balls.contains(entity) && !vehicles.contains(entity) {
+            return;
+        }
+
+        for (e, ball) in (&specsWorld.entities(), &mut balls).join() {
+            ball.active = e == entity;
+        }
+
+        for (e, vehicle) in (&specsWorld.entities(), &mut vehicles).join() {
+            vehicle.active = e == entity;
+        }
+    }
+
+
+
+    /// Passes control to the ball after the active one
+    pub fn next_ball(specsWorld: &specs::World) {
+        let balls: Vec<(specs::Entity, bool)> = {
+            let entities = specsWorld.entities();
+            let balls = specsWorld.read_storage::<Ball>();
+            (&entities, &balls).join().map(|(e, b)| (e, b.active)).collect()
+        };
+
+        let next = match balls.iter().position(|(_, active)| *active) {
+            Some(index) => balls.get((index + 1) % balls.len()),
+            None => balls.first(),
+        };
+
+        if let Some((entity, _)) = next {
+            PhysicsTest::activate(specsWorld, *entity);
+        }
+    }
+
+
+
+    /**
+     * Spawns any of the spawn tool shapes. Balls get the full set of ball
+     * components. Soft bodies follow the springs of the tuning and return
+     * the entity holding them, which has no body of its own. Cars return
+     * their chassis.
+     */
+    pub fn create_shape(specsWorld: &mut specs::World, shape: SpawnShape, material: &str, position: nalgebra::Point2<f32>) -> specs::Entity {
+        let desc = || shape.desc().expect("Spawn shape isn't a single body");
+
+        match shape {
+            SpawnShape::Ball => PhysicsTest::create_ball(specsWorld, material, position),
+            SpawnShape::Jelly => {
+                debug!("Create soft ring");
+                SoftBodyBuilder::ring(position, 30.0, 16)
+                    .material(material)
+                    .build(specsWorld)
+            },
+            SpawnShape::JellyGrid => {
+                debug!("Create soft grid");
+                SoftBodyBuilder::grid(position, 5, 4, 12.0)
+                    .material(material)
+                    .build(specsWorld)
+            },
+            SpawnShape::Crate => {
+                debug!("Create breakable crate");
+                BodyBuilder::new(specsWorld, desc())
+                    .position(position.x, position.y)
+                    .material(material)
+                    .render(RenderStyle::Fill, [0.7, 0.9, 1.0, 0.8])
+                    .with(Breakable::new(CRATE_STRENGTH))
+                    .build()
+            },
+            SpawnShape::Car => {
+                debug!("Create car");
+                VehicleBuilder::new(position)
+                    .material(material)
+                    .build(specsWorld)
+            },
+            SpawnShape::Sprite => {
+                debug!("Create sprite");
+                let sprite = SpriteBuilder::new(SPRITE_IMAGE)
+                    .position(position.x, position.y)
+                    .material(material)
+                    .build(specsWorld);
+
+                match sprite {
+                    Ok(entity) => entity,
+                    Err(e) => {
+                        warn!("{}, spawning a box instead", e);
+                        BodyBuilder::new(specsWorld, ShapeDesc::Cuboid { half_extents: [28.0, 28.0] })
+                            .position(position.x, position.y)
+                            .material(material)
+                            .build()
+                    },
+                }
+            },
+            SpawnShape::Ragdoll => {
+                debug!("Create ragdoll");
+                RagdollBuilder::new(position)
+                    .material(material)
+                    .build(specsWorld)
+            },
+            SpawnShape::Character => {
+                debug!("Create character");
+                BodyBuilder::new(specsWorld, desc())
+                    .position(position.x, position.y)
+                    .status(Status::Kinematic)
+                    .material(material)
+                    .render(RenderStyle::Fill, [1.0, 0.8, 0.3, 1.0])
+                    .with(CharacterController::default())
+                    .build()
+            },
+            SpawnShape::Platform => {
+                debug!("Create one-way platform");
+                BodyBuilder::new(specsWorld, desc())
+                    .position(position.x, position.y)
+                    .status(Status::Static)
+                    .material(material)
+                    .render(RenderStyle::Fill, [0.6, 0.6, 0.6, 0.6])
+                    .one_way(OneWay::default())
+                    .build()
+            },
+            SpawnShape::Conveyor => {
+                debug!("Create conveyor");
+                BodyBuilder::new(specsWorld, desc())
+                    .position(position.x, position.y)
+                    .status(Status::Static)
+                    .material(material)
+                    .conveyor(Conveyor { speed: CONVEYOR_SPEED })
+                    .render(RenderStyle::Fill, [0.4, 0.4, 0.5, 1.0])
+                    .build()
+            },
+            _ => {
+                debug!("Create {:?}", shape);
+                BodyBuilder::new(specsWorld, desc())
+                    .position(position.x, position.y)
+                    .material(material)
+                    .build()
+            }
+        }
+    }
+
+
+
+    pub fn create_walls(specsWorld: &mut specs::World) -> specs::Entity {
+        let wall_size = specsWorld.read_resource::<Tuning>().wall_size;
+
+        let shape = ShapeDesc::Polyline {
+            points: vec![
+                [- wall_size,   wall_size],
+                [- wall_size, - wall_size],
+                [  wall_size, - wall_size],
+                [  wall_size,   wall_size],
+            ],
+        };
+
+        BodyBuilder::new(specsWorld, shape)
+            .status(Status::Static)
+            .render(RenderStyle::Line(2.0), WHITE)
+            .with(Boundary)
+            .build()
+    }
+
+
+
+    /// The ground picked in the tuning, flat ground is an invisible segment under the walls
+    pub fn create_ground(specsWorld: &mut specs::World) -> specs::Entity {
+        let (wall_size, ground) = {
+            let tuning = specsWorld.read_resource::<Tuning>();
+            (tuning.wall_size, tuning.ground.clone())
+        };
+
+        if let Some(shape) = ground.shape(wall_size * 2.0) {
+            debug!("Create {} ground", ground.name());
+            let built = BodyBuilder::new(specsWorld, shape)
+                .position(0.0, wall_size)
+                .status(Status::Static)
+                .render(RenderStyle::Fill, [0.5, 0.8, 0.4, 1.0])
+                .with(Boundary)
+                .try_build();
+
+            match built {
+                Some(entity) => return entity,
+                None => warn!("The {} ground can't be built, using flat ground", ground.name()),
+            }
+        }
+
+        let shape = ShapeDesc::Segment {
+            a: [- wall_size, wall_size],
+            b: [  wall_size, wall_size],
+        };
+
+        BodyBuilder::new(specsWorld, shape)
+            .status(Status::Static)
+            .render(RenderStyle::Hidden, WHITE)
+            .with(Boundary)
+            .build()
+    }
+
+
+
+    /**
+     * Removes the walls and ground and builds them again at the current
+     * tuning wall size.
+     */
+    pub fn rebuild_boundaries(specsWorld: &mut specs::World) {
+        let boundaries: Vec<specs::Entity> = {
+            let entities = specsWorld.entities();
+            let boundary = specsWorld.read_storage::<Boundary>();
+            (&entities, &boundary).join().map(|(e, _)| e).collect()
+        };
+
+        for entity in boundaries {
+            crate::world::destroy_entity(specsWorld, entity);
+        }
+
+        PhysicsTest::create_walls(specsWorld);
+        PhysicsTest::create_ground(specsWorld);
+    }
 
-        let shape = ncollide2d::shape::ShapeHandle::new(
-            ncollide2d::shape::Segment::new(
-                nalgebra::Point2::new(- WALL_SIZE, WALL_SIZE),
-                nalgebra::Point2::new(  WALL_SIZE, WALL_SIZE),
-            )
-        );
-
-        let mut physics = specsWorld.write_resource::<crate::world::PhysicsWorld>();
-        physics.add_collider(
-            0.0,
-            shape,
-            nphysics2d::object::BodyHandle::ground(),
-            nalgebra::one(),
-            nphysics2d::object::Material::new(BALL_RESTITUTION, BALL_FRICTION),
-        );
+
+
+    /// Applies the active tool to the current mouse state
+    fn use_tool(&mut self, world: &mut SceneWorld) {
+        let mouse = world.mouse.clone();
+
+        if mouse.right_pressed {
+            if let Some(entity) = crate::world::entity_at(&world.specs, &mouse.position) {
+                PhysicsTest::activate(&world.specs, entity);
+            }
+        }
+
+        match self.tool {
+            Tool::Spawn => {
+                if mouse.left_pressed {
+                    // soft bodies and the multibody links of cars and ragdolls
+                    // have no description to respawn them from, so
+                    // record_spawn leaves them out and they can't be undone
+                    let entity = PhysicsTest::create_shape(&mut world.specs, self.shape, &self.material, mouse.position);
+                    history::record_spawn(&world.specs, entity);
+                }
+            },
+
+            Tool::DrawShape => {
+                if mouse.left {
+                    self.sketch.add([mouse.position.x, mouse.position.y]);
+                }
+
+                if mouse.left_released && !self.sketch.is_empty() {
+                    match self.sketch.finish() {
+                        Some((shape, center)) => {
+                            debug!("Create drawn shape");
+                            let entity = world.body(shape)
+                                .position(center[0], center[1])
+                                .material(&self.material)
+                                .build();
+                            history::record_spawn(&world.specs, entity);
+                        },
+
+                        None => warn!("Drawn shape was too small or crossed itself"),
+                    }
+                }
+            },
+
+            Tool::Select => {
+                if mouse.left_pressed {
+                    self.inspector.select(&world.specs, &mouse.position);
+                }
+            },
+
+            Tool::Slingshot => {
+                if mouse.left_pressed {
+                    self.slingshot.grab(&world.specs, &mouse.position);
+                }
+
+                if mouse.left {
+                    self.slingshot.pull = mouse.position;
+                }
+
+                if mouse.left_released {
+                    self.slingshot.release(&world.specs);
+                }
+            },
+
+            Tool::Explode => {
+                if mouse.left_pressed {
+                    let hits = self.explosion.apply(&world.specs, &mouse.position);
+                    debug!("Explosion hit {} bodies", hits);
+                }
+            },
+
+            Tool::Field => {
+                if mouse.left_pressed {
+                    PhysicsTest::create_field(&mut world.specs, self.force, mouse.position);
+                }
+            },
+
+            Tool::Rope => {
+                if mouse.left_pressed {
+                    self.rope.start = Some(mouse.position);
+                }
+
+                if mouse.left_released && self.rope.finish(&mut world.specs, &mouse.position).is_none() {
+                    warn!("Rope was too short");
+                }
+            },
+
+            Tool::Drag => {
+                if mouse.left_pressed {
+                    self.drag.grab(&world.specs, &mouse.position);
+                }
+
+                if mouse.left {
+                    self.drag.pull(&world.specs, &mouse.position);
+                }
+
+                if mouse.left_released {
+                    self.drag.release();
+                }
+            },
+        }
+    }
+
+
+
+    /// Adds the bodies from a scene file, a Tiled map or an SVG drawing to the world
+    pub fn load_scene(specsWorld: &mut specs::World, path: &str) {
+        let scene = if path.ends_with(".tmx") {
+            crate::tmx::load(path)
+        } else if path.ends_with(".svg") {
+            crate::svg::load(path, &specsWorld.read_resource::<crate::material::MaterialLibrary>(), SVG_SCALE)
+        } else {
+            SceneFile::load(path)
+        };
+
+        match scene {
+            Ok(scene) => {
+                let spawns = scene.spawn(specsWorld).into_iter()
+                    .filter_map(|entity| {
+                        let desc = BodyDesc::describe(specsWorld, entity)?;
+                        Some(Command::Spawn { uid: history::uid(specsWorld, entity), desc: desc })
+                    })
+                    .collect();
+                history::record(specsWorld, Command::Batch(spawns));
+            },
+
+            Err(e) => warn!("{}", e),
+        }
     }
 }
 
@@ -231,7 +760,29 @@ impl Scene<SceneWorld, InputEvent> for PhysicsTest {
      * Then checks for an exit case.
      */
     fn update(&mut self, world: &mut SceneWorld) -> SceneSwitch<SceneWorld, InputEvent> {
+        {
+            let mut player = world.specs.write_resource::<crate::player::PlayerInput>();
+            player.horizontal = world.input.get_axis_raw(Axis::Left) + world.input.get_axis_raw(Axis::Right);
+            player.vertical = world.input.get_axis_raw(Axis::Forward) + world.input.get_axis_raw(Axis::Backward);
+        }
+
         self.dispatcher.dispatch(&mut world.specs.res);
+        world.specs.write_resource::<crate::player::PlayerInput>().jump = false;
+        world.specs.maintain();
+        crate::fracture::shatter(&mut world.specs);
+        self.use_tool(world);
+        self.inspector.validate(&world.specs);
+
+        let wallsDirty = world.specs.read_resource::<Tuning>().walls_dirty;
+        if wallsDirty {
+            world.specs.write_resource::<Tuning>().walls_dirty = false;
+            PhysicsTest::rebuild_boundaries(&mut world.specs);
+            PhysicsTest::create_fluids(&mut world.specs, self.fluids);
+        }
+
+        if let Some(scene) = self.push.take() {
+            return SceneSwitch::Push(scene);
+        }
 
         // Cut, exit stage right
         if self.done {
@@ -246,49 +797,115 @@ impl Scene<SceneWorld, InputEvent> for PhysicsTest {
      * The draw mehtod should show all available objects.
      */
     fn draw(self: &mut Self, sceneWorld: &mut SceneWorld, context: &mut ggez::Context) -> ggez::GameResult<()> {
-        let camera_focus_x: f32 = 0.0;
-        let camera_focus_y: f32 = 0.0;
-
-        let screen_rect = ggez::graphics::Rect {
-            x: camera_focus_x - CAMERA_WIDTH / 2.0,
-            y: camera_focus_y - CAMERA_HEIGHT / 2.0,
-            w: CAMERA_WIDTH,
-            h: CAMERA_HEIGHT,
-        };
+        let screen_rect = sceneWorld.screen_rect();
 
         ggez::graphics::set_screen_coordinates(context, screen_rect)?;
 
-        if (self.drawBall) {
-            self.drawBall = false;
-            PhysicsTest::create_ball(context, &mut sceneWorld.specs);
+        crate::render::build_meshes(context, &mut sceneWorld.specs);
+        crate::render::draw_fluids(context, &sceneWorld.specs)?;
+        crate::softbody::draw_skins(context, &sceneWorld.specs)?;
+        crate::render::draw_bodies(context, &sceneWorld.specs)?;
+        crate::sprite::build_sprites(context, &mut sceneWorld.specs);
+        crate::sprite::draw_sprites(context, &sceneWorld.specs)?;
+        crate::rope::draw_ropes(context, &sceneWorld.specs)?;
+        crate::fracture::draw_joints(context, &sceneWorld.specs)?;
+
+        if self.sketch.points.len() > 1 {
+            let points: Vec<ggez::graphics::Point2> = self.sketch.points.iter()
+                .map(|p| ggez::graphics::Point2::new(p[0], p[1]))
+                .collect();
+            ggez::graphics::line(context, &points, 2.0)?;
         }
 
-        let mesh = sceneWorld.specs.read_storage::<crate::system::Mesh>();
-        let collider = sceneWorld.specs.read_storage::<crate::system::Collider>();
-        let mut physics_world = sceneWorld.specs.read_resource::<crate::world::PhysicsWorld>();
-        let mut ncollide_world = physics_world.collision_world();
+        if let Some(start) = self.rope.start {
+            let end = sceneWorld.mouse.position;
+            let points = [ggez::graphics::Point2::new(start.x, start.y), ggez::graphics::Point2::new(end.x, end.y)];
+            ggez::graphics::line(context, &points, 1.0)?;
+        }
 
-        // draw a list of objects that both are colliders and have meshes
-        for (c, m) in (&collider, &mesh).join() {
-            let collision_object = ncollide_world
-                .collision_object(c.object_handle)
-                .expect("Invalid collision object; was it removed from ncollide but not specs?");
+        if let Some(grab) = self.drag.handle(&sceneWorld.specs) {
+            let end = sceneWorld.mouse.position;
+            let points = [ggez::graphics::Point2::new(grab.x, grab.y), ggez::graphics::Point2::new(end.x, end.y)];
+            ggez::graphics::line(context, &points, 1.0)?;
+        }
 
-            let isometry = collision_object.position();
-            let point = ggez::nalgebra::Point2::new(isometry.translation.vector.x, isometry.translation.vector.y);
-            let angle = isometry.rotation.angle();
+        if let Some((origin, velocity)) = self.slingshot.aim(&sceneWorld.specs) {
+            let gravity = sceneWorld.specs.read_resource::<Tuning>().gravity_vector();
+            let arc: Vec<ggez::graphics::Point2> = crate::tool::trajectory(&origin, &velocity, &gravity).iter()
+                .map(|p| ggez::graphics::Point2::new(p.x, p.y))
+                .collect();
+            let band = [ggez::graphics::Point2::new(origin.x, origin.y), ggez::graphics::Point2::new(self.slingshot.pull.x, self.slingshot.pull.y)];
+
+            ggez::graphics::set_color(context, ggez::graphics::Color::new(1.0, 1.0, 0.0, 0.6))?;
+            ggez::graphics::line(context, &arc, 1.0)?;
+            ggez::graphics::line(context, &band, 2.0)?;
+            ggez::graphics::set_color(context, ggez::graphics::WHITE)?;
+        }
 
-            let drawParam = ggez::graphics::DrawParam {
-                dest: point,
-                rotation: angle,
-                color: Some(ggez::graphics::WHITE),
-                ..ggez::graphics::DrawParam::default()
-            };
+        if self.debug || self.tool == Tool::Field {
+            crate::render::draw_fields(context, &sceneWorld.specs)?;
+        }
+
+        if self.debug {
+            crate::render::draw_magnets(context, &sceneWorld.specs)?;
+        }
+
+        if self.tool == Tool::Explode {
+            let mouse = ggez::graphics::Point2::new(sceneWorld.mouse.position.x, sceneWorld.mouse.position.y);
+            ggez::graphics::set_color(context, ggez::graphics::Color::new(1.0, 0.5, 0.0, 0.6))?;
+            ggez::graphics::circle(context, ggez::graphics::DrawMode::Line(1.0), mouse, self.explosion.radius, 0.5)?;
+            ggez::graphics::set_color(context, ggez::graphics::WHITE)?;
+        }
+
+        if let Some(entity) = self.inspector.selected {
+            crate::render::draw_highlight(context, &sceneWorld.specs, entity)?;
+        }
+
+        if let Some(panel) = self.inspector.panel(&sceneWorld.specs) {
+            panel.draw(context, ggez::graphics::Point2::new(CAMERA_WIDTH - 290.0, 10.0))?;
+        }
+
+        let player = {
+            let physics = sceneWorld.specs.read_resource::<crate::world::PhysicsWorld>();
+            match sceneWorld.specs.read_resource::<ActiveBall>().object_handle {
+                Some(handle) if crate::player::grounded(&physics, handle) => "grounded",
+                Some(_) => "airborne",
+                None => "none",
+            }
+        };
+
+        let mut hud = crate::ui::Panel::new("Spawn (Z)")
+            .line(format!("tool: {:?} (Q)", self.tool))
+            .line(format!("shape: {:?} (X)", self.shape))
+            .line(format!("material: {} (M)", self.material))
+            .line(format!("player ball: {} (Tab)", player))
+            .line(format!("fluids: {:?} (H)", self.fluids))
+            .line(format!("ground: {} (U)", sceneWorld.specs.read_resource::<Tuning>().ground.name()));
+
+        if self.tool == Tool::Explode {
+            hud = hud
+                .line(format!("strength: {:.0} ([ ] I)", self.explosion.strength))
+                .line(format!("falloff: {:?} (F) sight: {} (O)", self.explosion.falloff, self.explosion.line_of_sight));
+        }
+
+        if self.tool == Tool::Field {
+            let strength = PhysicsTest::field_at(&sceneWorld.specs, &sceneWorld.mouse.position)
+                .and_then(|e| sceneWorld.specs.read_storage::<ForceGenerator>().get(e).map(|g| g.strength));
 
-            ggez::graphics::draw_ex(context, &m.mesh, drawParam);
+            hud = hud
+                .line(format!("force: {:?} (V, R turns wind)", self.force))
+                .line(match strength {
+                    Some(strength) => format!("strength: {:.1} ([ ] I, Delete)", strength),
+                    None => "hover a field to edit it".to_owned(),
+                });
         }
 
+        if self.tool == Tool::Rope {
+            hud = hud.line(format!("breakable: {} (O)", self.rope.breakable));
+        }
 
+        let height = hud.height();
+        hud.draw(context, ggez::graphics::Point2::new(10.0, CAMERA_HEIGHT - height - 12.0))?;
 
         Ok(())
     }
@@ -300,7 +917,7 @@ impl Scene<SceneWorld, InputEvent> for PhysicsTest {
      */
     fn input(&mut self, sceneWorld: &mut SceneWorld, _ev: InputEvent, _started: bool) {
 
-        if sceneWorld.input.get_button_pressed(Button::Shoot) {
+        if sceneWorld.input.get_button_pressed(Button::PushAll) {
             let mut physics = sceneWorld.specs.write_resource::<crate::world::PhysicsWorld>();
             let mut collider = physics.collision_world_mut();
 
@@ -325,22 +942,148 @@ impl Scene<SceneWorld, InputEvent> for PhysicsTest {
         }
 
         if sceneWorld.input.get_button_pressed(Button::Gravity) {
+            let mut tuning = sceneWorld.specs.write_resource::<Tuning>();
             let mut physics = sceneWorld.specs.write_resource::<crate::world::PhysicsWorld>();
-            match self.gravity {
-                false => {
-                    physics.set_gravity(nalgebra::Vector2::y() * 98.0);
-                    self.gravity = true;
-                },
-
-                true => {
-                    physics.set_gravity(nalgebra::Vector2::y() * 0.0);
-                    self.gravity = false;
-                }
-            }
+            let before = tuning.clone();
+            tuning.gravity_enabled = !tuning.gravity_enabled;
+            physics.set_gravity(tuning.gravity_vector());
+            history::record(&sceneWorld.specs, Command::Settings { before: before, after: tuning.clone() });
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::Terrain) {
+            let mut tuning = sceneWorld.specs.write_resource::<Tuning>();
+            let before = tuning.clone();
+            tuning.ground = tuning.ground.next();
+            tuning.walls_dirty = true;
+            history::record(&sceneWorld.specs, Command::Settings { before: before, after: tuning.clone() });
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::NextMaterial) {
+            let library = sceneWorld.specs.read_resource::<MaterialLibrary>();
+            self.material = library.next(&self.material).to_owned();
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::Tune) {
+            self.push = Some(Box::new(crate::scene::tuning::TuningScene::new()));
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::Editor) {
+            self.sketch = Sketch::default();
+            self.push = Some(Box::new(crate::scene::editor::EditorScene::new()));
         }
 
         if sceneWorld.input.get_button_pressed(Button::Rotate) {
-            self.drawBall = true;
+            let entity = PhysicsTest::create_shape(&mut sceneWorld.specs, self.shape, &self.material, nalgebra::Point2::origin());
+            history::record_spawn(&sceneWorld.specs, entity);
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::Undo) {
+            history::undo(&mut sceneWorld.specs);
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::Redo) {
+            history::redo(&mut sceneWorld.specs);
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::Jump) {
+            sceneWorld.specs.write_resource::<crate::player::PlayerInput>().jump = true;
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::NextBall) {
+            PhysicsTest::next_ball(&sceneWorld.specs);
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::PreviousField) {
+            self.inspector.previous_field();
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::NextField) {
+            self.inspector.next_field();
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::Increase) {
+            self.inspector.adjust(&mut sceneWorld.specs, 1.0);
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::Decrease) {
+            self.inspector.adjust(&mut sceneWorld.specs, -1.0);
+        }
+
+        // the strength keys edit the force field under the mouse with the field tool
+        let field = self.tool == Tool::Field;
+
+        if sceneWorld.input.get_button_pressed(Button::Implode) {
+            match field {
+                true => PhysicsTest::scale_field(sceneWorld, -1.0),
+                false => self.explosion.strength = - self.explosion.strength,
+            }
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::Weaker) {
+            match field {
+                true => PhysicsTest::scale_field(sceneWorld, 1.0 / STRENGTH_STEP),
+                false => self.explosion.strength /= STRENGTH_STEP,
+            }
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::Stronger) {
+            match field {
+                true => PhysicsTest::scale_field(sceneWorld, STRENGTH_STEP),
+                false => self.explosion.strength *= STRENGTH_STEP,
+            }
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::Delete) && field {
+            if let Some(entity) = PhysicsTest::field_at(&sceneWorld.specs, &sceneWorld.mouse.position) {
+                crate::world::destroy_entity(&mut sceneWorld.specs, entity);
+            }
+        }
+
+        // turns the hovered wind and the direction of the next one placed
+        if sceneWorld.input.get_button_pressed(Button::Turn) && field {
+            PhysicsTest::turn_field(sceneWorld, WIND_TURN);
+            self.force = self.force.turned(WIND_TURN);
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::NextForce) {
+            self.force = self.force.next();
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::Fluid) {
+            self.fluids = self.fluids.next();
+            PhysicsTest::create_fluids(&mut sceneWorld.specs, self.fluids);
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::Debug) {
+            self.debug = !self.debug;
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::NextFalloff) {
+            self.explosion.falloff = self.explosion.falloff.next();
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::LineOfSight) {
+            match self.tool {
+                Tool::Rope => self.rope.breakable = !self.rope.breakable,
+                _ => self.explosion.line_of_sight = !self.explosion.line_of_sight,
+            }
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::NextTool) {
+            self.tool = self.tool.next();
+            self.sketch = Sketch::default();
+            self.slingshot = Slingshot::default();
+            self.rope.start = None;
+            self.drag.release();
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::NextShape) {
+            self.shape = self.shape.next();
+        }
+
+        if sceneWorld.input.get_button_pressed(Button::LoadScene) {
+            PhysicsTest::load_scene(&mut sceneWorld.specs, SCENE_FILES[self.scene]);
+            self.scene = (self.scene + 1) % SCENE_FILES.len();
         }
 
         if sceneWorld.input.get_button_pressed(Button::Quit) {
@@ -0,0 +1,128 @@
+use ggez::graphics;
+use ggez_goodies::input::InputEffect;
+use ggez_goodies::scene::{Scene, SceneSwitch};
+
+use log::*;
+
+use crate::game::{Axis, Button, InputEvent};
+use crate::tuning::{Field, Tuning, PRESETS};
+use crate::world::SceneWorld;
+
+/**
+ * Settings panel drawn over the physics test. The simulation underneath is
+ * paused while it is open, every change is pushed to the physics world
+ * straight away and the values are saved when the panel closes.
+ */
+pub struct TuningScene {
+    cursor: usize,
+    before: Option<Tuning>,
+    done: bool,
+}
+
+impl TuningScene {
+    pub fn new () -> TuningScene {
+        debug!("Create TuningScene");
+
+        TuningScene {
+            cursor: 0,
+            before: None,
+            done: false,
+        }
+    }
+
+    fn selected(&self) -> Field {
+        Field::ALL[self.cursor]
+    }
+
+    fn adjust(&self, world: &mut SceneWorld, direction: f32) {
+        let field = self.selected();
+        let mut tuning = world.specs.write_resource::<Tuning>();
+        let value = tuning.get(field) + field.step() * direction;
+        tuning.set(field, value);
+        tuning.apply(&world.specs);
+    }
+
+    fn preset(&self, world: &mut SceneWorld, index: usize) {
+        if let Some(preset) = PRESETS.get(index) {
+            info!("Applying preset {}", preset.name);
+            let mut tuning = world.specs.write_resource::<Tuning>();
+            tuning.set(preset.field, preset.value);
+            tuning.apply(&world.specs);
+        }
+    }
+}
+
+impl Scene<SceneWorld, InputEvent> for TuningScene {
+    fn update(&mut self, world: &mut SceneWorld) -> SceneSwitch<SceneWorld, InputEvent> {
+        if self.before.is_none() {
+            self.before = Some(world.specs.read_resource::<Tuning>().clone());
+        }
+
+        if self.done {
+            let after = world.specs.read_resource::<Tuning>().clone();
+            after.save();
+
+            // the whole session is undone in one step
+            if let Some(before) = self.before.take() {
+                if !before.same_values(&after) {
+                    crate::history::record(&world.specs, crate::history::Command::Settings { before: before, after: after });
+                }
+            }
+
+            SceneSwitch::Pop
+        } else {
+            SceneSwitch::None
+        }
+    }
+
+    fn draw(&mut self, world: &mut SceneWorld, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        let tuning = world.specs.read_resource::<Tuning>();
+
+        let mut panel = crate::ui::Panel::new("Tuning (T to close)");
+        for field in Field::ALL.iter() {
+            panel = panel.line(format!("{:<12} {:>8.2}", field.label(), tuning.get(*field)));
+        }
+
+        panel = panel
+            .cursor(self.cursor)
+            .line("")
+            .line("Up/Down select, Left/Right adjust");
+
+        for (i, preset) in PRESETS.iter().enumerate() {
+            panel = panel.line(format!("{}: {} ({} {})", i + 1, preset.name, preset.field.label(), preset.value));
+        }
+
+        panel.draw(ctx, graphics::Point2::new(10.0, 10.0))
+    }
+
+    fn name(&self) -> &str {
+        "TuningScene"
+    }
+
+    fn draw_previous(&self) -> bool {
+        true
+    }
+
+    fn input(&mut self, world: &mut SceneWorld, ev: InputEvent, started: bool) {
+        if !started {
+            return;
+        }
+
+        match ev {
+            InputEffect::Axis(Axis::Forward, _) => {
+                self.cursor = (self.cursor + Field::ALL.len() - 1) % Field::ALL.len();
+            },
+
+            InputEffect::Axis(Axis::Backward, _) => {
+                self.cursor = (self.cursor + 1) % Field::ALL.len();
+            },
+
+            InputEffect::Button(Button::Increase) => self.adjust(world, 1.0),
+            InputEffect::Button(Button::Decrease) => self.adjust(world, -1.0),
+            InputEffect::Button(Button::Preset(index)) => self.preset(world, index),
+            InputEffect::Button(Button::Tune) | InputEffect::Button(Button::Quit) => self.done = true,
+
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,439 @@
+/**
+ *
+ * Scene files.
+ *
+ * A scene file is a RON list of body descriptions. Materials are referred
+ * to by their name in the `MaterialLibrary`. Everything but the shape is
+ * optional:
+ *
+ * ```ignore
+ * (
+ *     bodies: [
+ *         (shape: Ball(radius: 10.0), position: (0.0, -100.0), material: "rubber"),
+ *         (shape: Segment(a: (-50.0, 0.0), b: (50.0, 0.0)), status: Static),
+ *         (shape: Ball(radius: 8.0), charge: Some((charge: -1.0))),
+ *         (shape: Cuboid(half_extents: (15.0, 15.0)), breakable: Some((threshold: 20000.0))),
+ *         (shape: Cuboid(half_extents: (40.0, 3.0)), status: Static, one_way: Some(()), conveyor: Some((speed: 50.0))),
+ *     ],
+ *     vehicles: [
+ *         (position: (-100.0, 100.0)),
+ *     ],
+ *     images: [
+ *         (image: "images/boulder.png", position: (50.0, -100.0), material: "rock"),
+ *     ],
+ *     joints: [
+ *         (a: Some(0), point: (0.0, -110.0), threshold: 30000.0),
+ *     ],
+ * )
+ * ```
+ *
+ */
+
+use std::fs::File;
+use std::io::{Read, Write};
+
+use specs;
+use specs::prelude::*;
+
+use log::*;
+
+use crate::builder::{BodyBuilder, Status};
+use crate::character::CharacterController;
+use crate::fracture::{Breakable, BreakableJoint};
+use crate::material::{Density, MaterialRef, DEFAULT_MATERIAL};
+use crate::modifier::{Conveyor, OneWay};
+use crate::pairwise::{Charge, Magnet};
+use crate::render::Render;
+use crate::scene::physicstest::{Ball, PhysicsTest};
+use crate::shape::{Shape, ShapeDesc};
+use crate::sprite::{Sprite, SpriteBuilder, SpriteImage};
+use crate::system::{Collider, Gravity, Mesh, RigidBody};
+use crate::vehicle::VehicleBuilder;
+use crate::world::PhysicsWorld;
+
+fn default_material() -> String {
+    DEFAULT_MATERIAL.to_owned()
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BodyDesc {
+    pub shape: ShapeDesc,
+
+    #[serde(default)]
+    pub position: (f32, f32),
+
+    #[serde(default)]
+    pub rotation: f32,
+
+    #[serde(default)]
+    pub velocity: (f32, f32),
+
+    #[serde(default)]
+    pub status: Status,
+
+    #[serde(default = "default_material")]
+    pub material: String,
+
+    #[serde(default)]
+    pub density: Option<f32>,
+
+    #[serde(default)]
+    pub render: Render,
+
+    #[serde(default)]
+    pub charge: Option<Charge>,
+
+    #[serde(default)]
+    pub magnet: Option<Magnet>,
+
+    /// Strength of the n-body gravity pulling it towards other bodies
+    #[serde(default)]
+    pub gravity: Option<f32>,
+
+    /// Shatters when hit harder than its threshold
+    #[serde(default)]
+    pub breakable: Option<Breakable>,
+
+    /// Only holds bodies coming from the side of its normal
+    #[serde(default)]
+    pub one_way: Option<OneWay>,
+
+    #[serde(default)]
+    pub conveyor: Option<Conveyor>,
+
+    /// Only detects what overlaps it instead of colliding
+    #[serde(default)]
+    pub sensor: bool,
+
+    /// Drawn with an image instead of its shape
+    #[serde(default)]
+    pub sprite: Option<Sprite>,
+
+    /// One of the balls the player can take control of
+    #[serde(default)]
+    pub ball: bool,
+
+    /// Walked around with the player keys, see `CharacterController`
+    #[serde(default)]
+    pub character: bool,
+}
+
+impl BodyDesc {
+    pub fn new(shape: ShapeDesc) -> BodyDesc {
+        BodyDesc {
+            shape: shape,
+            position: (0.0, 0.0),
+            rotation: 0.0,
+            velocity: (0.0, 0.0),
+            status: Status::default(),
+            material: default_material(),
+            density: None,
+            render: Render::default(),
+            charge: None,
+            magnet: None,
+            gravity: None,
+            breakable: None,
+            one_way: None,
+            conveyor: None,
+            sensor: false,
+            sprite: None,
+            ball: false,
+            character: false,
+        }
+    }
+
+    /// A builder set up from this description, ready for extra components
+    pub fn builder<'a>(&self, specsWorld: &'a mut specs::World) -> BodyBuilder<'a> {
+        let mut builder = BodyBuilder::new(specsWorld, self.shape.clone())
+            .position(self.position.0, self.position.1)
+            .rotation(self.rotation)
+            .velocity(nalgebra::Vector2::new(self.velocity.0, self.velocity.1), 0.0)
+            .status(self.status)
+            .material(&self.material)
+            .sensor(self.sensor)
+            .render(self.render.style, self.render.color);
+
+        // before the gravity below, which replaces the one every ball gets
+        if self.ball {
+            builder = PhysicsTest::ball_components(builder);
+        }
+
+        if self.character {
+            builder = builder.with(CharacterController::default());
+        }
+
+        if let Some(density) = self.density {
+            builder = builder.density(density);
+        }
+
+        if let Some(charge) = self.charge {
+            builder = builder.with(charge);
+        }
+
+        if let Some(magnet) = self.magnet {
+            builder = builder.with(magnet);
+        }
+
+        if let Some(force) = self.gravity {
+            builder = builder.with(Gravity { force: Some(force) });
+        }
+
+        if let Some(breakable) = self.breakable {
+            builder = builder.with(breakable);
+        }
+
+        if let Some(oneWay) = self.one_way {
+            builder = builder.one_way(oneWay);
+        }
+
+        if let Some(conveyor) = self.conveyor {
+            builder = builder.conveyor(conveyor);
+        }
+
+        if let Some(sprite) = self.sprite.clone() {
+            builder = builder.with(sprite);
+        }
+
+        builder
+    }
+
+    /// Adds the body to the world. Shapes that can't be built are skipped
+    /// with a warning.
+    pub fn spawn(&self, specsWorld: &mut specs::World) -> Option<specs::Entity> {
+        let entity = self.builder(specsWorld).try_build();
+        if entity.is_none() {
+            warn!("Skipping body at {:?}, its {} shape can't be built", self.position, self.shape.name());
+        }
+
+        entity
+    }
+
+    pub fn pose(&self) -> nalgebra::Isometry2<f32> {
+        nalgebra::Isometry2::new(nalgebra::Vector2::new(self.position.0, self.position.1), self.rotation)
+    }
+
+    /// Scales the shape along with the sprite drawn over it
+    pub fn scale(&mut self, factor: f32) {
+        self.shape = self.shape.scaled(factor);
+        if let Some(sprite) = self.sprite.as_mut() {
+            sprite.scale *= factor;
+        }
+    }
+
+    /**
+     * Describes an existing body from its components and its current state
+     * in the physics world. Returns `None` for entities that weren't built
+     * from a shape description and for multibody links, like the parts of
+     * cars and ragdolls, which only exist along with their whole multibody.
+     */
+    pub fn describe(specsWorld: &specs::World, entity: specs::Entity) -> Option<BodyDesc> {
+        let physics = specsWorld.read_resource::<PhysicsWorld>();
+        let shapes = specsWorld.read_storage::<Shape>();
+        let rigidBodies = specsWorld.read_storage::<RigidBody>();
+        let materials = specsWorld.read_storage::<MaterialRef>();
+        let renders = specsWorld.read_storage::<Render>();
+
+        let shape = shapes.get(entity)?;
+        let rigidBody = physics.rigid_body(rigidBodies.get(entity)?.object_handle)?;
+        let pose = rigidBody.position();
+        let velocity = rigidBody.velocity();
+
+        let sensor = specsWorld.read_storage::<Collider>().get(entity)
+            .and_then(|c| physics.collision_world().collision_object(c.object_handle))
+            .map_or(false, |object| match object.query_type() {
+                ncollide2d::world::GeometricQueryType::Proximity(_) => true,
+                _ => false,
+            });
+
+        Some(BodyDesc {
+            shape: shape.desc.clone(),
+            position: (pose.translation.vector.x, pose.translation.vector.y),
+            rotation: pose.rotation.angle(),
+            velocity: (velocity.linear.x, velocity.linear.y),
+            status: rigidBody.status().into(),
+            material: materials.get(entity).map(|m| m.name.clone()).unwrap_or_else(default_material),
+            density: specsWorld.read_storage::<Density>().get(entity).map(|d| d.value),
+            render: renders.get(entity).cloned().unwrap_or_default(),
+            charge: specsWorld.read_storage::<Charge>().get(entity).cloned(),
+            magnet: specsWorld.read_storage::<Magnet>().get(entity).cloned(),
+            gravity: specsWorld.read_storage::<Gravity>().get(entity).and_then(|g| g.force),
+            breakable: specsWorld.read_storage::<Breakable>().get(entity).cloned(),
+            one_way: specsWorld.read_storage::<OneWay>().get(entity).cloned(),
+            conveyor: specsWorld.read_storage::<Conveyor>().get(entity).cloned(),
+            sensor: sensor,
+            sprite: specsWorld.read_storage::<Sprite>().get(entity).cloned(),
+            ball: specsWorld.read_storage::<Ball>().contains(entity),
+            character: specsWorld.read_storage::<CharacterController>().contains(entity),
+        })
+    }
+
+    /**
+     * Replaces the body of an existing entity with one built from this
+     * description. Components that aren't part of the description are kept.
+     */
+    pub fn rebuild(&self, specsWorld: &mut specs::World, entity: specs::Entity) {
+        if self.shape.handle().is_none() {
+            warn!("Keeping the body, its new {} shape can't be built", self.shape.name());
+            return;
+        }
+
+        {
+            let mut physics = specsWorld.write_resource::<PhysicsWorld>();
+            let mut rigidBodies = specsWorld.write_storage::<RigidBody>();
+            let mut colliders = specsWorld.write_storage::<Collider>();
+
+            if let Some(r) = rigidBodies.remove(entity) {
+                physics.remove_bodies(&[r.object_handle]);
+            } else if let Some(c) = colliders.get(entity) {
+                physics.remove_colliders(&[c.object_handle]);
+            }
+
+            colliders.remove(entity);
+            specsWorld.write_storage::<Mesh>().remove(entity);
+            specsWorld.write_storage::<SpriteImage>().remove(entity);
+        }
+
+        self.builder(specsWorld).reuse(entity).build();
+    }
+}
+
+
+
+/// A vehicle prefab, see `VehicleBuilder`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VehicleDesc {
+    pub position: (f32, f32),
+
+    #[serde(default)]
+    pub torque: Option<f32>,
+}
+
+
+
+/// A breakable joint pinning bodies of the scene together, see `BreakableJoint`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JointDesc {
+    /// Index of a body in the scene's bodies, `None` pins to the world
+    #[serde(default)]
+    pub a: Option<usize>,
+
+    #[serde(default)]
+    pub b: Option<usize>,
+
+    /// Where the bodies are pinned, in the world
+    pub point: (f32, f32),
+
+    pub threshold: f32,
+}
+
+
+
+/// A body traced from the alpha of an image, see `SpriteBuilder`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ImageDesc {
+    pub image: String,
+
+    #[serde(default)]
+    pub position: (f32, f32),
+
+    #[serde(default)]
+    pub scale: Option<f32>,
+
+    #[serde(default)]
+    pub status: Status,
+
+    #[serde(default = "default_material")]
+    pub material: String,
+}
+
+
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SceneFile {
+    pub bodies: Vec<BodyDesc>,
+
+    #[serde(default)]
+    pub vehicles: Vec<VehicleDesc>,
+
+    #[serde(default)]
+    pub images: Vec<ImageDesc>,
+
+    #[serde(default)]
+    pub joints: Vec<JointDesc>,
+}
+
+impl SceneFile {
+    pub fn load(path: &str) -> Result<SceneFile, String> {
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .map_err(|e| format!("Could not read {}: {}", path, e))?;
+
+        ron::de::from_str(&contents)
+            .map_err(|e| format!("Could not parse {}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| format!("Could not serialize scene: {}", e))?;
+
+        File::create(path)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .map_err(|e| format!("Could not write {}: {}", path, e))
+    }
+
+    /**
+     * Adds every body, vehicle, joint and image in the scene to the world
+     * and returns the bodies, images included. Vehicles are left out since
+     * they aren't a single body. Images that can't be read, shapes that
+     * can't be built and joints to the bodies skipped are skipped too.
+     */
+    pub fn spawn(&self, specsWorld: &mut specs::World) -> Vec<specs::Entity> {
+        debug!("Spawning {} bodies, {} vehicles, {} joints and {} images from scene file", self.bodies.len(), self.vehicles.len(), self.joints.len(), self.images.len());
+
+        for vehicle in self.vehicles.iter() {
+            let mut builder = VehicleBuilder::new(nalgebra::Point2::new(vehicle.position.0, vehicle.position.1));
+            if let Some(torque) = vehicle.torque {
+                builder = builder.torque(torque);
+            }
+            builder.build(specsWorld);
+        }
+
+        let bodies: Vec<Option<specs::Entity>> = self.bodies.iter().map(|body| body.spawn(specsWorld)).collect();
+
+        for joint in self.joints.iter() {
+            let body = |index: Option<usize>| match index {
+                Some(i) => bodies.get(i).cloned().unwrap_or(None).map(Some),
+                None => Some(None),
+            };
+
+            match (body(joint.a), body(joint.b)) {
+                (Some(a), Some(b)) => {
+                    let point = nalgebra::Point2::new(joint.point.0, joint.point.1);
+                    let joint = BreakableJoint::at(specsWorld, a, b, point, joint.threshold);
+                    specsWorld.create_entity().with(joint).build();
+                },
+
+                _ => warn!("Skipping joint at {:?}, one of its bodies is missing", joint.point),
+            }
+        }
+
+        let mut spawns: Vec<specs::Entity> = bodies.into_iter().filter_map(|body| body).collect();
+
+        for image in self.images.iter() {
+            let built = SpriteBuilder::new(&image.image)
+                .position(image.position.0, image.position.1)
+                .scale(image.scale.unwrap_or(1.0))
+                .status(image.status)
+                .material(&image.material)
+                .build(specsWorld);
+
+            match built {
+                Ok(entity) => spawns.push(entity),
+                Err(e) => warn!("{}", e),
+            }
+        }
+
+        spawns
+    }
+}
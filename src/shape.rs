@@ -0,0 +1,371 @@
+/**
+ *
+ * Collider shape descriptions.
+ *
+ * A `ShapeDesc` is a plain, serializable description of an ncollide shape.
+ * It can build the collision shape, the render mesh that matches it and is
+ * kept on the entity as a `Shape` component so the body can be saved or
+ * rebuilt later.
+ *
+ */
+
+use ggez::graphics;
+
+use ncollide2d::shape::ShapeHandle;
+
+use specs;
+use specs::prelude::*;
+
+use crate::geometry;
+use crate::render::RenderStyle;
+
+/// Segments used to draw round shapes
+const ROUND_SEGMENTS: usize = 24;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ShapeDesc {
+    Ball { radius: f32 },
+    Polyline { points: Vec<[f32; 2]> },
+    Segment { a: [f32; 2], b: [f32; 2] },
+    Cuboid { half_extents: [f32; 2] },
+
+    /// Aligned with the body's y axis
+    Capsule { half_height: f32, radius: f32 },
+
+    /// Any set of points, the collider and mesh use their convex hull
+    Convex { points: Vec<[f32; 2]> },
+
+    Compound { parts: Vec<Part> },
+
+    /**
+     * Ground made of heights spread evenly across the width, centered on
+     * the body's origin. Heights go up, towards -y, from a base at y 0 and
+     * the shape is drawn filled down to the base.
+     */
+    Heightfield { heights: Vec<f32>, width: f32 },
+}
+
+/// A sub-shape of a compound, placed relative to the body
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Part {
+    #[serde(default)]
+    pub position: [f32; 2],
+
+    #[serde(default)]
+    pub rotation: f32,
+
+    pub shape: ShapeDesc,
+}
+
+fn point(p: &[f32; 2]) -> nalgebra::Point2<f32> {
+    nalgebra::Point2::new(p[0], p[1])
+}
+
+fn gpoint(p: &[f32; 2]) -> graphics::Point2 {
+    graphics::Point2::new(p[0], p[1])
+}
+
+/// Whether every coordinate is a finite number
+fn finite(points: &[[f32; 2]]) -> bool {
+    points.iter().all(|p| p[0].is_finite() && p[1].is_finite())
+}
+
+/// Whether every size is a finite number above zero
+fn positive(sizes: &[f32]) -> bool {
+    sizes.iter().all(|size| size.is_finite() && *size > 0.0)
+}
+
+/// The surface of a heightfield from left to right
+fn surface(heights: &[f32], width: f32) -> Vec<[f32; 2]> {
+    let step = width / (heights.len().max(2) - 1) as f32;
+    heights.iter()
+        .enumerate()
+        .map(|(i, h)| [i as f32 * step - width / 2.0, - h])
+        .collect()
+}
+
+impl ShapeDesc {
+    /**
+     * A convex polygon from the convex hull of any set of points. Returns
+     * `None` when the points don't enclose an area.
+     */
+    pub fn convex(points: &[[f32; 2]]) -> Option<ShapeDesc> {
+        let hull = geometry::convex_hull(points);
+        if hull.len() < 3 {
+            return None;
+        }
+
+        Some(ShapeDesc::Convex { points: hull })
+    }
+
+    /**
+     * A simple polygon as a single convex shape, or a compound of convex
+     * pieces when it is concave, centered on its centroid. Returns the
+     * centroid along with it, `None` when the polygon crosses itself or
+     * has no area.
+     */
+    pub fn polygon(points: &[[f32; 2]]) -> Option<(ShapeDesc, [f32; 2])> {
+        let center = geometry::centroid(points);
+        let local: Vec<[f32; 2]> = points.iter()
+            .map(|p| [p[0] - center[0], p[1] - center[1]])
+            .collect();
+
+        let mut parts: Vec<ShapeDesc> = geometry::convex_decomposition(&local)?
+            .iter()
+            .filter_map(|piece| ShapeDesc::convex(piece))
+            .collect();
+
+        let shape = match parts.len() {
+            0 => return None,
+            1 => parts.remove(0),
+            _ => ShapeDesc::Compound {
+                parts: parts.into_iter()
+                    .map(|shape| Part { position: [0.0, 0.0], rotation: 0.0, shape: shape })
+                    .collect(),
+            },
+        };
+
+        Some((shape, center))
+    }
+
+    /**
+     * The ncollide shape in the body's local space. Returns `None` for
+     * descriptions ncollide can't build a shape from, like a ball, box or
+     * capsule without a size, a convex shape without area, a polyline with
+     * fewer than two points, a heightfield with fewer than two heights or
+     * without a width, or any value that isn't finite.
+     */
+    pub fn handle(&self) -> Option<ShapeHandle<f32>> {
+        let handle = match self {
+            ShapeDesc::Ball { radius } => {
+                if !positive(&[*radius]) {
+                    return None;
+                }
+
+                ShapeHandle::new(ncollide2d::shape::Ball::new(*radius))
+            },
+
+            ShapeDesc::Polyline { points } => {
+                if points.len() < 2 || !finite(points) {
+                    return None;
+                }
+
+                ShapeHandle::new(ncollide2d::shape::Polyline::new(points.iter().map(point).collect()))
+            },
+
+            ShapeDesc::Segment { a, b } => {
+                if !finite(&[*a, *b]) {
+                    return None;
+                }
+
+                ShapeHandle::new(ncollide2d::shape::Segment::new(point(a), point(b)))
+            },
+
+            ShapeDesc::Cuboid { half_extents } => {
+                if !positive(half_extents) {
+                    return None;
+                }
+
+                ShapeHandle::new(ncollide2d::shape::Cuboid::new(nalgebra::Vector2::new(half_extents[0], half_extents[1])))
+            },
+
+            ShapeDesc::Capsule { half_height, radius } => {
+                if !positive(&[*half_height, *radius]) {
+                    return None;
+                }
+
+                ShapeHandle::new(ncollide2d::shape::Capsule::new(*half_height, *radius))
+            },
+
+            ShapeDesc::Convex { points } => {
+                if !finite(points) {
+                    return None;
+                }
+
+                let points: Vec<nalgebra::Point2<f32>> = points.iter().map(point).collect();
+                ShapeHandle::new(ncollide2d::shape::ConvexPolygon::try_from_points(&points)?)
+            },
+
+            ShapeDesc::Compound { parts } => {
+                if parts.is_empty() {
+                    return None;
+                }
+
+                let shapes = parts.iter()
+                    .map(|part| {
+                        if !finite(&[part.position]) || !part.rotation.is_finite() {
+                            return None;
+                        }
+
+                        let pose = nalgebra::Isometry2::new(nalgebra::Vector2::new(part.position[0], part.position[1]), part.rotation);
+                        part.shape.handle().map(|handle| (pose, handle))
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+
+                ShapeHandle::new(ncollide2d::shape::Compound::new(shapes))
+            },
+
+            ShapeDesc::Heightfield { heights, width } => {
+                if heights.len() < 2 || !positive(&[*width]) || heights.iter().any(|h| !h.is_finite()) {
+                    return None;
+                }
+
+                ShapeHandle::new(ncollide2d::shape::Polyline::new(surface(heights, *width).iter().map(point).collect()))
+            },
+        };
+
+        Some(handle)
+    }
+
+    /**
+     * Whether the shape encloses an area and so has a mass. Open shapes, like
+     * segments, polylines and heightfields, can only be static or kinematic;
+     * a compound is solid when all of its parts are.
+     */
+    pub fn is_solid(&self) -> bool {
+        match self {
+            ShapeDesc::Polyline { .. } | ShapeDesc::Segment { .. } | ShapeDesc::Heightfield { .. } => false,
+            ShapeDesc::Compound { parts } => parts.iter().all(|part| part.shape.is_solid()),
+            _ => true,
+        }
+    }
+
+    /**
+     * Builds a mesh in the body's local space matching the collision shape.
+     * Open shapes are always drawn as lines.
+     */
+    pub fn mesh(&self, context: &mut ggez::Context, style: RenderStyle) -> ggez::GameResult<Option<graphics::Mesh>> {
+        let mode = match style.draw_mode() {
+            Some(mode) => mode,
+            None => return Ok(None),
+        };
+
+        let mut builder = graphics::MeshBuilder::default();
+        self.add_to_mesh(&mut builder, mode, &[0.0, 0.0], 0.0);
+        builder.build(context).map(Some)
+    }
+
+    /// Adds this shape to a mesh, placed at `position` and `rotation`
+    fn add_to_mesh(&self, builder: &mut graphics::MeshBuilder, mode: graphics::DrawMode, position: &[f32; 2], rotation: f32) {
+        let line_width = match mode {
+            graphics::DrawMode::Line(width) => width,
+            _ => 2.0,
+        };
+
+        let placed = |points: &[[f32; 2]]| -> Vec<graphics::Point2> {
+            points.iter()
+                .map(|p| gpoint(&geometry::transform(p, position, rotation)))
+                .collect()
+        };
+
+        match self {
+            ShapeDesc::Ball { radius } => {
+                builder.circle(mode, gpoint(position), *radius, 0.1);
+            },
+
+            ShapeDesc::Polyline { points } => {
+                builder.line(&placed(points), line_width);
+            },
+
+            ShapeDesc::Segment { a, b } => {
+                builder.line(&placed(&[*a, *b]), line_width);
+            },
+
+            ShapeDesc::Cuboid { half_extents } => {
+                let [x, y] = *half_extents;
+                builder.polygon(mode, &placed(&[[-x, -y], [x, -y], [x, y], [-x, y]]));
+            },
+
+            ShapeDesc::Capsule { half_height, radius } => {
+                builder.polygon(mode, &placed(&geometry::capsule(*half_height, *radius, ROUND_SEGMENTS)));
+            },
+
+            ShapeDesc::Convex { points } => {
+                builder.polygon(mode, &placed(&geometry::convex_hull(points)));
+            },
+
+            ShapeDesc::Compound { parts } => {
+                for part in parts {
+                    let part_position = geometry::transform(&part.position, position, rotation);
+                    part.shape.add_to_mesh(builder, mode, &part_position, rotation + part.rotation);
+                }
+            },
+
+            ShapeDesc::Heightfield { heights, width } => {
+                let top = surface(heights, *width);
+                match mode {
+                    // a column under every span keeps each polygon convex
+                    graphics::DrawMode::Fill => {
+                        for span in top.windows(2) {
+                            let (a, b) = (span[0], span[1]);
+                            builder.polygon(mode, &placed(&[a, b, [b[0], 0.0], [a[0], 0.0]]));
+                        }
+                    },
+
+                    _ => {
+                        builder.line(&placed(&top), line_width);
+                    },
+                }
+            },
+        }
+    }
+
+    /// The same shape scaled around the body's origin
+    pub fn scaled(&self, factor: f32) -> ShapeDesc {
+        let scale = |points: &Vec<[f32; 2]>| -> Vec<[f32; 2]> {
+            points.iter().map(|p| [p[0] * factor, p[1] * factor]).collect()
+        };
+
+        match self {
+            ShapeDesc::Ball { radius } => ShapeDesc::Ball { radius: radius * factor },
+            ShapeDesc::Polyline { points } => ShapeDesc::Polyline { points: scale(points) },
+            ShapeDesc::Segment { a, b } => ShapeDesc::Segment {
+                a: [a[0] * factor, a[1] * factor],
+                b: [b[0] * factor, b[1] * factor],
+            },
+            ShapeDesc::Cuboid { half_extents } => ShapeDesc::Cuboid {
+                half_extents: [half_extents[0] * factor, half_extents[1] * factor],
+            },
+            ShapeDesc::Capsule { half_height, radius } => ShapeDesc::Capsule {
+                half_height: half_height * factor,
+                radius: radius * factor,
+            },
+            ShapeDesc::Convex { points } => ShapeDesc::Convex { points: scale(points) },
+            ShapeDesc::Compound { parts } => ShapeDesc::Compound {
+                parts: parts.iter()
+                    .map(|part| Part {
+                        position: [part.position[0] * factor, part.position[1] * factor],
+                        rotation: part.rotation,
+                        shape: part.shape.scaled(factor),
+                    })
+                    .collect(),
+            },
+            ShapeDesc::Heightfield { heights, width } => ShapeDesc::Heightfield {
+                heights: heights.iter().map(|h| h * factor).collect(),
+                width: width * factor,
+            },
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ShapeDesc::Ball { .. } => "ball",
+            ShapeDesc::Polyline { .. } => "polyline",
+            ShapeDesc::Segment { .. } => "segment",
+            ShapeDesc::Cuboid { .. } => "box",
+            ShapeDesc::Capsule { .. } => "capsule",
+            ShapeDesc::Convex { .. } => "convex",
+            ShapeDesc::Compound { .. } => "compound",
+            ShapeDesc::Heightfield { .. } => "heightfield",
+        }
+    }
+}
+
+
+
+/// The description an entity's collider was built from
+#[derive(Clone, Debug, Component)]
+#[storage(VecStorage)]
+pub struct Shape {
+    pub desc: ShapeDesc,
+}
@@ -0,0 +1,355 @@
+/**
+ *
+ * Soft bodies made of mass-spring networks.
+ *
+ * A soft body is a set of small ball bodies, its particles, held together
+ * by damped springs. A ring of particles can also be filled with gas whose
+ * pressure pushes the ring back out when it is squashed, which makes a
+ * jelly blob. The `SoftBody` component sits on its own entity and refers to
+ * the particle entities:
+ *
+ * ```ignore
+ * SoftBodyBuilder::ring(nalgebra::Point2::new(0.0, -100.0), 30.0, 16)
+ *     .stiffness(400.0)
+ *     .pressure(100.0)
+ *     .material("rubber")
+ *     .build(&mut sceneWorld.specs);
+ * ```
+ *
+ */
+
+use ggez::graphics;
+
+use specs;
+use specs::prelude::*;
+
+use crate::builder::BodyBuilder;
+use crate::geometry;
+use crate::material::DEFAULT_MATERIAL;
+use crate::render::RenderStyle;
+use crate::shape::ShapeDesc;
+use crate::system::{BodyPoint, RigidBody};
+use crate::tuning::Tuning;
+use crate::world::PhysicsWorld;
+
+/// Radius of every particle
+pub const PARTICLE_RADIUS: f32 = 4.0;
+
+/// A damped spring between two particles, by their index in the soft body
+#[derive(Clone, Copy, Debug)]
+pub struct Spring {
+    pub a: usize,
+    pub b: usize,
+    pub rest: f32,
+}
+
+/**
+ * Springs and pressure holding a set of particles together. Stiffness and
+ * damping are per unit of particle mass so they don't depend on the size
+ * or material of the particles.
+ */
+#[derive(Clone, Debug, Component)]
+#[storage(HashMapStorage)]
+pub struct SoftBody {
+    pub particles: Vec<specs::Entity>,
+    pub springs: Vec<Spring>,
+
+    /// Indices of the particles around the outline, in order
+    pub skin: Vec<usize>,
+
+    pub stiffness: f32,
+    pub damping: f32,
+
+    /// Whether the stiffness and damping follow the tuning panel, which
+    /// bodies built with springs of their own don't
+    pub tuned: bool,
+
+    /// Gas pressure inside the skin per unit of particle mass, 0.0 for none
+    pub pressure: f32,
+
+    /// Area inside the skin at rest, where the gas pushes neither way
+    pub rest_area: f32,
+
+    pub color: [f32; 4],
+}
+
+/// Marks the particles of a soft body
+#[derive(Clone, Debug, Component)]
+#[storage(VecStorage)]
+pub struct SoftParticle;
+
+
+
+pub struct SoftBodyBuilder {
+    points: Vec<[f32; 2]>,
+    springs: Vec<(usize, usize)>,
+    skin: Vec<usize>,
+    stiffness: Option<f32>,
+    damping: Option<f32>,
+    pressure: f32,
+    material: String,
+    color: [f32; 4],
+}
+
+impl SoftBodyBuilder {
+    fn new(points: Vec<[f32; 2]>, springs: Vec<(usize, usize)>, skin: Vec<usize>) -> SoftBodyBuilder {
+        SoftBodyBuilder {
+            points: points,
+            springs: springs,
+            skin: skin,
+            stiffness: None,
+            damping: None,
+            pressure: 0.0,
+            material: DEFAULT_MATERIAL.to_owned(),
+            color: [0.4, 0.9, 0.5, 0.8],
+        }
+    }
+
+    /**
+     * A closed ring of particles. Every particle is tied to its neighbours
+     * and to the ones two along, which keeps the ring from folding.
+     */
+    pub fn ring(center: nalgebra::Point2<f32>, radius: f32, count: usize) -> SoftBodyBuilder {
+        let count = count.max(3);
+        let points = geometry::circle(&[center.x, center.y], radius, count);
+
+        let mut springs = Vec::new();
+        for i in 0..count {
+            springs.push((i, (i + 1) % count));
+            springs.push((i, (i + 2) % count));
+        }
+
+        SoftBodyBuilder::new(points, springs, (0..count).collect()).pressure(100.0)
+    }
+
+    /**
+     * A grid of particles centered on the point. Particles are tied to
+     * their neighbours along the rows, the columns and both diagonals.
+     */
+    pub fn grid(center: nalgebra::Point2<f32>, columns: usize, rows: usize, spacing: f32) -> SoftBodyBuilder {
+        let (columns, rows) = (columns.max(2), rows.max(2));
+        let index = |column: usize, row: usize| row * columns + column;
+        let origin = [
+            center.x - (columns - 1) as f32 * spacing / 2.0,
+            center.y - (rows - 1) as f32 * spacing / 2.0,
+        ];
+
+        let mut points = Vec::new();
+        let mut springs = Vec::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                points.push([origin[0] + column as f32 * spacing, origin[1] + row as f32 * spacing]);
+
+                if column + 1 < columns {
+                    springs.push((index(column, row), index(column + 1, row)));
+                }
+                if row + 1 < rows {
+                    springs.push((index(column, row), index(column, row + 1)));
+                }
+                if column + 1 < columns && row + 1 < rows {
+                    springs.push((index(column, row), index(column + 1, row + 1)));
+                    springs.push((index(column + 1, row), index(column, row + 1)));
+                }
+            }
+        }
+
+        // the outline goes along the top, down the right, back along the bottom and up the left
+        let mut skin: Vec<usize> = (0..columns).map(|c| index(c, 0)).collect();
+        skin.extend((1..rows).map(|r| index(columns - 1, r)));
+        skin.extend((0..columns - 1).rev().map(|c| index(c, rows - 1)));
+        skin.extend((1..rows - 1).rev().map(|r| index(0, r)));
+
+        SoftBodyBuilder::new(points, springs, skin)
+    }
+
+    /// Springs of its own instead of the tuning's, which the tuning panel leaves alone
+    pub fn stiffness(mut self, stiffness: f32) -> Self {
+        self.stiffness = Some(stiffness);
+        self
+    }
+
+    pub fn damping(mut self, damping: f32) -> Self {
+        self.damping = Some(damping);
+        self
+    }
+
+    pub fn pressure(mut self, pressure: f32) -> Self {
+        self.pressure = pressure;
+        self
+    }
+
+    /// Name of a material in the `MaterialLibrary`, used by every particle
+    pub fn material(mut self, name: &str) -> Self {
+        self.material = name.to_owned();
+        self
+    }
+
+    pub fn color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Creates the particles and the soft body entity tying them together
+    pub fn build(self, specsWorld: &mut specs::World) -> specs::Entity {
+        let particles: Vec<specs::Entity> = self.points.iter()
+            .map(|p| {
+                BodyBuilder::new(specsWorld, ShapeDesc::Ball { radius: PARTICLE_RADIUS })
+                    .position(p[0], p[1])
+                    .material(&self.material)
+                    .render(RenderStyle::Hidden, self.color)
+                    .with(SoftParticle)
+                    .build()
+            })
+            .collect();
+
+        let points = &self.points;
+        let springs = self.springs.iter()
+            .map(|&(a, b)| Spring {
+                a: a,
+                b: b,
+                rest: (points[a][0] - points[b][0]).hypot(points[a][1] - points[b][1]),
+            })
+            .collect();
+
+        let outline: Vec<[f32; 2]> = self.skin.iter().map(|i| points[*i]).collect();
+        let (stiffness, damping) = {
+            let tuning = specsWorld.read_resource::<Tuning>();
+            (self.stiffness.unwrap_or(tuning.stiffness), self.damping.unwrap_or(tuning.damping))
+        };
+
+        specsWorld.create_entity()
+            .with(SoftBody {
+                particles: particles,
+                springs: springs,
+                rest_area: geometry::area(&outline),
+                skin: self.skin,
+                stiffness: stiffness,
+                damping: damping,
+                tuned: self.stiffness.is_none() && self.damping.is_none(),
+                pressure: self.pressure,
+                color: self.color,
+            })
+            .build()
+    }
+}
+
+
+
+/// The center of a particle and how it moves, `None` once it was deleted
+type Particle = Option<BodyPoint>;
+
+/**
+ * Applies the spring and pressure forces of every soft body to its
+ * particles. Particles that were deleted are skipped along with their
+ * springs.
+ */
+pub struct SoftBodySystem;
+
+impl<'a> specs::System<'a> for SoftBodySystem {
+    type SystemData = (
+        specs::ReadStorage<'a, SoftBody>,
+        specs::ReadStorage<'a, RigidBody>,
+        specs::WriteExpect<'a, PhysicsWorld>,
+    );
+
+    fn run(&mut self, (softBodies, rigidBodies, mut physics_world): Self::SystemData) {
+        for soft in softBodies.join() {
+            let particles: Vec<Particle> = soft.particles.iter()
+                .map(|entity| BodyPoint::at(&physics_world, rigidBodies.get(*entity)?.object_handle, &nalgebra::Point2::origin()))
+                .collect();
+
+            // particles that don't move leave the springs to the other end
+            let particleMass = |particle: &BodyPoint| particle.mass().unwrap_or(std::f32::MAX);
+
+            let mut forces = vec![nalgebra::Vector2::zeros(); particles.len()];
+
+            for spring in soft.springs.iter() {
+                let (a, b) = match (particles[spring.a], particles[spring.b]) {
+                    (Some(a), Some(b)) => (a, b),
+                    _ => continue,
+                };
+
+                let offset = b.position - a.position;
+                let length = offset.norm();
+                if length < std::f32::EPSILON {
+                    continue;
+                }
+
+                let direction = offset / length;
+                let stretch = length - spring.rest;
+                let closing = (b.velocity - a.velocity).dot(&direction);
+                let force = direction * (soft.stiffness * stretch + soft.damping * closing) * particleMass(&a).min(particleMass(&b));
+
+                forces[spring.a] += force;
+                forces[spring.b] -= force;
+            }
+
+            if soft.pressure > 0.0 {
+                let outline: Option<Vec<[f32; 2]>> = soft.skin.iter()
+                    .map(|i| particles[*i].map(|p| [p.position.x, p.position.y]))
+                    .collect();
+
+                if let Some(points) = outline {
+                    let area = geometry::area(&points).max(std::f32::EPSILON);
+                    let mass = particles.iter().filter_map(|p| p.and_then(|p| p.mass())).fold(std::f32::MAX, f32::min);
+
+                    // gas pressure over the pressure at rest, pushing out when squashed
+                    let excess = soft.pressure * (soft.rest_area / area - 1.0) * mass;
+                    let winding = if geometry::is_ccw(&points) { 1.0 } else { -1.0 };
+
+                    for i in 0..points.len() {
+                        let (a, b) = (points[i], points[(i + 1) % points.len()]);
+
+                        // the outward normal scaled by the edge length
+                        let normal = nalgebra::Vector2::new(b[1] - a[1], a[0] - b[0]) * winding;
+                        let force = normal * excess * 0.5;
+                        forces[soft.skin[i]] += force;
+                        forces[soft.skin[(i + 1) % points.len()]] += force;
+                    }
+                }
+            }
+
+            for (particle, force) in particles.iter().zip(forces) {
+                if let Some(particle) = particle {
+                    particle.push(&mut physics_world, &force);
+                }
+            }
+        }
+    }
+}
+
+
+
+/// Fills the skin of every soft body through its particles
+pub fn draw_skins(context: &mut ggez::Context, specsWorld: &specs::World) -> ggez::GameResult<()> {
+    let softBodies = specsWorld.read_storage::<SoftBody>();
+    let rigidBodies = specsWorld.read_storage::<RigidBody>();
+    let physics_world = specsWorld.read_resource::<PhysicsWorld>();
+
+    for soft in softBodies.join() {
+        let outline: Vec<graphics::Point2> = soft.skin.iter()
+            .filter_map(|i| {
+                let handle = rigidBodies.get(soft.particles[*i])?.object_handle;
+                let translation = physics_world.rigid_body(handle)?.position().translation.vector;
+                Some(graphics::Point2::new(translation.x, translation.y))
+            })
+            .collect();
+
+        if outline.len() < 3 {
+            continue;
+        }
+
+        let color = graphics::Color::new(soft.color[0], soft.color[1], soft.color[2], soft.color[3]);
+        graphics::set_color(context, color)?;
+
+        // a squashed skin may cross itself, which the triangulation of a
+        // filled polygon can't handle, so it falls back to the outline
+        let fill = graphics::Mesh::new_polygon(context, graphics::DrawMode::Fill, &outline);
+        match fill {
+            Ok(mesh) => graphics::draw(context, &mesh, graphics::Point2::origin(), 0.0)?,
+            Err(_) => graphics::polygon(context, graphics::DrawMode::Line(PARTICLE_RADIUS * 2.0), &outline)?,
+        }
+    }
+
+    graphics::set_color(context, graphics::WHITE)
+}
@@ -0,0 +1,404 @@
+/**
+ *
+ * Bodies shaped like their art.
+ *
+ * A sprite body is drawn with an image and collides with the opaque part
+ * of it. The outline is traced with marching squares over the alpha
+ * channel, simplified and split into convex pieces that make up a single
+ * compound shape centered on the image:
+ *
+ * ```ignore
+ * SpriteBuilder::new("images/boulder.png")
+ *     .position(0.0, -100.0)
+ *     .scale(0.5)
+ *     .material("rock")
+ *     .build(&mut sceneWorld.specs)?;
+ * ```
+ *
+ * Holes in the art are filled in since the pieces can't leave them out.
+ * The image is read again when it is first drawn, which needs a graphics
+ * context.
+ *
+ */
+
+use std::collections::HashMap;
+
+use ggez::graphics;
+
+use specs;
+use specs::prelude::*;
+
+use log::*;
+
+use crate::builder::{BodyBuilder, Status};
+use crate::geometry;
+use crate::material::DEFAULT_MATERIAL;
+use crate::render::RenderStyle;
+use crate::shape::{Part, ShapeDesc};
+use crate::system::Collider;
+use crate::world::PhysicsWorld;
+
+/// Pixels at least this opaque are solid
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// Outline points closer than this many pixels to the simplified outline are dropped
+const OUTLINE_TOLERANCE: f32 = 1.5;
+
+/// Outlines smaller than this many pixels are specks and ignored
+const OUTLINE_MIN_AREA: f32 = 8.0;
+
+/// Draws a body with an image, scaled around its center
+#[derive(Clone, Debug, Component, Serialize, Deserialize)]
+#[storage(HashMapStorage)]
+pub struct Sprite {
+    pub image: String,
+
+    #[serde(default = "unscaled")]
+    pub scale: f32,
+}
+
+fn unscaled() -> f32 {
+    1.0
+}
+
+/// The loaded image of a `Sprite`, built lazily like a `Mesh`
+#[derive(Clone, Debug, Component)]
+#[storage(HashMapStorage)]
+pub struct SpriteImage {
+    /// `None` when the image couldn't be loaded, so it isn't tried again every frame
+    pub image: Option<graphics::Image>,
+}
+
+
+
+/// The pixels of an image, one RGBA quadruple each
+pub struct Pixels {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+impl Pixels {
+    pub fn load(path: &str) -> Result<Pixels, String> {
+        let image = image::open(path)
+            .map_err(|e| format!("Could not read {}: {}", path, e))?
+            .to_rgba();
+
+        Ok(Pixels {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            rgba: image.into_raw(),
+        })
+    }
+
+    /// Alpha at a pixel, transparent outside the image
+    pub fn alpha(&self, x: i32, y: i32) -> u8 {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return 0;
+        }
+
+        self.rgba[(y as usize * self.width + x as usize) * 4 + 3]
+    }
+
+    /**
+     * The outlines between solid and clear pixels, in pixels from the top
+     * left corner of the image. Samples sit on the pixel centers and the
+     * crossings are interpolated along the alpha. Outer outlines and the
+     * outlines of holes wind opposite ways.
+     */
+    pub fn contours(&self) -> Vec<Vec<geometry::Point>> {
+        type Edge = ((i32, i32), (i32, i32));
+
+        let solid = |p: (i32, i32)| self.alpha(p.0, p.1) >= ALPHA_THRESHOLD;
+
+        // where the outline crosses the edge between two samples
+        let crossing = |edge: &Edge| -> geometry::Point {
+            let ((ax, ay), (bx, by)) = *edge;
+            let (a, b) = (self.alpha(ax, ay) as f32, self.alpha(bx, by) as f32);
+            let t = if (b - a).abs() > std::f32::EPSILON { (ALPHA_THRESHOLD as f32 - a) / (b - a) } else { 0.5 };
+            let t = t.max(0.0).min(1.0);
+            [ax as f32 + (bx - ax) as f32 * t + 0.5, ay as f32 + (by - ay) as f32 * t + 0.5]
+        };
+
+        // every cell links the crossing the outline enters it at to the one
+        // it leaves at, going round its corners clockwise on screen
+        let mut links: HashMap<Edge, Edge> = HashMap::new();
+        for y in -1..self.height as i32 {
+            for x in -1..self.width as i32 {
+                let corners = [(x, y), (x + 1, y), (x + 1, y + 1), (x, y + 1)];
+
+                let mut entering = None;
+                let mut crossings = Vec::new();
+                for i in 0..4 {
+                    let (a, b) = (corners[i], corners[(i + 1) % 4]);
+                    if solid(a) != solid(b) {
+                        crossings.push((solid(b), if a < b { (a, b) } else { (b, a) }));
+                    }
+                }
+
+                // start after a leaving crossing so every enter is followed by its leave
+                if let Some(start) = crossings.iter().position(|c| !c.0) {
+                    crossings.rotate_left(start + 1);
+                }
+
+                for (inward, edge) in crossings {
+                    match (inward, entering.take()) {
+                        (true, _) => entering = Some(edge),
+                        (false, Some(from)) => { links.insert(from, edge); },
+                        (false, None) => {},
+                    }
+                }
+            }
+        }
+
+        let mut contours = Vec::new();
+        while let Some(&start) = links.keys().next() {
+            let mut contour = Vec::new();
+            let mut at = start;
+
+            while let Some(next) = links.remove(&at) {
+                contour.push(crossing(&at));
+                at = next;
+            }
+
+            contours.push(contour);
+        }
+
+        contours
+    }
+
+    /**
+     * The solid part of the image as a compound shape of convex pieces,
+     * centered on the image and scaled. `None` when nothing is solid.
+     */
+    pub fn shape(&self, scale: f32) -> Option<ShapeDesc> {
+        let contours: Vec<Vec<geometry::Point>> = self.contours().into_iter()
+            .map(|contour| geometry::simplify_closed(&contour, OUTLINE_TOLERANCE))
+            .filter(|contour| contour.len() >= 3 && geometry::area(contour) >= OUTLINE_MIN_AREA)
+            .collect();
+
+        // the biggest outline is always an outer one, holes wind the other way
+        let outer = contours.iter()
+            .max_by(|a, b| geometry::area(a).partial_cmp(&geometry::area(b)).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|contour| geometry::is_ccw(contour))?;
+
+        let center = [self.width as f32 / 2.0, self.height as f32 / 2.0];
+        let mut parts = Vec::new();
+        for contour in contours.iter().filter(|c| geometry::is_ccw(c) == outer) {
+            let local: Vec<geometry::Point> = contour.iter()
+                .map(|p| [(p[0] - center[0]) * scale, (p[1] - center[1]) * scale])
+                .collect();
+
+            match geometry::convex_decomposition(&local) {
+                Some(pieces) => parts.extend(pieces.iter()
+                    .filter_map(|piece| ShapeDesc::convex(piece))
+                    .map(|shape| Part { position: [0.0, 0.0], rotation: 0.0, shape: shape })),
+
+                None => warn!("Skipping an outline that crosses itself"),
+            }
+        }
+
+        if parts.is_empty() {
+            return None;
+        }
+
+        Some(ShapeDesc::Compound { parts: parts })
+    }
+}
+
+
+
+pub struct SpriteBuilder {
+    image: String,
+    scale: f32,
+    position: nalgebra::Point2<f32>,
+    status: Status,
+    material: String,
+}
+
+impl SpriteBuilder {
+    /// A dynamic body at the origin, the size of the image
+    pub fn new(image: &str) -> SpriteBuilder {
+        SpriteBuilder {
+            image: image.to_owned(),
+            scale: 1.0,
+            position: nalgebra::Point2::origin(),
+            status: Status::Dynamic,
+            material: DEFAULT_MATERIAL.to_owned(),
+        }
+    }
+
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn position(mut self, x: f32, y: f32) -> Self {
+        self.position = nalgebra::Point2::new(x, y);
+        self
+    }
+
+    pub fn status(mut self, status: Status) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Name of a material in the `MaterialLibrary`
+    pub fn material(mut self, name: &str) -> Self {
+        self.material = name.to_owned();
+        self
+    }
+
+    /// Traces the image and creates the body, fails when it can't be read or has nothing solid
+    pub fn build(self, specsWorld: &mut specs::World) -> Result<specs::Entity, String> {
+        let shape = Pixels::load(&self.image)?
+            .shape(self.scale)
+            .ok_or_else(|| format!("{} has no opaque area to collide with", self.image))?;
+
+        Ok(BodyBuilder::new(specsWorld, shape)
+            .position(self.position.x, self.position.y)
+            .status(self.status)
+            .material(&self.material)
+            .render(RenderStyle::Hidden, [1.0, 1.0, 1.0, 1.0])
+            .with(Sprite { image: self.image, scale: self.scale })
+            .build())
+    }
+}
+
+
+
+/// Loads the images of the sprites that haven't tried yet
+pub fn build_sprites(context: &mut ggez::Context, specsWorld: &mut specs::World) {
+    let pending: Vec<(specs::Entity, Sprite)> = {
+        let entities = specsWorld.entities();
+        let sprites = specsWorld.read_storage::<Sprite>();
+        let images = specsWorld.read_storage::<SpriteImage>();
+
+        (&entities, &sprites, !&images)
+            .join()
+            .map(|(e, s, _)| (e, s.clone()))
+            .collect()
+    };
+
+    let mut images = specsWorld.write_storage::<SpriteImage>();
+    for (entity, sprite) in pending {
+        let image = Pixels::load(&sprite.image)
+            .and_then(|pixels| {
+                graphics::Image::from_rgba8(context, pixels.width as u16, pixels.height as u16, &pixels.rgba)
+                    .map_err(|e| e.to_string())
+            });
+
+        let image = match image {
+            Ok(image) => Some(image),
+            Err(e) => {
+                warn!("Could not load sprite for {:?}: {}", entity, e);
+                None
+            },
+        };
+
+        images.insert(entity, SpriteImage { image: image });
+    }
+}
+
+/// Draws every sprite centered on its body
+pub fn draw_sprites(context: &mut ggez::Context, specsWorld: &specs::World) -> ggez::GameResult<()> {
+    let sprites = specsWorld.read_storage::<Sprite>();
+    let images = specsWorld.read_storage::<SpriteImage>();
+    let colliders = specsWorld.read_storage::<Collider>();
+    let physics_world = specsWorld.read_resource::<PhysicsWorld>();
+
+    for (sprite, image, c) in (&sprites, &images, &colliders).join() {
+        let image = match image.image {
+            Some(ref image) => image,
+            None => continue,
+        };
+
+        let isometry = match physics_world.collision_world().collision_object(c.object_handle) {
+            Some(object) => object.position(),
+            None => continue,
+        };
+
+        let drawParam = graphics::DrawParam {
+            dest: graphics::Point2::new(isometry.translation.vector.x, isometry.translation.vector.y),
+            rotation: isometry.rotation.angle(),
+            offset: graphics::Point2::new(0.5, 0.5),
+            scale: graphics::Point2::new(sprite.scale, sprite.scale),
+            ..graphics::DrawParam::default()
+        };
+
+        graphics::draw_ex(context, image, drawParam)?;
+    }
+
+    Ok(())
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pixels from rows of text, `#` is solid and anything else is clear
+    fn pixels(rows: &[&str]) -> Pixels {
+        let rgba = rows.iter()
+            .flat_map(|row| row.chars())
+            .flat_map(|c| vec![255, 255, 255, if c == '#' { 255 } else { 0 }])
+            .collect();
+
+        Pixels {
+            width: rows[0].len(),
+            height: rows.len(),
+            rgba: rgba,
+        }
+    }
+
+    /// Left, top, right and bottom of the outline
+    fn bounds(contour: &[geometry::Point]) -> [f32; 4] {
+        contour.iter().fold([std::f32::MAX, std::f32::MAX, std::f32::MIN, std::f32::MIN], |b, p| {
+            [b[0].min(p[0]), b[1].min(p[1]), b[2].max(p[0]), b[3].max(p[1])]
+        })
+    }
+
+    /// Whether the outline hugs the edges of the pixels from `min` to `max`
+    fn around(contour: &[geometry::Point], min: [f32; 2], max: [f32; 2]) -> bool {
+        let close = |a: f32, b: f32| (a - b).abs() < 0.01;
+        let [left, top, right, bottom] = bounds(contour);
+        close(left, min[0]) && close(top, min[1]) && close(right, max[0]) && close(bottom, max[1])
+    }
+
+    #[test]
+    fn a_solid_block_has_one_outline_around_it() {
+        let contours = pixels(&["###", "###", "###"]).contours();
+
+        assert_eq!(contours.len(), 1);
+        assert!(around(&contours[0], [0.0, 0.0], [3.0, 3.0]));
+        assert!(geometry::area(&contours[0]) > 8.0);
+    }
+
+    #[test]
+    fn a_hole_winds_the_other_way() {
+        let mut contours = pixels(&["#####", "#####", "##.##", "#####", "#####"]).contours();
+        contours.sort_by(|a, b| geometry::area(b).partial_cmp(&geometry::area(a)).unwrap());
+
+        assert_eq!(contours.len(), 2);
+        assert!(around(&contours[0], [0.0, 0.0], [5.0, 5.0]));
+        assert!(around(&contours[1], [2.0, 2.0], [3.0, 3.0]));
+        assert_ne!(geometry::is_ccw(&contours[0]), geometry::is_ccw(&contours[1]));
+    }
+
+    #[test]
+    fn a_diagonal_saddle_splits_into_two_outlines() {
+        let mut contours = pixels(&["#.", ".#"]).contours();
+        contours.sort_by(|a, b| bounds(a)[0].partial_cmp(&bounds(b)[0]).unwrap());
+
+        assert_eq!(contours.len(), 2);
+        assert!(around(&contours[0], [0.0, 0.0], [1.0, 1.0]));
+        assert!(around(&contours[1], [1.0, 1.0], [2.0, 2.0]));
+        assert_eq!(geometry::is_ccw(&contours[0]), geometry::is_ccw(&contours[1]));
+    }
+
+    #[test]
+    fn clear_pixels_have_no_outline() {
+        assert!(pixels(&["...", "..."]).contours().is_empty());
+    }
+}
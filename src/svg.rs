@@ -0,0 +1,925 @@
+/**
+ *
+ * SVG import.
+ *
+ * Turns the shapes of an SVG drawing into a `SceneFile`, so drawings are
+ * loaded, spawned and undone like any other scene. Paths, rectangles,
+ * circles, ellipses, lines, polylines and polygons are read, curves and
+ * arcs are flattened into line segments no further than `FLATTEN_TOLERANCE`
+ * world units from the curve. Group and element transforms are applied.
+ *
+ * Closed shapes become solid bodies split into convex pieces, open ones
+ * static polylines. The words in an element's `class` and `id`, the id
+ * split at dashes and underscores, pick its material and status, and are
+ * inherited from enclosing groups:
+ *
+ * ```ignore
+ * <g class="static">
+ *     <path id="floor-ice" d="M 0 300 C 100 250 300 250 400 300" />
+ *     <rect class="rubber dynamic" x="180" y="40" width="40" height="40" />
+ * </g>
+ * ```
+ *
+ * A word naming a material in the `MaterialLibrary` sets the material,
+ * `dynamic`, `static` and `kinematic` the status. Bodies are static unless
+ * told otherwise. Bodies are filled with their fill color, or drawn with
+ * their stroke when they have no fill.
+ *
+ * The drawing is centered on the origin. Its width and height, in any CSS
+ * unit, are converted to pixels and scaled by the `scale` given to `load`,
+ * the world units per pixel.
+ *
+ */
+
+use std::fs::File;
+use std::io::BufReader;
+
+use log::*;
+
+use xml::attribute::OwnedAttribute;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::builder::Status;
+use crate::geometry::{self, Point};
+use crate::material::MaterialLibrary;
+use crate::render::{Render, RenderStyle};
+use crate::scenefile::{BodyDesc, SceneFile};
+use crate::shape::ShapeDesc;
+
+/// Furthest flattened curves stray from the real curve, in world units
+const FLATTEN_TOLERANCE: f32 = 0.5;
+
+/// Curve subdivisions never go deeper than this
+const MAX_SUBDIVISIONS: u32 = 16;
+
+/// Closed shapes smaller than this, in world units, are drawn as outlines
+const MIN_AREA: f32 = 1.0;
+
+const DEFAULT_FILL: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+
+/// Elements whose content is never drawn directly
+const HIDDEN_ELEMENTS: [&str; 9] = ["defs", "clipPath", "mask", "symbol", "marker", "pattern", "metadata", "title", "style"];
+
+/// Reads a drawing and converts its shapes
+pub fn load(path: &str, library: &MaterialLibrary, scale: f32) -> Result<SceneFile, String> {
+    let file = File::open(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+
+    let mut bodies = Vec::new();
+    let mut stack: Vec<Style> = Vec::new();
+    let mut hidden = 0;
+
+    for event in EventReader::new(BufReader::new(file)) {
+        match event.map_err(|e| format!("Could not parse {}: {}", path, e))? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                let element = name.local_name.as_str();
+                if hidden > 0 || HIDDEN_ELEMENTS.contains(&element) {
+                    hidden += 1;
+                    continue;
+                }
+
+                let style = match stack.last() {
+                    Some(parent) => parent.derive(&attributes),
+                    None => Style::root(&attributes, scale).derive(&attributes),
+                };
+
+                bodies.extend(element_bodies(element, &attributes, &style, library));
+                stack.push(style);
+            },
+
+            XmlEvent::EndElement { .. } => {
+                if hidden > 0 {
+                    hidden -= 1;
+                } else {
+                    stack.pop();
+                }
+            },
+
+            _ => {},
+        }
+    }
+
+    debug!("Converted {} into {} bodies", path, bodies.len());
+
+    Ok(SceneFile {
+        bodies: bodies,
+        ..SceneFile::default()
+    })
+}
+
+
+
+/// A 2D affine transform as in SVG, x' = a x + c y + e and y' = b x + d y + f
+#[derive(Clone, Copy, Debug)]
+struct Affine([f32; 6]);
+
+impl Affine {
+    fn identity() -> Affine {
+        Affine([1.0, 0.0, 0.0, 1.0, 0.0, 0.0])
+    }
+
+    fn translate(x: f32, y: f32) -> Affine {
+        Affine([1.0, 0.0, 0.0, 1.0, x, y])
+    }
+
+    fn scale(x: f32, y: f32) -> Affine {
+        Affine([x, 0.0, 0.0, y, 0.0, 0.0])
+    }
+
+    fn rotate(degrees: f32) -> Affine {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Affine([cos, sin, - sin, cos, 0.0, 0.0])
+    }
+
+    /// Applies `other` first, then this
+    fn then(&self, other: &Affine) -> Affine {
+        let [a, b, c, d, e, f] = self.0;
+        let [oa, ob, oc, od, oe, of] = other.0;
+        Affine([
+            a * oa + c * ob,
+            b * oa + d * ob,
+            a * oc + c * od,
+            b * oc + d * od,
+            a * oe + c * of + e,
+            b * oe + d * of + f,
+        ])
+    }
+
+    fn apply(&self, p: &Point) -> Point {
+        let [a, b, c, d, e, f] = self.0;
+        [a * p[0] + c * p[1] + e, b * p[0] + d * p[1] + f]
+    }
+
+    /// How much lengths grow on average
+    fn stretch(&self) -> f32 {
+        let [a, b, c, d, _, _] = self.0;
+        (a * d - b * c).abs().sqrt()
+    }
+
+    /// Whether circles stay circles
+    fn is_similarity(&self) -> bool {
+        let [a, b, c, d, _, _] = self.0;
+        (a - d).abs() < 1e-4 && (b + c).abs() < 1e-4
+    }
+
+    /**
+     * Parses a transform list like `translate(10 20) rotate(45)`. Anything
+     * it doesn't understand is left out.
+     */
+    fn parse(text: &str) -> Affine {
+        let mut result = Affine::identity();
+        for item in text.split(')') {
+            let mut parts = item.splitn(2, '(');
+            let name = parts.next().unwrap_or("").trim_matches(|c: char| c == ',' || c.is_whitespace());
+            let args = numbers(parts.next().unwrap_or(""));
+
+            let transform = match (name, args.as_slice()) {
+                ("", []) => continue,
+                ("matrix", &[a, b, c, d, e, f]) => Affine([a, b, c, d, e, f]),
+                ("translate", &[x]) => Affine::translate(x, 0.0),
+                ("translate", &[x, y]) => Affine::translate(x, y),
+                ("scale", &[s]) => Affine::scale(s, s),
+                ("scale", &[x, y]) => Affine::scale(x, y),
+                ("rotate", &[angle]) => Affine::rotate(angle),
+                ("rotate", &[angle, x, y]) => Affine::translate(x, y).then(&Affine::rotate(angle)).then(&Affine::translate(- x, - y)),
+                ("skewX", &[angle]) => Affine([1.0, 0.0, angle.to_radians().tan(), 1.0, 0.0, 0.0]),
+                ("skewY", &[angle]) => Affine([1.0, angle.to_radians().tan(), 0.0, 1.0, 0.0, 0.0]),
+                _ => {
+                    warn!("Ignoring SVG transform {}({:?})", name, args);
+                    continue;
+                },
+            };
+
+            result = result.then(&transform);
+        }
+
+        result
+    }
+}
+
+
+
+/// Reads the numbers, flags and commands of path data and number lists
+struct Tokens<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(text: &'a str) -> Tokens<'a> {
+        Tokens { text: text.as_bytes(), pos: 0 }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).cloned()
+    }
+
+    fn skip_separators(&mut self) {
+        while self.peek().map_or(false, |c| c == b',' || c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_digits(&mut self) -> bool {
+        let start = self.pos;
+        while self.peek().map_or(false, |c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.pos > start
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_separators();
+        self.pos >= self.text.len()
+    }
+
+    fn command(&mut self) -> Option<char> {
+        self.skip_separators();
+        match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() => {
+                self.pos += 1;
+                Some(c as char)
+            },
+            _ => None,
+        }
+    }
+
+    /// A number, which may run straight into the next like `1-2` or `.5.5`
+    fn number(&mut self) -> Option<f32> {
+        self.skip_separators();
+        let start = self.pos;
+
+        if let Some(b'+') | Some(b'-') = self.peek() {
+            self.pos += 1;
+        }
+
+        let mut digits = self.skip_digits();
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            digits |= self.skip_digits();
+        }
+
+        if !digits {
+            self.pos = start;
+            return None;
+        }
+
+        if let Some(b'e') | Some(b'E') = self.peek() {
+            let mantissa = self.pos;
+            self.pos += 1;
+            if let Some(b'+') | Some(b'-') = self.peek() {
+                self.pos += 1;
+            }
+            if !self.skip_digits() {
+                self.pos = mantissa;
+            }
+        }
+
+        std::str::from_utf8(&self.text[start..self.pos]).ok()?.parse().ok()
+    }
+
+    /// An arc flag, a single 0 or 1 that needs no separator
+    fn flag(&mut self) -> Option<bool> {
+        self.skip_separators();
+        let flag = match self.peek()? {
+            b'0' => false,
+            b'1' => true,
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(flag)
+    }
+
+    fn point(&mut self) -> Option<Point> {
+        Some([self.number()?, self.number()?])
+    }
+}
+
+fn numbers(text: &str) -> Vec<f32> {
+    let mut tokens = Tokens::new(text);
+    let mut result = Vec::new();
+    while let Some(n) = tokens.number() {
+        result.push(n);
+    }
+    result
+}
+
+/// The number at the start of a length, ignoring its unit
+fn length(text: &str) -> Option<f32> {
+    Tokens::new(text).number()
+}
+
+/// A length in CSS pixels
+fn pixels(text: &str) -> Option<f32> {
+    let value = length(text)?;
+    let unit = text.trim().trim_start_matches(|c: char| c.is_ascii_digit() || "+-.eE".contains(c));
+    let factor = match unit {
+        "" | "px" => 1.0,
+        "in" => 96.0,
+        "cm" => 96.0 / 2.54,
+        "mm" => 96.0 / 25.4,
+        "pt" => 96.0 / 72.0,
+        "pc" => 16.0,
+        _ => {
+            warn!("Unknown SVG unit {}, using pixels", unit);
+            1.0
+        },
+    };
+
+    Some(value * factor)
+}
+
+/// A `#rgb` or `#rrggbb` color. `Some(None)` for `none`, `None` when it can't be read.
+fn color(text: &str) -> Option<Option<[f32; 4]>> {
+    let text = text.trim();
+    if text == "none" {
+        return Some(None);
+    }
+
+    let hex = text.trim_start_matches('#');
+    let digits: Vec<u32> = hex.chars().map(|c| c.to_digit(16)).collect::<Option<_>>()?;
+    let channels = match digits.len() {
+        3 => [digits[0] * 17, digits[1] * 17, digits[2] * 17],
+        6 => [digits[0] * 16 + digits[1], digits[2] * 16 + digits[3], digits[4] * 16 + digits[5]],
+        _ => return None,
+    };
+
+    Some(Some([channels[0] as f32 / 255.0, channels[1] as f32 / 255.0, channels[2] as f32 / 255.0, 1.0]))
+}
+
+fn attribute<'a>(attributes: &'a [OwnedAttribute], name: &str) -> Option<&'a str> {
+    attributes.iter()
+        .find(|a| a.name.local_name == name)
+        .map(|a| a.value.as_str())
+}
+
+fn number_attribute(attributes: &[OwnedAttribute], name: &str) -> f32 {
+    attribute(attributes, name).and_then(length).unwrap_or(0.0)
+}
+
+
+
+/// What an element inherits from the groups around it
+#[derive(Clone, Debug)]
+struct Style {
+    /// From the element's own units to world units
+    transform: Affine,
+
+    /// Words from the classes and ids, outermost first
+    words: Vec<String>,
+
+    fill: Option<[f32; 4]>,
+    stroke: Option<[f32; 4]>,
+    stroke_width: f32,
+}
+
+impl Style {
+    /**
+     * The style of the root `svg` element. Its size and view box turn
+     * user units into pixels and the view box is centered on the origin.
+     */
+    fn root(attributes: &[OwnedAttribute], scale: f32) -> Style {
+        let width = attribute(attributes, "width").and_then(pixels);
+        let height = attribute(attributes, "height").and_then(pixels);
+        let viewBox = attribute(attributes, "viewBox").map(numbers).filter(|v| v.len() == 4);
+
+        let (center, pixelsPerUnit) = match (viewBox.as_ref(), width) {
+            (Some(v), Some(width)) if v[2] > 0.0 => ([v[0] + v[2] / 2.0, v[1] + v[3] / 2.0], width / v[2]),
+            (Some(v), None) => ([v[0] + v[2] / 2.0, v[1] + v[3] / 2.0], 1.0),
+            (_, width) => ([width.unwrap_or(0.0) / 2.0, height.unwrap_or(0.0) / 2.0], 1.0),
+        };
+
+        let s = scale * pixelsPerUnit;
+        Style {
+            transform: Affine::scale(s, s).then(&Affine::translate(- center[0], - center[1])),
+            words: Vec::new(),
+            fill: Some(DEFAULT_FILL),
+            stroke: None,
+            stroke_width: 1.0,
+        }
+    }
+
+    /// The style of a child element with these attributes
+    fn derive(&self, attributes: &[OwnedAttribute]) -> Style {
+        let mut style = self.clone();
+
+        if let Some(transform) = attribute(attributes, "transform") {
+            style.transform = self.transform.then(&Affine::parse(transform));
+        }
+
+        if let Some(class) = attribute(attributes, "class") {
+            style.words.extend(class.split_whitespace().map(|w| w.to_lowercase()));
+        }
+
+        if let Some(id) = attribute(attributes, "id") {
+            style.words.extend(id.split(|c| c == '-' || c == '_').map(|w| w.to_lowercase()));
+        }
+
+        // presentation attributes, overridden by the style attribute
+        let mut properties: Vec<(&str, &str)> = ["fill", "stroke", "stroke-width"].iter()
+            .filter_map(|name| attribute(attributes, name).map(|value| (*name, value)))
+            .collect();
+
+        if let Some(css) = attribute(attributes, "style") {
+            properties.extend(css.split(';').filter_map(|declaration| {
+                let mut parts = declaration.splitn(2, ':');
+                Some((parts.next()?.trim(), parts.next()?.trim()))
+            }));
+        }
+
+        for (name, value) in properties {
+            match name {
+                "fill" => style.fill = color(value).unwrap_or(style.fill),
+                "stroke" => style.stroke = color(value).unwrap_or(style.stroke),
+                "stroke-width" => style.stroke_width = length(value).unwrap_or(style.stroke_width),
+                _ => {},
+            }
+        }
+
+        style
+    }
+
+    /// The last word naming a material wins, like the last status word
+    fn material(&self, library: &MaterialLibrary) -> Option<String> {
+        self.words.iter().rev().find(|w| library.contains(w)).cloned()
+    }
+
+    fn status(&self) -> Status {
+        self.words.iter().rev()
+            .filter_map(|w| match w.as_str() {
+                "dynamic" => Some(Status::Dynamic),
+                "static" => Some(Status::Static),
+                "kinematic" => Some(Status::Kinematic),
+                _ => None,
+            })
+            .next()
+            .unwrap_or(Status::Static)
+    }
+
+    /// How far flattened curves may stray, in the element's own units
+    fn tolerance(&self) -> f32 {
+        FLATTEN_TOLERANCE / self.transform.stretch().max(std::f32::EPSILON)
+    }
+}
+
+
+
+/// Distance from a point to the line segment between `a` and `b`
+fn distance(p: &Point, a: &Point, b: &Point) -> f32 {
+    let ab = [b[0] - a[0], b[1] - a[1]];
+    let length2 = ab[0] * ab[0] + ab[1] * ab[1];
+    let t = if length2 > 0.0 {
+        (((p[0] - a[0]) * ab[0] + (p[1] - a[1]) * ab[1]) / length2).max(0.0).min(1.0)
+    } else {
+        0.0
+    };
+    (p[0] - a[0] - ab[0] * t).hypot(p[1] - a[1] - ab[1] * t)
+}
+
+fn lerp(a: &Point, b: &Point, t: f32) -> Point {
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t]
+}
+
+/// Flattens a cubic Bézier by halving it until every piece is flat enough
+fn cubic(p: [Point; 4], tolerance: f32, depth: u32, out: &mut Vec<Point>) {
+    let flatness = distance(&p[1], &p[0], &p[3]).max(distance(&p[2], &p[0], &p[3]));
+    if flatness <= tolerance || depth >= MAX_SUBDIVISIONS {
+        out.push(p[3]);
+        return;
+    }
+
+    let (ab, bc, cd) = (lerp(&p[0], &p[1], 0.5), lerp(&p[1], &p[2], 0.5), lerp(&p[2], &p[3], 0.5));
+    let (abc, bcd) = (lerp(&ab, &bc, 0.5), lerp(&bc, &cd, 0.5));
+    let middle = lerp(&abc, &bcd, 0.5);
+
+    cubic([p[0], ab, abc, middle], tolerance, depth + 1, out);
+    cubic([middle, bcd, cd, p[3]], tolerance, depth + 1, out);
+}
+
+/// A quadratic Bézier is the cubic with its controls two thirds of the way to the quadratic's
+fn quadratic(from: &Point, control: &Point, to: &Point, tolerance: f32, out: &mut Vec<Point>) {
+    cubic([*from, lerp(from, control, 2.0 / 3.0), lerp(to, control, 2.0 / 3.0), *to], tolerance, 0, out);
+}
+
+/// Segments of an elliptical arc needed to stay within the tolerance
+fn arc_segments(radius: f32, sweep: f32, tolerance: f32) -> usize {
+    let step = 2.0 * (1.0 - (tolerance / radius.max(std::f32::EPSILON)).min(1.0)).acos();
+    (sweep.abs() / step.max(0.01)).ceil().max(1.0) as usize
+}
+
+/**
+ * Flattens an SVG elliptical arc given by its end points and its large arc
+ * and sweep flags, converted to its center as in the SVG implementation notes.
+ */
+fn arc(from: &Point, to: &Point, radii: Point, rotation: f32, (large, sweep): (bool, bool), tolerance: f32, out: &mut Vec<Point>) {
+    use std::f32::consts::PI;
+
+    let (mut rx, mut ry) = (radii[0].abs(), radii[1].abs());
+    if rx < std::f32::EPSILON || ry < std::f32::EPSILON || from == to {
+        out.push(*to);
+        return;
+    }
+
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    let (dx, dy) = ((from[0] - to[0]) / 2.0, (from[1] - to[1]) / 2.0);
+    let (x1, y1) = (cos * dx + sin * dy, - sin * dx + cos * dy);
+
+    // radii too small to reach are scaled up until they just do
+    let reach = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+    if reach > 1.0 {
+        rx *= reach.sqrt();
+        ry *= reach.sqrt();
+    }
+
+    let numerator = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+    let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+    let mut k = (numerator / denominator).max(0.0).sqrt();
+    if large == sweep {
+        k = - k;
+    }
+
+    let (cx1, cy1) = (k * rx * y1 / ry, - k * ry * x1 / rx);
+    let center = [cos * cx1 - sin * cy1 + (from[0] + to[0]) / 2.0, sin * cx1 + cos * cy1 + (from[1] + to[1]) / 2.0];
+
+    let start = ((y1 - cy1) / ry).atan2((x1 - cx1) / rx);
+    let end = ((- y1 - cy1) / ry).atan2((- x1 - cx1) / rx);
+    let mut delta = end - start;
+    if sweep && delta < 0.0 {
+        delta += 2.0 * PI;
+    } else if !sweep && delta > 0.0 {
+        delta -= 2.0 * PI;
+    }
+
+    let segments = arc_segments(rx.max(ry), delta, tolerance);
+    for i in 1..segments {
+        let (s, c) = (start + delta * i as f32 / segments as f32).sin_cos();
+        let (x, y) = (rx * c, ry * s);
+        out.push([cos * x - sin * y + center[0], sin * x + cos * y + center[1]]);
+    }
+    out.push(*to);
+}
+
+/**
+ * The subpaths of path data as lists of points, each with whether it was
+ * closed. Parsing stops at the first error, keeping what came before.
+ */
+fn path(data: &str, tolerance: f32) -> Vec<(Vec<Point>, bool)> {
+    let mut tokens = Tokens::new(data);
+    let mut subpaths = Vec::new();
+    let mut current: Vec<Point> = Vec::new();
+    let mut pen = [0.0, 0.0];
+    let mut start = [0.0, 0.0];
+    let mut previous = ' ';
+
+    // the last control point, for the smooth curve commands to mirror
+    let mut cubicControl: Option<Point> = None;
+    let mut quadraticControl: Option<Point> = None;
+
+    loop {
+        let command = match tokens.command() {
+            Some(command) => command,
+            None if !tokens.at_end() && previous != ' ' => match previous {
+                'M' => 'L',
+                'm' => 'l',
+                // closing takes no numbers, repeating it would never move on
+                'Z' | 'z' => {
+                    warn!("SVG path data continues after {} without a command", previous);
+                    break;
+                },
+                previous => previous,
+            },
+            None => break,
+        };
+
+        let offset = if command.is_ascii_lowercase() { pen } else { [0.0, 0.0] };
+        let relative = |p: Point| [p[0] + offset[0], p[1] + offset[1]];
+        let mirror = |control: Option<Point>| control.map_or(pen, |c| [2.0 * pen[0] - c[0], 2.0 * pen[1] - c[1]]);
+
+        let mut nextCubic = None;
+        let mut nextQuadratic = None;
+
+        let parsed = match command.to_ascii_uppercase() {
+            'M' => tokens.point().map(|p| {
+                let p = relative(p);
+                if current.len() > 1 {
+                    subpaths.push((std::mem::replace(&mut current, Vec::new()), false));
+                }
+                current = vec![p];
+                start = p;
+                p
+            }),
+
+            'L' => tokens.point().map(|p| {
+                let p = relative(p);
+                current.push(p);
+                p
+            }),
+
+            'H' => tokens.number().map(|x| {
+                let p = [x + offset[0], pen[1]];
+                current.push(p);
+                p
+            }),
+
+            'V' => tokens.number().map(|y| {
+                let p = [pen[0], y + offset[1]];
+                current.push(p);
+                p
+            }),
+
+            'C' => (|| Some((tokens.point()?, tokens.point()?, tokens.point()?)))().map(|(c1, c2, p)| {
+                let (c1, c2, p) = (relative(c1), relative(c2), relative(p));
+                cubic([pen, c1, c2, p], tolerance, 0, &mut current);
+                nextCubic = Some(c2);
+                p
+            }),
+
+            'S' => (|| Some((tokens.point()?, tokens.point()?)))().map(|(c2, p)| {
+                let (c1, c2, p) = (mirror(cubicControl), relative(c2), relative(p));
+                cubic([pen, c1, c2, p], tolerance, 0, &mut current);
+                nextCubic = Some(c2);
+                p
+            }),
+
+            'Q' => (|| Some((tokens.point()?, tokens.point()?)))().map(|(c, p)| {
+                let (c, p) = (relative(c), relative(p));
+                quadratic(&pen, &c, &p, tolerance, &mut current);
+                nextQuadratic = Some(c);
+                p
+            }),
+
+            'T' => tokens.point().map(|p| {
+                let (c, p) = (mirror(quadraticControl), relative(p));
+                quadratic(&pen, &c, &p, tolerance, &mut current);
+                nextQuadratic = Some(c);
+                p
+            }),
+
+            'A' => (|| Some((tokens.point()?, tokens.number()?, tokens.flag()?, tokens.flag()?, tokens.point()?)))()
+                .map(|(radii, rotation, large, sweep, p)| {
+                    let p = relative(p);
+                    arc(&pen, &p, radii, rotation, (large, sweep), tolerance, &mut current);
+                    p
+                }),
+
+            'Z' => {
+                if current.len() > 2 {
+                    subpaths.push((std::mem::replace(&mut current, Vec::new()), true));
+                }
+                current = vec![start];
+                Some(start)
+            },
+
+            _ => {
+                warn!("Unknown SVG path command {}", command);
+                None
+            },
+        };
+
+        match parsed {
+            Some(p) => pen = p,
+            None => {
+                warn!("Could not read SVG path data after {:?}", pen);
+                break;
+            },
+        }
+
+        cubicControl = nextCubic;
+        quadraticControl = nextQuadratic;
+        previous = command;
+    }
+
+    if current.len() > 1 {
+        subpaths.push((current, false));
+    }
+
+    subpaths
+}
+
+/// Points around an ellipse, as many as the tolerance needs
+fn ellipse(center: &Point, rx: f32, ry: f32, tolerance: f32) -> Vec<Point> {
+    let segments = arc_segments(rx.max(ry), 2.0 * std::f32::consts::PI, tolerance).max(8);
+    (0..segments)
+        .map(|i| {
+            let (s, c) = (i as f32 / segments as f32 * 2.0 * std::f32::consts::PI).sin_cos();
+            [center[0] + rx * c, center[1] + ry * s]
+        })
+        .collect()
+}
+
+/// The bodies for a drawing element, none for groups and unknown elements
+fn element_bodies(element: &str, attributes: &[OwnedAttribute], style: &Style, library: &MaterialLibrary) -> Vec<BodyDesc> {
+    let get = |name: &str| number_attribute(attributes, name);
+    let tolerance = style.tolerance();
+
+    let subpaths = match element {
+        "path" => path(attribute(attributes, "d").unwrap_or(""), tolerance),
+
+        "rect" => {
+            let (x, y, w, h) = (get("x"), get("y"), get("width"), get("height"));
+            vec![(vec![[x, y], [x + w, y], [x + w, y + h], [x, y + h]], true)]
+        },
+
+        // a size of zero disables drawing the element
+        "circle" if !(get("r") > 0.0) => return Vec::new(),
+        "ellipse" if !(get("rx") > 0.0 && get("ry") > 0.0) => return Vec::new(),
+
+        "circle" if style.transform.is_similarity() => {
+            let center = style.transform.apply(&[get("cx"), get("cy")]);
+            let radius = get("r") * style.transform.stretch();
+
+            let mut body = BodyDesc::new(ShapeDesc::Ball { radius: radius });
+            body.position = (center[0], center[1]);
+            return vec![finish(body, style, library, true)];
+        },
+
+        "circle" => vec![(ellipse(&[get("cx"), get("cy")], get("r"), get("r"), tolerance), true)],
+        "ellipse" => vec![(ellipse(&[get("cx"), get("cy")], get("rx"), get("ry"), tolerance), true)],
+        "line" => vec![(vec![[get("x1"), get("y1")], [get("x2"), get("y2")]], false)],
+
+        "polyline" | "polygon" => {
+            let coordinates = numbers(attribute(attributes, "points").unwrap_or(""));
+            let points = coordinates.chunks(2).filter(|c| c.len() == 2).map(|c| [c[0], c[1]]).collect();
+            vec![(points, element == "polygon")]
+        },
+
+        _ => return Vec::new(),
+    };
+
+    subpaths.into_iter()
+        .filter_map(|(points, closed)| {
+            let mut points: Vec<Point> = points.iter().map(|p| style.transform.apply(p)).collect();
+            if closed && points.len() > 1 && points.first() == points.last() {
+                points.pop();
+            }
+
+            subpath_body(points, closed, style, library)
+        })
+        .collect()
+}
+
+/**
+ * A solid body for a closed subpath with an area, a static polyline for
+ * an open one or one that crosses itself.
+ */
+fn subpath_body(mut points: Vec<Point>, closed: bool, style: &Style, library: &MaterialLibrary) -> Option<BodyDesc> {
+    if closed && points.len() >= 3 && geometry::area(&points) >= MIN_AREA {
+        match ShapeDesc::polygon(&points) {
+            Some((shape, center)) => {
+                let mut body = BodyDesc::new(shape);
+                body.position = (center[0], center[1]);
+                return Some(finish(body, style, library, true));
+            },
+
+            None => warn!("An SVG shape crosses itself, importing its outline"),
+        }
+    }
+
+    if points.len() < 2 {
+        return None;
+    }
+
+    if closed {
+        points.push(points[0]);
+    }
+
+    Some(finish(BodyDesc::new(ShapeDesc::Polyline { points: points }), style, library, false))
+}
+
+/// Sets the material, status and look. Bodies without an area are always static.
+fn finish(mut body: BodyDesc, style: &Style, library: &MaterialLibrary, solid: bool) -> BodyDesc {
+    if let Some(material) = style.material(library) {
+        body.material = material;
+    }
+
+    body.status = if solid { style.status() } else { Status::Static };
+
+    let width = (style.stroke_width * style.transform.stretch()).max(1.0);
+    body.render = match (solid, style.fill, style.stroke) {
+        (true, Some(fill), _) => Render::new(RenderStyle::Fill, fill),
+        (_, _, Some(stroke)) => Render::new(RenderStyle::Line(width), stroke),
+        (_, fill, None) => Render::new(RenderStyle::Line(width), fill.unwrap_or(DEFAULT_FILL)),
+    };
+
+    body
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: &Point, b: &Point) -> bool {
+        (a[0] - b[0]).abs() < 1e-3 && (a[1] - b[1]).abs() < 1e-3
+    }
+
+    fn radius(p: &Point, center: &Point) -> f32 {
+        ((p[0] - center[0]).powi(2) + (p[1] - center[1]).powi(2)).sqrt()
+    }
+
+    fn attributes(pairs: &[(&str, &str)]) -> Vec<OwnedAttribute> {
+        pairs.iter()
+            .map(|(name, value)| OwnedAttribute { name: xml::name::OwnedName::local(*name), value: value.to_string() })
+            .collect()
+    }
+
+    #[test]
+    fn path_reads_absolute_and_relative_commands() {
+        let subpaths = path("M 0 0 L 10 0 L 10 10 Z m 5 5 l 10 0 v 10 h -10 z", 0.5);
+
+        assert_eq!(subpaths.len(), 2);
+        assert_eq!(subpaths[0], (vec![[0.0, 0.0], [10.0, 0.0], [10.0, 10.0]], true));
+        assert_eq!(subpaths[1], (vec![[5.0, 5.0], [15.0, 5.0], [15.0, 15.0], [5.0, 15.0]], true));
+    }
+
+    #[test]
+    fn path_repeats_the_previous_command() {
+        let subpaths = path("M 0 0 10 0 10 10", 0.5);
+        assert_eq!(subpaths, vec![(vec![[0.0, 0.0], [10.0, 0.0], [10.0, 10.0]], false)]);
+    }
+
+    #[test]
+    fn path_stops_at_numbers_after_closing() {
+        let subpaths = path("M 0 0 L 10 0 L 10 10 Z 5 5", 0.5);
+        assert_eq!(subpaths, vec![(vec![[0.0, 0.0], [10.0, 0.0], [10.0, 10.0]], true)]);
+    }
+
+    #[test]
+    fn path_keeps_what_came_before_an_error() {
+        let subpaths = path("M 0 0 L 10 0 L 10", 0.5);
+        assert_eq!(subpaths, vec![(vec![[0.0, 0.0], [10.0, 0.0]], false)]);
+    }
+
+    #[test]
+    fn parse_applies_transforms_right_to_left() {
+        let transform = Affine::parse("translate(10 20) scale(2)");
+        assert!(close(&transform.apply(&[1.0, 1.0]), &[12.0, 22.0]));
+
+        let transform = Affine::parse("matrix(1,0,0,1,3,4)");
+        assert!(close(&transform.apply(&[0.0, 0.0]), &[3.0, 4.0]));
+    }
+
+    #[test]
+    fn parse_rotates_around_a_point() {
+        let transform = Affine::parse("rotate(90)");
+        assert!(close(&transform.apply(&[1.0, 0.0]), &[0.0, 1.0]));
+
+        let transform = Affine::parse("rotate(90 5 5)");
+        assert!(close(&transform.apply(&[5.0, 5.0]), &[5.0, 5.0]));
+        assert!(close(&transform.apply(&[6.0, 5.0]), &[5.0, 6.0]));
+    }
+
+    #[test]
+    fn parse_skips_unknown_transforms() {
+        let transform = Affine::parse("wobble(1) translate(1 2)");
+        assert!(close(&transform.apply(&[0.0, 0.0]), &[1.0, 2.0]));
+    }
+
+    #[test]
+    fn arc_follows_the_circle_and_ends_on_the_target() {
+        let mut out = Vec::new();
+        arc(&[0.0, 0.0], &[20.0, 0.0], [10.0, 10.0], 0.0, (false, true), 0.1, &mut out);
+
+        assert!(out.len() > 2);
+        assert!(close(out.last().unwrap(), &[20.0, 0.0]));
+        for p in out.iter() {
+            assert!((radius(p, &[10.0, 0.0]) - 10.0).abs() < 1e-3);
+        }
+
+        // sweeping clockwise on screen from the left goes over the top
+        assert!(out.iter().any(|p| p[1] < -9.0));
+    }
+
+    #[test]
+    fn arc_grows_radii_that_fall_short() {
+        let mut out = Vec::new();
+        arc(&[0.0, 0.0], &[20.0, 0.0], [1.0, 1.0], 0.0, (false, false), 0.1, &mut out);
+
+        for p in out.iter() {
+            assert!((radius(p, &[10.0, 0.0]) - 10.0).abs() < 1e-3);
+        }
+        assert!(out.iter().any(|p| p[1] > 9.0));
+    }
+
+    #[test]
+    fn arc_without_radius_is_a_line() {
+        let mut out = Vec::new();
+        arc(&[0.0, 0.0], &[20.0, 0.0], [0.0, 10.0], 0.0, (false, true), 0.1, &mut out);
+        assert_eq!(out, vec![[20.0, 0.0]]);
+    }
+
+    #[test]
+    fn elements_without_a_size_have_no_body() {
+        let library = MaterialLibrary::default();
+        let style = Style::root(&[], 1.0);
+
+        let circle = attributes(&[("r", "0")]);
+        assert!(element_bodies("circle", &circle, &style, &library).is_empty());
+
+        let ellipse = attributes(&[("rx", "5"), ("ry", "0")]);
+        assert!(element_bodies("ellipse", &ellipse, &style, &library).is_empty());
+
+        let circle = attributes(&[("r", "5")]);
+        assert_eq!(element_bodies("circle", &circle, &style, &library).len(), 1);
+    }
+}
@@ -12,6 +12,8 @@
  *
  */
 
+use std::collections::HashMap;
+
 use log::*;
 
 use specs;
@@ -51,13 +53,106 @@ pub struct RigidBody {
 
 
 
+/**
+ * Marks a body that is a link of a multibody, held to its parent by a
+ * joint. The links of a multibody are removed together, along with the
+ * entities of all its parts, once any of them is destroyed.
+ */
+#[derive(Clone, Copy, Debug, Component)]
+#[storage(HashMapStorage)]
+pub struct Link {
+    /// The entity of the root link, which is jointed to the ground
+    pub root: specs::Entity,
+}
+
+
+
+/// Area covered by a `ForceGenerator`, centered on its `center`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Region {
+    Box { half_extents: nalgebra::Vector2<f32> },
+    Circle { radius: f32 },
+}
+
+impl Region {
+    pub fn contains(&self, offset: &nalgebra::Vector2<f32>) -> bool {
+        match self {
+            Region::Box { half_extents } => offset.x.abs() <= half_extents.x && offset.y.abs() <= half_extents.y,
+            Region::Circle { radius } => offset.norm() <= *radius,
+        }
+    }
+}
+
+
+
+/// What a `ForceGenerator` does to the bodies inside it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ForceKind {
+    /// Pushes everything along the direction
+    Wind { direction: nalgebra::Vector2<f32> },
+
+    /// Spins everything clockwise around the center
+    Vortex,
+
+    /// Pulls everything towards the center, or pushes away when negative
+    Attractor,
+
+    /// Slows everything down in proportion to its speed
+    Drag,
+}
+
+impl ForceKind {
+    pub fn next(&self) -> ForceKind {
+        match self {
+            ForceKind::Wind { .. } => ForceKind::Vortex,
+            ForceKind::Vortex => ForceKind::Attractor,
+            ForceKind::Attractor => ForceKind::Drag,
+            ForceKind::Drag => ForceKind::Wind { direction: nalgebra::Vector2::x() },
+        }
+    }
+
+    /// Wind turned by `angle`, other kinds have no direction to turn
+    pub fn turned(&self, angle: f32) -> ForceKind {
+        match self {
+            ForceKind::Wind { direction } => ForceKind::Wind { direction: nalgebra::UnitComplex::new(angle) * direction },
+            other => *other,
+        }
+    }
+}
+
+
+
 /// Allows controlling physics bodies by applying
-/// force to a rigid body
+/// force to every dynamic rigid body inside a region.
+/// The strength is an acceleration, so heavy and light
+/// bodies are affected the same. For drag it is per second.
 #[derive(Clone, Debug, Component)]
-#[storage(VecStorage)]
+#[storage(HashMapStorage)]
 pub struct ForceGenerator {
-    pub object_handle: nphysics2d::object::BodyHandle,
     pub center: Point2,
+    pub region: Region,
+    pub kind: ForceKind,
+    pub strength: f32,
+}
+
+impl ForceGenerator {
+    /// The force on a body at `position` moving at `velocity`, or `None` outside the region
+    pub fn force(&self, position: &Point2, velocity: &nalgebra::Vector2<f32>, mass: f32) -> Option<nalgebra::Vector2<f32>> {
+        let offset = position - self.center;
+        if !self.region.contains(&offset) {
+            return None;
+        }
+
+        let direction = offset.try_normalize(std::f32::EPSILON).unwrap_or(nalgebra::Vector2::zeros());
+        let acceleration = match self.kind {
+            ForceKind::Wind { direction } => direction * self.strength,
+            ForceKind::Vortex => nalgebra::Vector2::new(- direction.y, direction.x) * self.strength,
+            ForceKind::Attractor => - direction * self.strength,
+            ForceKind::Drag => - velocity * self.strength,
+        };
+
+        Some(acceleration * mass)
+    }
 }
 
 
@@ -99,6 +194,184 @@ pub type CollisionWorld = nphysics2d::world::CollisionWorld<f32>;
 
 
 
+/// The z component of the cross product, the torque of `force` pushing at the end of `arm`
+pub fn cross(arm: &nalgebra::Vector2<f32>, force: &nalgebra::Vector2<f32>) -> f32 {
+    arm.x * force.y - arm.y * force.x
+}
+
+/// The velocity of the point `arm` away from the center of mass of a body moving at `velocity`
+pub fn point_velocity(velocity: &nphysics2d::math::Velocity<f32>, arm: &nalgebra::Vector2<f32>) -> nalgebra::Vector2<f32> {
+    velocity.linear + nalgebra::Vector2::new(- arm.y, arm.x) * velocity.angular
+}
+
+
+
+/// Whether a body or a multibody link moves under forces, `None` once it is gone
+pub fn is_dynamic(physics: &PhysicsWorld, handle: nphysics2d::object::BodyHandle) -> Option<bool> {
+    // multibody links are always dynamic
+    match (physics.rigid_body(handle), physics.multibody_link(handle)) {
+        (Some(rb), _) => Some(rb.status() == nphysics2d::object::BodyStatus::Dynamic),
+        (None, Some(_)) => Some(true),
+        (None, None) => None,
+    }
+}
+
+
+
+/// Something tied to a rope or a joint
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Anchor {
+    /// A fixed point in the world
+    Point(Point2),
+
+    /// A point on a body, in the body's frame
+    Body(specs::Entity, Point2),
+}
+
+impl Anchor {
+    /// Ties to the body at the world point, or to the point when there is no body
+    pub fn at(specsWorld: &specs::World, entity: Option<specs::Entity>, point: Point2) -> Anchor {
+        match entity.and_then(|e| crate::world::body_pose(specsWorld, e).map(|pose| (e, pose))) {
+            Some((entity, pose)) => Anchor::Body(entity, pose.inverse() * point),
+            None => Anchor::Point(point),
+        }
+    }
+
+    /// Finds the tied point in the world, `None` once its body is gone
+    pub fn resolve(&self, physics: &PhysicsWorld, rigidBodies: &specs::ReadStorage<RigidBody>) -> Option<BodyPoint> {
+        match self {
+            Anchor::Point(point) => Some(BodyPoint::fixed(*point)),
+            Anchor::Body(entity, local) => BodyPoint::at(physics, rigidBodies.get(*entity)?.object_handle, local),
+        }
+    }
+}
+
+
+
+/**
+ * A point in the world or on a body and how it moves. Forces pushed at the
+ * point last for the next physics step only, since every step clears them,
+ * so the systems pushing them run before the `PhysicsSystem`.
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct BodyPoint {
+    pub handle: Option<nphysics2d::object::BodyHandle>,
+    pub position: Point2,
+    pub velocity: nalgebra::Vector2<f32>,
+
+    /// From the body's center of mass to the point
+    pub arm: nalgebra::Vector2<f32>,
+
+    /// Mass and angular inertia of the body, `None` when forces can't move it
+    pub inertia: Option<(f32, f32)>,
+}
+
+impl BodyPoint {
+    pub fn fixed(position: Point2) -> BodyPoint {
+        BodyPoint {
+            handle: None,
+            position: position,
+            velocity: nalgebra::Vector2::zeros(),
+            arm: nalgebra::Vector2::zeros(),
+            inertia: None,
+        }
+    }
+
+    /// The point at `local` in the frame of a body or a multibody link, `None` once it is gone
+    pub fn at(physics: &PhysicsWorld, handle: nphysics2d::object::BodyHandle, local: &Point2) -> Option<BodyPoint> {
+        let dynamic = is_dynamic(physics, handle)?;
+        let part = physics.body_part(handle);
+        let position = part.position() * local;
+        let arm = position - part.center_of_mass();
+        let inertia = part.local_inertia();
+
+        Some(BodyPoint {
+            handle: Some(handle),
+            position: position,
+            velocity: point_velocity(&part.velocity(), &arm),
+            arm: arm,
+            inertia: if dynamic { Some((inertia.linear, inertia.angular)) } else { None },
+        })
+    }
+
+    pub fn mass(&self) -> Option<f32> {
+        self.inertia.map(|(mass, _)| mass)
+    }
+
+    /// How much the point speeds up along `direction`, a unit vector, for each unit of impulse along it
+    pub fn inverse_mass(&self, direction: &nalgebra::Vector2<f32>) -> f32 {
+        match self.inertia {
+            Some((mass, angular)) if angular > 0.0 => 1.0 / mass + cross(&self.arm, direction).powi(2) / angular,
+            Some((mass, _)) => 1.0 / mass,
+            None => 0.0,
+        }
+    }
+
+    /// Applies a force at the point, points that can't be moved ignore it
+    pub fn push(&self, physics: &mut PhysicsWorld, force: &nalgebra::Vector2<f32>) {
+        if self.inertia.is_none() {
+            return;
+        }
+
+        if let Some(handle) = self.handle {
+            physics.body_part_mut(handle).apply_force(&nphysics2d::math::Force::new(*force, cross(&self.arm, force)));
+        }
+    }
+}
+
+/// The force of a damped spring of zero length pulling `a` towards `b`, per unit of mass
+pub fn spring(a: &BodyPoint, b: &BodyPoint, stiffness: f32, damping: f32) -> nalgebra::Vector2<f32> {
+    (b.position - a.position) * stiffness + (b.velocity - a.velocity) * damping
+}
+
+
+
+
+/**
+ * Applies the force of every `ForceGenerator` to the dynamic bodies inside
+ * it. Regions overlap freely and add up with the world gravity.
+ */
+pub struct ForceFieldSystem;
+
+impl<'a> specs::System<'a> for ForceFieldSystem {
+    type SystemData = (
+        specs::ReadStorage<'a, ForceGenerator>,
+        specs::ReadStorage<'a, RigidBody>,
+        specs::WriteExpect<'a, PhysicsWorld>,
+    );
+
+    fn run(&mut self, (generators, rigidBodies, mut physics_world): Self::SystemData) {
+        if generators.join().next().is_none() {
+            return;
+        }
+
+        for r in rigidBodies.join() {
+            let rb = match physics_world.rigid_body_mut(r.object_handle) {
+                Some(rb) => rb,
+                None => continue,
+            };
+
+            if rb.status() != nphysics2d::object::BodyStatus::Dynamic {
+                continue;
+            }
+
+            let position = Point2::from_coordinates(rb.position().translation.vector);
+            let velocity = rb.velocity().linear;
+            let mass = rb.local_inertia().linear;
+
+            let total = generators.join()
+                .filter_map(|g| g.force(&position, &velocity, mass))
+                .fold(nalgebra::Vector2::zeros(), |sum, force| sum + force);
+
+            if total != nalgebra::Vector2::zeros() {
+                rb.apply_force(&nphysics2d::math::Force::linear(total));
+            }
+        }
+    }
+}
+
+
+
 /**
  * Gravity system, for the attractive objects ;)
  *
@@ -113,13 +386,67 @@ impl<'a> specs::System<'a> for PhysicsSystem {
         specs::ReadStorage<'a,  Gravity>,
         specs::ReadStorage<'a,  Collider>,
         specs::ReadStorage<'a,  Mass>,
+        specs::Entities<'a>,
+        specs::ReadStorage<'a, RigidBody>,
+        specs::ReadStorage<'a, crate::fracture::Breakable>,
+        specs::Write<'a, crate::fracture::Impacts>,
         specs::WriteExpect<'a, PhysicsWorld>,
     );
 
-    fn run(&mut self, (mut motion, gravity, collider, mass, mut physics_world): Self::SystemData) {
+    fn run(&mut self, (mut motion, gravity, collider, mass, entities, rigidBodies, breakables, mut impacts, mut physics_world): Self::SystemData) {
+        // how every body moved before the step, to measure how hard new contacts hit
+        let before: HashMap<ncollide2d::world::CollisionObjectHandle, (nphysics2d::math::Velocity<f32>, Point2, Option<f32>)> =
+            (&collider, &rigidBodies).join()
+                .filter_map(|(c, r)| {
+                    let dynamic = is_dynamic(&physics_world, r.object_handle)?;
+                    let part = physics_world.body_part(r.object_handle);
+                    let mass = if dynamic { Some(part.local_inertia().linear) } else { None };
+                    Some((c.object_handle, (part.velocity(), part.center_of_mass(), mass)))
+                })
+                .collect();
+
+        let breakable: HashMap<ncollide2d::world::CollisionObjectHandle, specs::Entity> = (&entities, &collider, &breakables).join()
+            .map(|(e, c, _)| (c.object_handle, e))
+            .collect();
+
         physics_world.step();
 
         for contact in physics_world.contact_events() {
+            if let ncollide2d::events::ContactEvent::Started(h1, h2) = *contact {
+                if !breakable.contains_key(&h1) && !breakable.contains_key(&h2) {
+                    continue;
+                }
+
+                let (point, normal) = match crate::fracture::contact(&physics_world, h1, h2) {
+                    Some(contact) => contact,
+                    None => continue,
+                };
+
+                // the impulse stopping the two bodies closing in along the normal
+                let motion = |handle: ncollide2d::world::CollisionObjectHandle| before.get(&handle).map(|(velocity, centerOfMass, mass)| {
+                    (point_velocity(velocity, &(point - centerOfMass)), *mass)
+                });
+                let ((v1, m1), (v2, m2)) = match (motion(h1), motion(h2)) {
+                    (Some(a), Some(b)) => (a, b),
+                    _ => continue,
+                };
+
+                let inverse = m1.map_or(0.0, |m| 1.0 / m) + m2.map_or(0.0, |m| 1.0 / m);
+                let speed = (v1 - v2).dot(&normal);
+                if inverse <= 0.0 || speed <= 0.0 {
+                    continue;
+                }
+
+                for handle in [h1, h2].iter() {
+                    if let Some(entity) = breakable.get(handle) {
+                        impacts.impacts.push(crate::fracture::Impact {
+                            entity: *entity,
+                            impulse: speed / inverse,
+                            point: point,
+                        });
+                    }
+                }
+            }
         }
 
         for proximity in physics_world.proximity_events() {
@@ -0,0 +1,165 @@
+/**
+ *
+ * Generated ground.
+ *
+ * The floor of the test area is either the flat segment it always was or
+ * a `ShapeDesc::Heightfield` of rolling hills. Hills come from seeded value
+ * noise, the same seed always gives the same hills, or from a list of
+ * heights spread evenly across the floor. The ground is picked in the
+ * tuning and rebuilt with the walls:
+ *
+ * ```ignore
+ * sceneWorld.specs.write_resource::<Tuning>().ground = Ground::Noise(Noise {
+ *     seed: 7,
+ *     ..Noise::default()
+ * });
+ * ```
+ *
+ */
+
+use crate::shape::ShapeDesc;
+
+/// Horizontal distance between the samples of generated hills
+const SAMPLE_SPACING: f32 = 8.0;
+
+/// Seeds cycled through before going back to flat ground
+const HILL_SEEDS: u32 = 3;
+
+/// Seeded fractal value noise
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Noise {
+    pub seed: u32,
+
+    /// Layers of ever finer detail, each half the wavelength of the last
+    pub octaves: u32,
+
+    /// Height of the highest possible hill
+    pub amplitude: f32,
+
+    /// How much of the previous layer's height each finer layer adds, 0 to 1
+    pub roughness: f32,
+
+    /// Width of the coarsest hills
+    pub wavelength: f32,
+}
+
+impl Default for Noise {
+    fn default() -> Noise {
+        Noise {
+            seed: 1,
+            octaves: 4,
+            amplitude: 60.0,
+            roughness: 0.5,
+            wavelength: 160.0,
+        }
+    }
+}
+
+/// A random value in 0..1 for every lattice point of every seed
+fn lattice(seed: u32, i: i32) -> f32 {
+    let mut h = seed.wrapping_mul(0x9E37_79B9) ^ (i as u32).wrapping_mul(0x85EB_CA6B);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2C1B_3C6D);
+    h ^= h >> 12;
+    h = h.wrapping_mul(0x297A_2D39);
+    h ^= h >> 15;
+    (h & 0x00FF_FFFF) as f32 / 0x00FF_FFFF as f32
+}
+
+impl Noise {
+    /// The height at `x`, between 0 and the amplitude
+    pub fn height(&self, x: f32) -> f32 {
+        let mut total = 0.0;
+        let mut weight = 1.0;
+        let mut weights = 0.0;
+        let mut frequency = 1.0 / self.wavelength.max(std::f32::EPSILON);
+
+        for octave in 0..self.octaves.max(1) {
+            let t = x * frequency;
+            let i = t.floor();
+            let f = t - i;
+
+            // smoothstep between the neighbouring lattice points
+            let s = f * f * (3.0 - 2.0 * f);
+            let seed = self.seed.wrapping_add(octave.wrapping_mul(0x68E3_1DA4));
+            let (a, b) = (lattice(seed, i as i32), lattice(seed, i as i32 + 1));
+
+            total += (a + (b - a) * s) * weight;
+            weights += weight;
+            weight *= self.roughness;
+            frequency *= 2.0;
+        }
+
+        total / weights * self.amplitude
+    }
+
+    /// Evenly spaced heights from one end of `width` to the other
+    pub fn heights(&self, width: f32) -> Vec<f32> {
+        let samples = (width / SAMPLE_SPACING).ceil().max(1.0) as usize + 1;
+        (0..samples)
+            .map(|i| self.height(i as f32 * width / (samples - 1) as f32))
+            .collect()
+    }
+}
+
+
+
+/// What the floor of the test area is made of
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Ground {
+    /// A single flat segment
+    Flat,
+
+    /// Hills generated from noise
+    Noise(Noise),
+
+    /// Hills spread evenly across the floor, from left to right
+    Heights(Vec<f32>),
+}
+
+impl Default for Ground {
+    fn default() -> Ground {
+        Ground::Flat
+    }
+}
+
+impl Ground {
+    /// Flat ground, then hills for a few seeds, then flat again
+    pub fn next(&self) -> Ground {
+        match self {
+            Ground::Flat => Ground::Noise(Noise::default()),
+            Ground::Noise(noise) if noise.seed < HILL_SEEDS => Ground::Noise(Noise {
+                seed: noise.seed + 1,
+                ..noise.clone()
+            }),
+            _ => Ground::Flat,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Ground::Flat => "flat".to_owned(),
+            Ground::Noise(noise) => format!("hills {}", noise.seed),
+            Ground::Heights(_) => "heights".to_owned(),
+        }
+    }
+
+    /**
+     * The heightfield for a floor `width` wide, centered on the body's
+     * origin with its base at y 0. `None` for flat ground, or when there
+     * are too few heights to make a surface.
+     */
+    pub fn shape(&self, width: f32) -> Option<ShapeDesc> {
+        let heights = match self {
+            Ground::Flat => return None,
+            Ground::Noise(noise) => noise.heights(width),
+            Ground::Heights(heights) => heights.clone(),
+        };
+
+        if heights.len() < 2 {
+            return None;
+        }
+
+        Some(ShapeDesc::Heightfield { heights: heights, width: width })
+    }
+}
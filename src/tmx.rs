@@ -0,0 +1,338 @@
+/**
+ *
+ * Tiled map import.
+ *
+ * Turns the collision geometry of a Tiled `.tmx` map into a `SceneFile`,
+ * so maps are loaded, spawned and undone like any other scene. The map is
+ * centered on the origin, one pixel is one world unit.
+ *
+ * Solid tiles of a tile layer are merged into outlines, one static
+ * polyline per connected area. Only the edges between solid and empty
+ * tiles are kept and runs of them are joined into single segments, so
+ * bodies sliding along a floor never catch on the seams between tiles.
+ * Only tile layers with a `solid` or a `collision` property set to true
+ * are solid, the others are left to decoration.
+ *
+ * Rectangles, ellipses, polygons and polylines on object layers become
+ * bodies. Both kinds of layer honor these custom properties:
+ *
+ * - `material`: name of a material in the `MaterialLibrary`
+ * - `dynamic`: the bodies fall and get pushed around instead of being static
+ * - `sensor`: the bodies only detect what overlaps them
+ *
+ * Outlines and polylines have no area to give them a mass, they stay
+ * static whatever `dynamic` says.
+ *
+ */
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use log::*;
+
+use tiled::{Map, Object, ObjectShape, Properties, PropertyValue};
+
+use crate::builder::Status;
+use crate::geometry;
+use crate::render::{Render, RenderStyle};
+use crate::scenefile::{BodyDesc, SceneFile};
+use crate::shape::ShapeDesc;
+
+/// Segments used to approximate ellipses that aren't circles
+const ELLIPSE_SEGMENTS: usize = 16;
+
+const STATIC_COLOR: [f32; 4] = [0.6, 0.6, 0.7, 1.0];
+const DYNAMIC_COLOR: [f32; 4] = [0.9, 0.7, 0.4, 1.0];
+const SENSOR_COLOR: [f32; 4] = [0.4, 0.9, 0.4, 0.3];
+
+/// Reads a map and converts its collision geometry
+pub fn load(path: &str) -> Result<SceneFile, String> {
+    let map = tiled::parse_file(Path::new(path))
+        .map_err(|e| format!("Could not read {}: {:?}", path, e))?;
+
+    Ok(convert(&map))
+}
+
+/// The tile layers and object layers of a map as bodies
+pub fn convert(map: &Map) -> SceneFile {
+    let size = [(map.width * map.tile_width) as f32, (map.height * map.tile_height) as f32];
+    let origin = [- size[0] / 2.0, - size[1] / 2.0];
+
+    let mut bodies = Vec::new();
+
+    for layer in map.layers.iter().filter(|l| solid(&l.properties)) {
+        let tile = [map.tile_width as f32, map.tile_height as f32];
+        for outline in outlines(&layer.tiles) {
+            let mut points: Vec<[f32; 2]> = outline.iter()
+                .map(|&(x, y)| [x as f32 * tile[0], y as f32 * tile[1]])
+                .collect();
+            points.push(points[0]);
+
+            let mut body = BodyDesc::new(ShapeDesc::Polyline { points: points });
+            body.position = (origin[0], origin[1]);
+            apply_properties(&mut body, &layer.properties, true);
+            bodies.push(body);
+        }
+    }
+
+    for group in map.object_groups.iter() {
+        for object in group.objects.iter() {
+            match object_body(object, &origin) {
+                Some(body) => bodies.push(body),
+                None => debug!("Skipping map object {} ({:?})", object.id, object.shape),
+            }
+        }
+    }
+
+    debug!("Converted map into {} bodies", bodies.len());
+
+    SceneFile {
+        bodies: bodies,
+        ..SceneFile::default()
+    }
+}
+
+
+
+fn flag(properties: &Properties, name: &str) -> Option<bool> {
+    match properties.get(name)? {
+        PropertyValue::BoolValue(value) => Some(*value),
+        PropertyValue::StringValue(value) => Some(value == "true"),
+        PropertyValue::IntValue(value) => Some(*value != 0),
+        _ => None,
+    }
+}
+
+/// Whether a tile layer asks for collision
+fn solid(properties: &Properties) -> bool {
+    flag(properties, "solid").or_else(|| flag(properties, "collision")).unwrap_or(false)
+}
+
+/**
+ * Sets the material, status and sensor flag from the custom properties.
+ * Open shapes are always static and drawn as lines.
+ */
+fn apply_properties(body: &mut BodyDesc, properties: &Properties, open: bool) {
+    if let Some(PropertyValue::StringValue(material)) = properties.get("material") {
+        body.material = material.clone();
+    }
+
+    let dynamic = !open && flag(properties, "dynamic").unwrap_or(false);
+    body.status = if dynamic { Status::Dynamic } else { Status::Static };
+    body.sensor = flag(properties, "sensor").unwrap_or(false);
+
+    let color = match (body.sensor, dynamic) {
+        (true, _) => SENSOR_COLOR,
+        (false, true) => DYNAMIC_COLOR,
+        (false, false) => STATIC_COLOR,
+    };
+    let style = if open { RenderStyle::Line(2.0) } else { RenderStyle::Fill };
+    body.render = Render::new(style, color);
+}
+
+
+
+/**
+ * The outlines of the non-empty tiles, in tile corners. Each outline runs
+ * clockwise on screen around solid tiles and the other way around holes,
+ * without the corners that only split a straight run.
+ */
+fn outlines(tiles: &[Vec<u32>]) -> Vec<Vec<(i32, i32)>> {
+    let solid = |x: i32, y: i32| -> bool {
+        y >= 0 && x >= 0
+            && tiles.get(y as usize).and_then(|row| row.get(x as usize)).map_or(false, |gid| *gid != 0)
+    };
+
+    // every edge between a solid tile and an empty one, keyed by its start
+    let mut edges: BTreeMap<(i32, i32), Vec<(i32, i32)>> = BTreeMap::new();
+    for (y, row) in tiles.iter().enumerate() {
+        for x in 0..row.len() {
+            let (x, y) = (x as i32, y as i32);
+            if !solid(x, y) {
+                continue;
+            }
+
+            let sides = [
+                (solid(x, y - 1), (x, y), (x + 1, y)),
+                (solid(x + 1, y), (x + 1, y), (x + 1, y + 1)),
+                (solid(x, y + 1), (x + 1, y + 1), (x, y + 1)),
+                (solid(x - 1, y), (x, y + 1), (x, y)),
+            ];
+
+            for &(covered, from, to) in sides.iter() {
+                if !covered {
+                    edges.entry(from).or_insert_with(Vec::new).push(to);
+                }
+            }
+        }
+    }
+
+    let mut result = Vec::new();
+    while let Some(&start) = edges.keys().next() {
+        let mut outline = vec![start];
+        let mut at = start;
+
+        loop {
+            let next = match edges.get_mut(&at).and_then(|ends| ends.pop()) {
+                Some(next) => next,
+                None => break,
+            };
+
+            if edges.get(&at).map_or(false, |ends| ends.is_empty()) {
+                edges.remove(&at);
+            }
+
+            if next == start {
+                break;
+            }
+
+            outline.push(next);
+            at = next;
+        }
+
+        let points: Vec<[f32; 2]> = outline.iter().map(|&(x, y)| [x as f32, y as f32]).collect();
+        let corners = geometry::remove_collinear(&points);
+        if corners.len() >= 3 {
+            result.push(corners.iter().map(|p| (p[0] as i32, p[1] as i32)).collect());
+        }
+    }
+
+    result
+}
+
+
+
+/**
+ * The body for a map object. Tiled places objects by their top left
+ * corner, or bottom left for tile objects, and rotates them clockwise
+ * around it in degrees. Points and ellipses without a size are skipped
+ * since they have no area.
+ */
+fn object_body(object: &Object, origin: &[f32; 2]) -> Option<BodyDesc> {
+    let rotation = object.rotation.to_radians();
+
+    // the shape and its center relative to the object's position
+    let (shape, center) = match &object.shape {
+        ObjectShape::Rect { width, height } => {
+            let top = if object.gid != 0 { - height } else { 0.0 };
+            (ShapeDesc::Cuboid { half_extents: [width / 2.0, height / 2.0] }, [width / 2.0, top + height / 2.0])
+        },
+
+        ObjectShape::Ellipse { width, height } => {
+            if !(*width > 0.0 && *height > 0.0) {
+                return None;
+            }
+
+            let center = [width / 2.0, height / 2.0];
+            if (width - height).abs() < std::f32::EPSILON {
+                (ShapeDesc::Ball { radius: width / 2.0 }, center)
+            } else {
+                let points: Vec<[f32; 2]> = geometry::circle(&[0.0, 0.0], 1.0, ELLIPSE_SEGMENTS).iter()
+                    .map(|p| [p[0] * center[0], p[1] * center[1]])
+                    .collect();
+                (ShapeDesc::convex(&points)?, center)
+            }
+        },
+
+        ObjectShape::Polygon { points } => {
+            let points: Vec<[f32; 2]> = points.iter().map(|&(x, y)| [x, y]).collect();
+            ShapeDesc::polygon(&points)?
+        },
+
+        ObjectShape::Polyline { points } => {
+            let points = points.iter().map(|&(x, y)| [x, y]).collect();
+            (ShapeDesc::Polyline { points: points }, [0.0, 0.0])
+        },
+
+        ObjectShape::Point(..) => return None,
+    };
+
+    let open = match shape {
+        ShapeDesc::Polyline { .. } => true,
+        _ => false,
+    };
+
+    let mut body = BodyDesc::new(shape);
+    let placed = geometry::transform(&center, &[object.x + origin[0], object.y + origin[1]], rotation);
+    body.position = (placed[0], placed[1]);
+    body.rotation = rotation;
+    apply_properties(&mut body, &object.properties, open);
+
+    Some(body)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Twice the area enclosed, positive for outlines running clockwise on screen
+    fn signed_area(outline: &[(i32, i32)]) -> i32 {
+        let n = outline.len();
+        (0..n)
+            .map(|i| {
+                let (a, b) = (outline[i], outline[(i + 1) % n]);
+                a.0 * b.1 - b.0 * a.1
+            })
+            .sum()
+    }
+
+    /// Length of a closed outline, checking every side runs along the grid
+    fn perimeter(outline: &[(i32, i32)]) -> i32 {
+        let n = outline.len();
+        (0..n)
+            .map(|i| {
+                let (a, b) = (outline[i], outline[(i + 1) % n]);
+                assert!(a.0 == b.0 || a.1 == b.1, "Side from {:?} to {:?} isn't along the grid", a, b);
+                (b.0 - a.0).abs() + (b.1 - a.1).abs()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn merges_runs_of_tiles() {
+        let result = outlines(&[vec![1, 1, 1], vec![0, 0, 1]]);
+
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].len(), 6);
+        assert_eq!(perimeter(&result[0]), 10);
+        assert!(signed_area(&result[0]) > 0);
+    }
+
+    #[test]
+    fn outlines_holes_the_other_way_around() {
+        let mut result = outlines(&[vec![1, 1, 1], vec![1, 0, 1], vec![1, 1, 1]]);
+        result.sort_by_key(|outline| - perimeter(outline));
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].len(), 4);
+        assert_eq!(result[1].len(), 4);
+        assert_eq!(perimeter(&result[0]), 12);
+        assert_eq!(perimeter(&result[1]), 4);
+        assert_eq!(signed_area(&result[0]), 18);
+        assert_eq!(signed_area(&result[1]), -2);
+    }
+
+    #[test]
+    fn keeps_every_side_of_diagonal_tiles() {
+        let result = outlines(&[vec![1, 0], vec![0, 1]]);
+
+        let total: i32 = result.iter().map(|outline| perimeter(outline)).sum();
+        let corners: usize = result.iter().map(|outline| outline.len()).sum();
+        assert_eq!(total, 8);
+        assert_eq!(corners, 8);
+    }
+
+    #[test]
+    fn solid_layers_are_opt_in() {
+        let mut properties = Properties::new();
+        assert!(!solid(&properties));
+
+        properties.insert("collision".to_owned(), PropertyValue::BoolValue(true));
+        assert!(solid(&properties));
+
+        properties.insert("solid".to_owned(), PropertyValue::BoolValue(false));
+        assert!(!solid(&properties));
+    }
+}
@@ -0,0 +1,327 @@
+/**
+ *
+ * Mouse tools for the physics test.
+ *
+ * The active tool decides what the left mouse button does. Tools are cycled
+ * with the `NextTool` button.
+ *
+ */
+
+use specs;
+use specs::prelude::*;
+
+use crate::geometry;
+use crate::shape::{Part, ShapeDesc};
+use crate::system::{spring, BodyPoint};
+
+/// Points closer than this to the simplified outline are dropped
+const SKETCH_TOLERANCE: f32 = 2.0;
+
+/// Mouse moves shorter than this don't add a point to the sketch
+const SKETCH_SPACING: f32 = 3.0;
+
+/// Sketches smaller than this are ignored
+const SKETCH_MIN_AREA: f32 = 20.0;
+
+/// Longest slingshot pull, anything further is clamped
+const SLINGSHOT_MAX_PULL: f32 = 150.0;
+
+/// Shorter pulls let go of the ball without launching it, like a click
+const SLINGSHOT_MIN_PULL: f32 = 5.0;
+
+/// Launch speed per unit of pull
+const SLINGSHOT_SPEED: f32 = 4.0;
+
+/// Length of the predicted trajectory in seconds and its number of points
+const TRAJECTORY_TIME: f32 = 2.0;
+const TRAJECTORY_POINTS: usize = 40;
+
+/// Ropes are split into segments about this long
+const ROPE_SEGMENT_LENGTH: f32 = 12.0;
+
+/// Breakable ropes snap above this tension
+const ROPE_BREAK_TENSION: f32 = 50000.0;
+
+/// Spring pulling a dragged body to the mouse, per unit of mass
+const DRAG_STIFFNESS: f32 = 300.0;
+const DRAG_DAMPING: f32 = 30.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Tool {
+    /// Click to spawn the selected shape
+    Spawn,
+
+    /// Drag out a closed outline to spawn it as a body
+    DrawShape,
+
+    /// Click a body to show it in the inspector
+    Select,
+
+    /// Drag back from a ball and release to launch it
+    Slingshot,
+
+    /// Click to blow bodies away from the cursor, or pull them in
+    Explode,
+
+    /// Click to place a force field
+    Field,
+
+    /// Drag from where a rope is tied to where it ends
+    Rope,
+
+    /// Drag a body around and throw it
+    Drag,
+}
+
+impl Tool {
+    pub fn next(&self) -> Tool {
+        match self {
+            Tool::Spawn => Tool::DrawShape,
+            Tool::DrawShape => Tool::Select,
+            Tool::Select => Tool::Slingshot,
+            Tool::Slingshot => Tool::Explode,
+            Tool::Explode => Tool::Field,
+            Tool::Field => Tool::Rope,
+            Tool::Rope => Tool::Drag,
+            Tool::Drag => Tool::Spawn,
+        }
+    }
+}
+
+
+
+/**
+ * An outline drawn with the mouse. On release the outline is closed,
+ * simplified with Douglas-Peucker and split into convex pieces which make up
+ * a single compound shape.
+ */
+#[derive(Debug, Default)]
+pub struct Sketch {
+    pub points: Vec<[f32; 2]>,
+}
+
+impl Sketch {
+    pub fn add(&mut self, point: [f32; 2]) {
+        let far_enough = match self.points.last() {
+            Some(last) => (point[0] - last[0]).hypot(point[1] - last[1]) >= SKETCH_SPACING,
+            None => true,
+        };
+
+        if far_enough {
+            self.points.push(point);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /**
+     * Clears the sketch and returns it as a compound shape centered on the
+     * outline's centroid, along with that centroid in world coordinates.
+     * Returns `None` when the outline is too small or crosses itself.
+     */
+    pub fn finish(&mut self) -> Option<(ShapeDesc, [f32; 2])> {
+        let points = std::mem::replace(&mut self.points, Vec::new());
+        let outline = geometry::simplify_closed(&points, SKETCH_TOLERANCE);
+        if outline.len() < 3 || geometry::area(&outline) < SKETCH_MIN_AREA {
+            return None;
+        }
+
+        let center = geometry::centroid(&outline);
+        let local: Vec<[f32; 2]> = outline.iter()
+            .map(|p| [p[0] - center[0], p[1] - center[1]])
+            .collect();
+
+        let parts: Vec<Part> = geometry::convex_decomposition(&local)?
+            .iter()
+            .filter_map(|piece| ShapeDesc::convex(piece))
+            .map(|shape| Part { position: [0.0, 0.0], rotation: 0.0, shape: shape })
+            .collect();
+
+        if parts.is_empty() {
+            return None;
+        }
+
+        Some((ShapeDesc::Compound { parts: parts }, center))
+    }
+}
+
+
+
+/**
+ * A slingshot aim. The ball is grabbed on press and pulled back with the
+ * mouse. It is launched opposite to the pull, faster the further it was
+ * pulled.
+ */
+#[derive(Debug, Default)]
+pub struct Slingshot {
+    pub ball: Option<specs::Entity>,
+    pub pull: nalgebra::Point2<f32>,
+}
+
+impl Slingshot {
+    /// Grabs the ball under the point. Bodies that aren't balls are ignored.
+    pub fn grab(&mut self, specsWorld: &specs::World, point: &nalgebra::Point2<f32>) {
+        self.ball = crate::world::entity_at(specsWorld, point)
+            .filter(|e| specsWorld.read_storage::<crate::scene::physicstest::Ball>().contains(*e));
+        self.pull = *point;
+    }
+
+    /// The velocity the ball at `origin` leaves with
+    pub fn launch_velocity(&self, origin: &nalgebra::Point2<f32>) -> nalgebra::Vector2<f32> {
+        let pull = origin - self.pull;
+        let length = pull.norm().min(SLINGSHOT_MAX_PULL);
+        match pull.try_normalize(std::f32::EPSILON) {
+            Some(direction) => direction * length * SLINGSHOT_SPEED,
+            None => nalgebra::Vector2::zeros(),
+        }
+    }
+
+    /// The grabbed ball's position and launch velocity while aiming
+    pub fn aim(&self, specsWorld: &specs::World) -> Option<(nalgebra::Point2<f32>, nalgebra::Vector2<f32>)> {
+        let pose = crate::world::body_pose(specsWorld, self.ball?)?;
+        let origin = nalgebra::Point2::from_coordinates(pose.translation.vector);
+        Some((origin, self.launch_velocity(&origin)))
+    }
+
+    /**
+     * Lets go of the ball. Its velocity is replaced by the launch velocity,
+     * the same as an impulse that first stops it. A pull shorter than
+     * `SLINGSHOT_MIN_PULL` leaves the ball as it was.
+     */
+    pub fn release(&mut self, specsWorld: &specs::World) {
+        let (origin, velocity) = match self.aim(specsWorld) {
+            Some(aim) => aim,
+            None => return,
+        };
+
+        let ball = self.ball.take().expect("Aiming without a ball");
+        if (origin - self.pull).norm() < SLINGSHOT_MIN_PULL {
+            return;
+        }
+
+        let mut physics = specsWorld.write_resource::<crate::world::PhysicsWorld>();
+        let rigidBodies = specsWorld.read_storage::<crate::system::RigidBody>();
+
+        if let Some(rb) = rigidBodies.get(ball).and_then(|r| physics.rigid_body_mut(r.object_handle)) {
+            rb.set_velocity(nphysics2d::math::Velocity::new(velocity, 0.0));
+            rb.activate();
+        }
+    }
+}
+
+
+
+/**
+ * A rope being dragged out. The start is tied to the body under it or to
+ * the world, the end to the body it is released on or left free.
+ */
+#[derive(Debug, Default)]
+pub struct RopeDrag {
+    pub start: Option<nalgebra::Point2<f32>>,
+    pub breakable: bool,
+}
+
+impl RopeDrag {
+    /// Ties a rope from the start of the drag to the point, `None` when nothing was dragged
+    pub fn finish(&mut self, specsWorld: &mut specs::World, end: &nalgebra::Point2<f32>) -> Option<specs::Entity> {
+        let start = self.start.take()?;
+        let segments = ((end - start).norm() / ROPE_SEGMENT_LENGTH).ceil() as usize;
+        if segments < 2 {
+            return None;
+        }
+
+        let first = crate::world::entity_at(specsWorld, &start);
+        let last = crate::world::entity_at(specsWorld, end).filter(|e| Some(*e) != first);
+
+        let mut rope = crate::rope::RopeBuilder::new(start, *end, segments)
+            .segment_length(ROPE_SEGMENT_LENGTH)
+            .start(crate::system::Anchor::at(specsWorld, first, start));
+
+        if last.is_some() {
+            rope = rope.end(crate::system::Anchor::at(specsWorld, last, *end));
+        }
+
+        if self.breakable {
+            rope = rope.breakable(ROPE_BREAK_TENSION);
+        }
+
+        Some(rope.build(specsWorld))
+    }
+}
+
+
+
+/**
+ * A body dragged with the mouse. It is held by the point it was grabbed at
+ * and pulled towards the mouse by a damped spring, so it swings around the
+ * grab and keeps its speed when let go.
+ */
+#[derive(Debug, Default)]
+pub struct MouseDrag {
+    pub body: Option<specs::Entity>,
+
+    /// Where the body was grabbed, in its frame
+    pub grab: nalgebra::Point2<f32>,
+}
+
+impl MouseDrag {
+    /// Grabs the dynamic body or multibody link under the point
+    pub fn grab(&mut self, specsWorld: &specs::World, point: &nalgebra::Point2<f32>) {
+        let physics = specsWorld.read_resource::<crate::world::PhysicsWorld>();
+        let rigidBodies = specsWorld.read_storage::<crate::system::RigidBody>();
+
+        self.body = crate::world::entity_at(specsWorld, point)
+            .filter(|e| {
+                rigidBodies.get(*e)
+                    .and_then(|r| BodyPoint::at(&physics, r.object_handle, point))
+                    .map_or(false, |grabbed| grabbed.inertia.is_some())
+            });
+
+        if let Some(pose) = self.body.and_then(|e| crate::world::body_pose(specsWorld, e)) {
+            self.grab = pose.inverse() * point;
+        }
+    }
+
+    /// The grab point in world space, `None` when nothing is held
+    pub fn handle(&self, specsWorld: &specs::World) -> Option<nalgebra::Point2<f32>> {
+        let pose = crate::world::body_pose(specsWorld, self.body?)?;
+        Some(pose * self.grab)
+    }
+
+    /// Pulls the held body's grab point towards the target, every frame the button is held
+    pub fn pull(&self, specsWorld: &specs::World, target: &nalgebra::Point2<f32>) {
+        let body = match self.body {
+            Some(body) => body,
+            None => return,
+        };
+
+        let mut physics = specsWorld.write_resource::<crate::world::PhysicsWorld>();
+        let rigidBodies = specsWorld.read_storage::<crate::system::RigidBody>();
+
+        if let Some(grabbed) = rigidBodies.get(body).and_then(|r| BodyPoint::at(&physics, r.object_handle, &self.grab)) {
+            let force = spring(&grabbed, &BodyPoint::fixed(*target), DRAG_STIFFNESS, DRAG_DAMPING) * grabbed.mass().unwrap_or(0.0);
+            grabbed.push(&mut physics, &force);
+        }
+    }
+
+    pub fn release(&mut self) {
+        self.body = None;
+    }
+}
+
+
+
+/**
+ * Points along the path of a body launched from `origin`, ignoring
+ * collisions and drag.
+ */
+pub fn trajectory(origin: &nalgebra::Point2<f32>, velocity: &nalgebra::Vector2<f32>, gravity: &nalgebra::Vector2<f32>) -> Vec<nalgebra::Point2<f32>> {
+    (0..TRAJECTORY_POINTS)
+        .map(|i| {
+            let t = i as f32 * TRAJECTORY_TIME / TRAJECTORY_POINTS as f32;
+            origin + velocity * t + gravity * (0.5 * t * t)
+        })
+        .collect()
+}
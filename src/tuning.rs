@@ -0,0 +1,326 @@
+/**
+ *
+ * Live tuning of the physics test constants.
+ *
+ * These used to be compile time constants in `scene/physicstest.rs`. They now
+ * live in a specs resource so the tuning panel can edit them while the
+ * simulation runs. Restitution, friction and density are those of the
+ * default material. The last used values are written to `TUNING_FILE` when the
+ * panel closes and read back on the next start.
+ *
+ */
+
+use std::fs::File;
+use std::io::{Read, Write};
+
+use specs;
+use specs::prelude::*;
+
+use nphysics2d::volumetric::Volumetric;
+
+use log::*;
+
+use crate::material::{Density, MaterialLibrary, MaterialRef, PhysicsMaterial, DEFAULT_MATERIAL};
+use crate::terrain::Ground;
+use crate::world::PhysicsWorld;
+
+pub const TUNING_FILE: &str = "tuning.ron";
+
+// Restitution:
+// 0.9 bouncy ball
+// 0.5 baloon
+// 0.0 wet toilet paper
+const BALL_RESTITUTION: f32 = 0.0;
+
+// Friction
+// 50.0 velcro
+// 2.0 rocks
+// 0.0 ice
+const BALL_FRICTION: f32 = 50.0;
+const BALL_DENSITY: f32 = 1.0;
+
+// Applied downwards when gravity is toggled on with G
+const GRAVITY: f32 = 98.0;
+
+const WALL_SIZE: f32 = 200.0;
+
+// Springs of soft bodies, per unit of particle mass
+const SOFT_STIFFNESS: f32 = 400.0;
+const SOFT_DAMPING: f32 = 4.0;
+
+fn default_stiffness() -> f32 {
+    SOFT_STIFFNESS
+}
+
+fn default_damping() -> f32 {
+    SOFT_DAMPING
+}
+
+
+
+/// A single tunable value as shown in the tuning panel.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Field {
+    Restitution,
+    Friction,
+    Density,
+    Gravity,
+    WallSize,
+    Stiffness,
+    Damping,
+}
+
+impl Field {
+    pub const ALL: [Field; 7] = [
+        Field::Restitution,
+        Field::Friction,
+        Field::Density,
+        Field::Gravity,
+        Field::WallSize,
+        Field::Stiffness,
+        Field::Damping,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Field::Restitution => "Restitution",
+            Field::Friction => "Friction",
+            Field::Density => "Density",
+            Field::Gravity => "Gravity",
+            Field::WallSize => "Wall size",
+            Field::Stiffness => "Stiffness",
+            Field::Damping => "Damping",
+        }
+    }
+
+    /// How much a single Left/Right press changes the value
+    pub fn step(&self) -> f32 {
+        match self {
+            Field::Restitution => 0.1,
+            Field::Friction => 1.0,
+            Field::Density => 0.1,
+            Field::Gravity => 10.0,
+            Field::WallSize => 10.0,
+            Field::Stiffness => 50.0,
+            Field::Damping => 0.5,
+        }
+    }
+
+    pub fn min(&self) -> f32 {
+        match self {
+            Field::Density => 0.1,
+            Field::WallSize => 50.0,
+            _ => 0.0,
+        }
+    }
+}
+
+
+
+/// One click presets, taken from the comments that used to sit next to the
+/// constants. Each preset only touches the value it describes.
+pub struct Preset {
+    pub name: &'static str,
+    pub field: Field,
+    pub value: f32,
+}
+
+pub const PRESETS: [Preset; 6] = [
+    Preset { name: "bouncy ball",      field: Field::Restitution, value: 0.9 },
+    Preset { name: "baloon",           field: Field::Restitution, value: 0.5 },
+    Preset { name: "wet toilet paper", field: Field::Restitution, value: 0.0 },
+    Preset { name: "velcro",           field: Field::Friction,    value: 50.0 },
+    Preset { name: "rocks",            field: Field::Friction,    value: 2.0 },
+    Preset { name: "ice",              field: Field::Friction,    value: 0.0 },
+];
+
+
+
+/// The tunable world constants. Stored as a specs resource.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Tuning {
+    pub restitution: f32,
+    pub friction: f32,
+    pub density: f32,
+    pub gravity: f32,
+    pub gravity_enabled: bool,
+    pub wall_size: f32,
+
+    #[serde(default = "default_stiffness")]
+    pub stiffness: f32,
+
+    #[serde(default = "default_damping")]
+    pub damping: f32,
+
+    /// The floor between the walls, rebuilt with them
+    #[serde(default)]
+    pub ground: Ground,
+
+    /// Set when the walls need to be rebuilt, which needs a graphics context
+    #[serde(skip)]
+    pub walls_dirty: bool,
+}
+
+impl Default for Tuning {
+    fn default() -> Tuning {
+        Tuning {
+            restitution: BALL_RESTITUTION,
+            friction: BALL_FRICTION,
+            density: BALL_DENSITY,
+            gravity: GRAVITY,
+            gravity_enabled: false,
+            wall_size: WALL_SIZE,
+            stiffness: SOFT_STIFFNESS,
+            damping: SOFT_DAMPING,
+            ground: Ground::Flat,
+            walls_dirty: false,
+        }
+    }
+}
+
+impl Tuning {
+    /**
+     * Reads the last used values. Falls back to the defaults when the file
+     * is missing or can't be parsed.
+     */
+    pub fn load() -> Tuning {
+        let mut contents = String::new();
+        let result = File::open(TUNING_FILE)
+            .and_then(|mut file| file.read_to_string(&mut contents));
+
+        if let Err(e) = result {
+            debug!("No saved tuning ({}), using defaults", e);
+            return Tuning::default();
+        }
+
+        match ron::de::from_str(&contents) {
+            Ok(tuning) => tuning,
+            Err(e) => {
+                warn!("Could not parse {}: {}", TUNING_FILE, e);
+                Tuning::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let contents = match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(contents) => contents,
+            Err(e) => {
+                warn!("Could not serialize tuning: {}", e);
+                return;
+            }
+        };
+
+        let result = File::create(TUNING_FILE)
+            .and_then(|mut file| file.write_all(contents.as_bytes()));
+
+        if let Err(e) = result {
+            warn!("Could not write {}: {}", TUNING_FILE, e);
+        }
+    }
+
+    pub fn get(&self, field: Field) -> f32 {
+        match field {
+            Field::Restitution => self.restitution,
+            Field::Friction => self.friction,
+            Field::Density => self.density,
+            Field::Gravity => self.gravity,
+            Field::WallSize => self.wall_size,
+            Field::Stiffness => self.stiffness,
+            Field::Damping => self.damping,
+        }
+    }
+
+    pub fn set(&mut self, field: Field, value: f32) {
+        let value = value.max(field.min());
+        match field {
+            Field::Restitution => self.restitution = value,
+            Field::Friction => self.friction = value,
+            Field::Density => self.density = value,
+            Field::Gravity => self.gravity = value,
+            Field::WallSize => {
+                self.wall_size = value;
+                self.walls_dirty = true;
+            },
+            Field::Stiffness => self.stiffness = value,
+            Field::Damping => self.damping = value,
+        }
+    }
+
+    /// Compares the tunable values, ignoring pending work like `walls_dirty`
+    pub fn same_values(&self, other: &Tuning) -> bool {
+        self.restitution == other.restitution
+            && self.friction == other.friction
+            && self.density == other.density
+            && self.gravity == other.gravity
+            && self.gravity_enabled == other.gravity_enabled
+            && self.wall_size == other.wall_size
+            && self.stiffness == other.stiffness
+            && self.damping == other.damping
+            && self.ground == other.ground
+    }
+
+    pub fn gravity_vector(&self) -> nalgebra::Vector2<f32> {
+        if self.gravity_enabled {
+            nalgebra::Vector2::y() * self.gravity
+        } else {
+            nalgebra::Vector2::zeros()
+        }
+    }
+
+    /**
+     * Pushes the current values into the physics world. The values are the
+     * default material in the `MaterialLibrary`. Every existing collider is
+     * reset to its own material, dynamic bodies get their inertia recomputed
+     * from their own density or their material's, the world gravity is
+     * reset and soft bodies without springs of their own get the stiffness
+     * and damping.
+     */
+    pub fn apply(&self, specsWorld: &specs::World) {
+        let mut library = specsWorld.write_resource::<MaterialLibrary>();
+        let mut physics = specsWorld.write_resource::<PhysicsWorld>();
+        let colliders = specsWorld.read_storage::<crate::system::Collider>();
+        let rigidBodies = specsWorld.read_storage::<crate::system::RigidBody>();
+        let materials = specsWorld.read_storage::<MaterialRef>();
+        let densities = specsWorld.read_storage::<Density>();
+
+        let default = PhysicsMaterial {
+            restitution: self.restitution,
+            friction: self.friction,
+            density: self.density,
+            ..library.get(DEFAULT_MATERIAL).clone()
+        };
+        library.insert(default);
+
+        physics.set_gravity(self.gravity_vector());
+
+        for soft in (&mut specsWorld.write_storage::<crate::softbody::SoftBody>()).join().filter(|soft| soft.tuned) {
+            soft.stiffness = self.stiffness;
+            soft.damping = self.damping;
+        }
+
+        for (c, m) in (&colliders, &materials).join() {
+            if let Some(object) = physics.collision_world_mut().collision_object_mut(c.object_handle) {
+                *object.data_mut().material_mut() = library.get(&m.name).material();
+            }
+        }
+
+        for (c, r, m, d) in (&colliders, &rigidBodies, &materials, densities.maybe()).join() {
+            let dynamic = physics.rigid_body(r.object_handle)
+                .map_or(false, |rb| rb.status() == nphysics2d::object::BodyStatus::Dynamic);
+            if !dynamic {
+                continue;
+            }
+
+            let density = d.map_or(library.get(&m.name).density, |d| d.value);
+            let inertia = match physics.collision_world().collision_object(c.object_handle) {
+                Some(object) => object.shape().inertia(density),
+                None => continue,
+            };
+
+            if let Some(rb) = physics.rigid_body_mut(r.object_handle) {
+                rb.set_local_inertia(inertia);
+            }
+        }
+    }
+}
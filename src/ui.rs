@@ -0,0 +1,77 @@
+/**
+ *
+ * Minimal immediate mode panels drawn on top of the scene.
+ *
+ * A panel is a title and a list of lines with an optional cursor. It is
+ * rebuilt every frame from whatever state it displays.
+ *
+ */
+
+use ggez::graphics;
+
+const LINE_HEIGHT: f32 = 16.0;
+const PADDING: f32 = 8.0;
+const PANEL_WIDTH: f32 = 280.0;
+
+pub struct Panel {
+    pub title: String,
+    pub lines: Vec<String>,
+    pub cursor: Option<usize>,
+}
+
+impl Panel {
+    pub fn new(title: &str) -> Panel {
+        Panel {
+            title: title.to_owned(),
+            lines: Vec::new(),
+            cursor: None,
+        }
+    }
+
+    pub fn line<S: Into<String>>(mut self, line: S) -> Panel {
+        self.lines.push(line.into());
+        self
+    }
+
+    pub fn cursor(mut self, cursor: usize) -> Panel {
+        self.cursor = Some(cursor);
+        self
+    }
+
+    pub fn height(&self) -> f32 {
+        (self.lines.len() + 1) as f32 * LINE_HEIGHT + PADDING * 2.0
+    }
+
+    /**
+     * Draws the panel with its top left corner offset from the top left
+     * of the current screen coordinates.
+     */
+    pub fn draw(&self, context: &mut ggez::Context, offset: graphics::Point2) -> ggez::GameResult<()> {
+        let screen = graphics::get_screen_coordinates(context);
+        let x = screen.x + offset.x;
+        let y = screen.y + offset.y;
+        let height = self.height();
+
+        graphics::set_color(context, graphics::Color::new(0.0, 0.0, 0.0, 0.7))?;
+        graphics::rectangle(context, graphics::DrawMode::Fill, graphics::Rect::new(x, y, PANEL_WIDTH, height))?;
+        graphics::set_color(context, graphics::WHITE)?;
+
+        let title = graphics::TextCached::new(self.title.as_str())?;
+        title.queue(context, graphics::Point2::new(x + PADDING, y + PADDING), Some(graphics::WHITE));
+
+        for (i, line) in self.lines.iter().enumerate() {
+            let selected = self.cursor == Some(i);
+            let (marker, color) = match selected {
+                true => ("> ", graphics::Color::new(1.0, 1.0, 0.0, 1.0)),
+                false => ("  ", graphics::WHITE),
+            };
+
+            let text = graphics::TextCached::new(format!("{}{}", marker, line).as_str())?;
+            let position = graphics::Point2::new(x + PADDING, y + PADDING + (i + 1) as f32 * LINE_HEIGHT);
+            text.queue(context, position, Some(color));
+        }
+
+        graphics::TextCached::draw_queued(context, graphics::DrawParam::default())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,273 @@
+/**
+ *
+ * Vehicles.
+ *
+ * A vehicle is a multibody. The chassis is its root and each wheel hangs
+ * under it from a hub, which slides along the chassis' suspension axis on
+ * a prismatic joint limited to the suspension travel. The wheel spins on a
+ * revolute joint in the hub. The suspension is a spring and damper between
+ * the chassis and the hub, and the motor and brakes are the motor of the
+ * wheel's joint, so the chassis leans back when pulling away:
+ *
+ * ```ignore
+ * VehicleBuilder::new(nalgebra::Point2::new(-100.0, 100.0))
+ *     .torque(400.0)
+ *     .suspension(300.0, 10.0)
+ *     .build(&mut sceneWorld.specs);
+ * ```
+ *
+ * Forward and backward on the player input are throttle and brake of the
+ * active vehicle, the brake reverses once the vehicle has stopped. The
+ * others coast.
+ *
+ */
+
+use nphysics2d::joint::{FreeJoint, PrismaticJoint, RevoluteJoint};
+
+use specs;
+use specs::prelude::*;
+
+use crate::builder::BodyBuilder;
+use crate::player::PlayerInput;
+use crate::render::RenderStyle;
+use crate::shape::ShapeDesc;
+use crate::system::{spring, BodyPoint, Link, RigidBody};
+use crate::world::PhysicsWorld;
+
+/// Mass of the hub a wheel spins in, which has no collider of its own
+const HUB_MASS: f32 = 10.0;
+
+/// Wheel spin below which braking turns into reversing
+const REVERSE_SPIN: f32 = 0.5;
+
+/// Ties a wheel to its chassis through its hub
+#[derive(Clone, Debug, Component)]
+#[storage(HashMapStorage)]
+pub struct WheelJoint {
+    pub chassis: specs::Entity,
+
+    /// The link sliding along the suspension axis the wheel spins in
+    pub hub: specs::Entity,
+
+    /// Where the wheel center sits at rest, in the chassis' frame
+    pub anchor: nalgebra::Point2<f32>,
+
+    /// Direction the suspension extends in, in the chassis' frame
+    pub axis: nalgebra::Vector2<f32>,
+
+    /// Suspension spring and damper, per unit of wheel mass
+    pub stiffness: f32,
+    pub damping: f32,
+
+    /// Whether the motor drives this wheel
+    pub driven: bool,
+}
+
+#[derive(Clone, Debug, Component)]
+#[storage(HashMapStorage)]
+pub struct Vehicle {
+    pub wheels: Vec<specs::Entity>,
+
+    /// Drive torque per unit of wheel inertia at full throttle
+    pub torque: f32,
+
+    /// Brake torque per unit of wheel inertia at full brake
+    pub brake: f32,
+
+    /// Fastest the motor spins a wheel, in radians per second
+    pub max_spin: f32,
+
+    /// Whether the player input drives it
+    pub active: bool,
+}
+
+
+
+pub struct VehicleBuilder {
+    position: nalgebra::Point2<f32>,
+    half_extents: [f32; 2],
+    wheel_radius: f32,
+    torque: f32,
+    brake: f32,
+    stiffness: f32,
+    damping: f32,
+    material: String,
+}
+
+impl VehicleBuilder {
+    /// A small rear wheel drive car with its chassis centered on the point
+    pub fn new(position: nalgebra::Point2<f32>) -> VehicleBuilder {
+        VehicleBuilder {
+            position: position,
+            half_extents: [35.0, 7.0],
+            wheel_radius: 11.0,
+            torque: 400.0,
+            brake: 300.0,
+            stiffness: 300.0,
+            damping: 10.0,
+            material: "rubber".to_owned(),
+        }
+    }
+
+    pub fn torque(mut self, torque: f32) -> Self {
+        self.torque = torque;
+        self
+    }
+
+    pub fn brake(mut self, brake: f32) -> Self {
+        self.brake = brake;
+        self
+    }
+
+    pub fn suspension(mut self, stiffness: f32, damping: f32) -> Self {
+        self.stiffness = stiffness;
+        self.damping = damping;
+        self
+    }
+
+    /// Name of a material in the `MaterialLibrary`, used by the tyres
+    pub fn material(mut self, name: &str) -> Self {
+        self.material = name.to_owned();
+        self
+    }
+
+    /// Creates the chassis, the hubs and the wheels, returns the chassis
+    pub fn build(self, specsWorld: &mut specs::World) -> specs::Entity {
+        let chassis = BodyBuilder::new(specsWorld, ShapeDesc::Cuboid { half_extents: self.half_extents })
+            .render(RenderStyle::Fill, [0.9, 0.3, 0.2, 1.0])
+            .link(None, FreeJoint::new(nalgebra::Isometry2::new(self.position.coords, 0.0)), nalgebra::zero(), nalgebra::zero())
+            .build();
+        let chassisHandle = specsWorld.read_storage::<RigidBody>().get(chassis)
+            .expect("Chassis was deleted while being built")
+            .object_handle;
+
+        let travel = self.wheel_radius * 0.6;
+        let hubInertia = nphysics2d::math::Inertia::new(HUB_MASS, HUB_MASS * self.wheel_radius.powi(2) * 0.5);
+
+        let (x, y) = (self.half_extents[0] * 0.75, self.half_extents[1] + self.wheel_radius * 0.5);
+        let wheels = [(-x, true), (x, false)].iter()
+            .map(|&(offset, driven)| {
+                let anchor = nalgebra::Point2::new(offset, y);
+                let axis = nalgebra::Vector2::y();
+
+                let mut slide = PrismaticJoint::new(nalgebra::Unit::new_normalize(axis), 0.0);
+                slide.enable_min_offset(- travel);
+                slide.enable_max_offset(travel);
+
+                let hubHandle = specsWorld.write_resource::<PhysicsWorld>()
+                    .add_multibody_link(chassisHandle, slide, anchor.coords, nalgebra::zero(), hubInertia, nalgebra::Point2::origin());
+                let hub = specsWorld.create_entity()
+                    .with(RigidBody { object_handle: hubHandle })
+                    .with(Link { root: chassis })
+                    .build();
+
+                BodyBuilder::new(specsWorld, ShapeDesc::Ball { radius: self.wheel_radius })
+                    .material(&self.material)
+                    .render(RenderStyle::Line(2.0), [0.9, 0.9, 0.9, 1.0])
+                    .link(Some(hub), RevoluteJoint::new(0.0), nalgebra::zero(), nalgebra::zero())
+                    .with(WheelJoint {
+                        chassis: chassis,
+                        hub: hub,
+                        anchor: anchor,
+                        axis: axis,
+                        stiffness: self.stiffness,
+                        damping: self.damping,
+                        driven: driven,
+                    })
+                    .build()
+            })
+            .collect();
+
+        specsWorld.write_storage::<Vehicle>()
+            .insert(chassis, Vehicle {
+                wheels: wheels,
+                torque: self.torque,
+                brake: self.brake,
+                max_spin: 30.0,
+                active: false,
+            })
+            .expect("Chassis was deleted while being built");
+
+        chassis
+    }
+}
+
+
+
+/**
+ * Applies the suspension springs and sets the wheel motors. The spring
+ * pushes the hub and the chassis at the anchor apart, so bumps tip the
+ * chassis the way they would on a real suspension. Driving and braking set
+ * the speed and the torque of the motor of the wheel's joint, coasting
+ * turns it off. Only the active vehicle follows the player input.
+ */
+pub struct VehicleSystem;
+
+impl<'a> specs::System<'a> for VehicleSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        specs::ReadStorage<'a, Vehicle>,
+        specs::ReadStorage<'a, WheelJoint>,
+        specs::ReadStorage<'a, RigidBody>,
+        specs::ReadExpect<'a, PlayerInput>,
+        specs::WriteExpect<'a, PhysicsWorld>,
+    );
+
+    fn run(&mut self, (entities, vehicles, joints, rigidBodies, input, mut physics_world): Self::SystemData) {
+        let handle = |entity: specs::Entity| rigidBodies.get(entity).map(|r| r.object_handle);
+
+        for (wheelEntity, joint) in (&entities, &joints).join() {
+            let (chassisHandle, hubHandle, wheelHandle) = match (handle(joint.chassis), handle(joint.hub), handle(wheelEntity)) {
+                (Some(chassis), Some(hub), Some(wheel)) => (chassis, hub, wheel),
+                _ => continue,
+            };
+
+            let origin = nalgebra::Point2::origin();
+            let (anchor, hub, wheel) = match (
+                BodyPoint::at(&physics_world, chassisHandle, &joint.anchor),
+                BodyPoint::at(&physics_world, hubHandle, &origin),
+                BodyPoint::at(&physics_world, wheelHandle, &origin),
+            ) {
+                (Some(anchor), Some(hub), Some(wheel)) => (anchor, hub, wheel),
+                _ => continue,
+            };
+
+            // the prismatic joint holds the hub across the axis, the spring works along it
+            let axis = physics_world.body_part(chassisHandle).position().rotation * joint.axis;
+            let force = axis * spring(&hub, &anchor, joint.stiffness, joint.damping).dot(&axis) * wheel.mass().unwrap_or(0.0);
+            hub.push(&mut physics_world, &force);
+            anchor.push(&mut physics_world, &- force);
+
+            let vehicle = match vehicles.get(joint.chassis) {
+                Some(vehicle) => vehicle,
+                None => continue,
+            };
+
+            let spin = physics_world.body_part(wheelHandle).velocity().angular - physics_world.body_part(chassisHandle).velocity().angular;
+            let throttle = if vehicle.active { input.vertical } else { 0.0 };
+            let unit = wheel.inertia.map_or(0.0, |(_, angular)| angular);
+
+            // forward drives, backward brakes while still rolling forward and reverses after
+            let motor = if throttle < 0.0 && spin > REVERSE_SPIN {
+                Some((0.0, vehicle.brake * unit * - throttle))
+            } else if joint.driven && throttle != 0.0 {
+                Some((vehicle.max_spin * throttle.signum(), vehicle.torque * unit * throttle.abs()))
+            } else {
+                None
+            };
+
+            if let Some(mut link) = physics_world.multibody_link_mut(wheelHandle) {
+                if let Some(revolute) = link.joint_mut().downcast_mut::<RevoluteJoint<f32>>() {
+                    match motor {
+                        Some((velocity, torque)) => {
+                            revolute.enable_angular_motor();
+                            revolute.set_desired_angular_motor_velocity(velocity);
+                            revolute.set_max_angular_motor_torque(torque);
+                        },
+                        None => revolute.disable_angular_motor(),
+                    }
+                }
+            }
+        }
+    }
+}
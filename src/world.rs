@@ -7,12 +7,76 @@ use crate::game::InputState;
 
 use ggez_goodies::input::InputState as GInputState;
 
+pub const CAMERA_WIDTH: f32 = 800.0;
+pub const CAMERA_HEIGHT: f32 = 600.0;
+
 pub struct SceneWorld {
     pub specs: specs::World,
     pub input: InputState,
+    pub mouse: Mouse,
+    pub camera: nalgebra::Point2<f32>,
     pub quit: bool,
 }
 
+
+
+/**
+ * Mouse state in world coordinates. The scene stack only forwards key
+ * bindings, so scenes poll this from their update. The pressed and released
+ * flags last for a single update.
+ */
+#[derive(Debug, Clone)]
+pub struct Mouse {
+    pub position: nalgebra::Point2<f32>,
+    pub left: bool,
+    pub right: bool,
+    pub left_pressed: bool,
+    pub left_released: bool,
+    pub right_pressed: bool,
+    pub right_released: bool,
+}
+
+impl Default for Mouse {
+    fn default() -> Mouse {
+        Mouse {
+            position: nalgebra::Point2::origin(),
+            left: false,
+            right: false,
+            left_pressed: false,
+            left_released: false,
+            right_pressed: false,
+            right_released: false,
+        }
+    }
+}
+
+impl Mouse {
+    pub fn update_button(&mut self, button: ggez::event::MouseButton, down: bool) {
+        match button {
+            ggez::event::MouseButton::Left => {
+                self.left = down;
+                self.left_pressed |= down;
+                self.left_released |= !down;
+            },
+
+            ggez::event::MouseButton::Right => {
+                self.right = down;
+                self.right_pressed |= down;
+                self.right_released |= !down;
+            },
+
+            _ => {}
+        }
+    }
+
+    pub fn clear_edges(&mut self) {
+        self.left_pressed = false;
+        self.left_released = false;
+        self.right_pressed = false;
+        self.right_released = false;
+    }
+}
+
 /**
  * The SceneWorld, A snapshot of the world as the scene knows it.
  */
@@ -25,24 +89,199 @@ impl SceneWorld {
 
         let mut specsWorld = specs::World::new();
         specsWorld.register::<crate::scene::physicstest::Ball>();
+        specsWorld.register::<crate::scene::physicstest::Boundary>();
         specsWorld.register::<crate::system::Collider>();
         specsWorld.register::<crate::system::RigidBody>();
+        specsWorld.register::<crate::system::Link>();
         specsWorld.register::<crate::system::Motion>();
         specsWorld.register::<crate::system::Mass>();
         specsWorld.register::<crate::system::Mesh>();
         specsWorld.register::<crate::system::Gravity>();
+        specsWorld.register::<crate::system::ForceGenerator>();
+        specsWorld.register::<crate::fluid::FluidVolume>();
+        specsWorld.register::<crate::pairwise::Charge>();
+        specsWorld.register::<crate::pairwise::Magnet>();
+        specsWorld.register::<crate::softbody::SoftBody>();
+        specsWorld.register::<crate::softbody::SoftParticle>();
+        specsWorld.register::<crate::rope::Rope>();
+        specsWorld.register::<crate::fracture::Breakable>();
+        specsWorld.register::<crate::fracture::Lifetime>();
+        specsWorld.register::<crate::fracture::BreakableJoint>();
+        specsWorld.register::<crate::character::CharacterController>();
+        specsWorld.register::<crate::modifier::OneWay>();
+        specsWorld.register::<crate::modifier::Conveyor>();
+        specsWorld.register::<crate::modifier::PartOf>();
+        specsWorld.register::<crate::vehicle::Vehicle>();
+        specsWorld.register::<crate::vehicle::WheelJoint>();
+        specsWorld.register::<crate::sprite::Sprite>();
+        specsWorld.register::<crate::sprite::SpriteImage>();
+        specsWorld.register::<crate::material::MaterialRef>();
+        specsWorld.register::<crate::material::Density>();
+        specsWorld.register::<crate::shape::Shape>();
+        specsWorld.register::<crate::render::Render>();
+        specsWorld.register::<crate::history::Uid>();
 
         specsWorld.add_resource(physicsWorld);
+        specsWorld.add_resource(crate::tuning::Tuning::load());
+        specsWorld.add_resource(crate::material::MaterialLibrary::default());
+        specsWorld.add_resource(crate::history::History::default());
+        specsWorld.add_resource(crate::player::PlayerInput::default());
+        specsWorld.add_resource(crate::fracture::Impacts::default());
+        specsWorld.add_resource(crate::scene::physicstest::ActiveBall::default());
+
+        crate::scene::physicstest::PhysicsTest::create_walls(&mut specsWorld);
+        let ball = crate::scene::physicstest::PhysicsTest::create_ball(&mut specsWorld, crate::material::DEFAULT_MATERIAL, nalgebra::Point2::origin());
+        crate::scene::physicstest::PhysicsTest::activate(&specsWorld, ball);
+        crate::scene::physicstest::PhysicsTest::create_ground(&mut specsWorld);
 
-        crate::scene::physicstest::PhysicsTest::create_walls(context, &mut specsWorld);
-        crate::scene::physicstest::PhysicsTest::create_ball(context, &mut specsWorld);
-        crate::scene::physicstest::PhysicsTest::create_ground(context, &mut specsWorld);
+        // gravity and the default material may have been changed in the saved tuning
+        specsWorld.read_resource::<crate::tuning::Tuning>().apply(&specsWorld);
 
         SceneWorld {
             specs: specsWorld,
             input: GInputState::new(),
+            mouse: Mouse::default(),
+            camera: nalgebra::Point2::origin(),
             quit: false,
         }
     }
+
+    /// The area of the world on screen, centered on the camera
+    pub fn screen_rect(&self) -> ggez::graphics::Rect {
+        ggez::graphics::Rect {
+            x: self.camera.x - CAMERA_WIDTH / 2.0,
+            y: self.camera.y - CAMERA_HEIGHT / 2.0,
+            w: CAMERA_WIDTH,
+            h: CAMERA_HEIGHT,
+        }
+    }
+
+    /// Converts a window position in pixels to world coordinates
+    pub fn screen_to_world(&self, context: &ggez::Context, x: i32, y: i32) -> nalgebra::Point2<f32> {
+        let (width, height) = ggez::graphics::get_size(context);
+        let rect = self.screen_rect();
+        nalgebra::Point2::new(
+            rect.x + x as f32 / width as f32 * rect.w,
+            rect.y + y as f32 / height as f32 * rect.h,
+        )
+    }
+
+    /// Starts building a new body in this world
+    pub fn body(&mut self, shape: crate::shape::ShapeDesc) -> crate::builder::BodyBuilder {
+        crate::builder::BodyBuilder::new(&mut self.specs, shape)
+    }
+}
+
+
+
+
+/**
+ * Removes an entity from specs along with its rigid body and collider in the
+ * physics world. Removing the body also removes the colliders attached to it.
+ * A multibody link takes the whole multibody with it, every part entity
+ * included, since links can't be removed on their own.
+ */
+pub fn destroy_entity(specsWorld: &mut specs::World, entity: specs::Entity) {
+    use specs::Join;
+
+    let mut doomed = vec![entity];
+    {
+        let mut physics = specsWorld.write_resource::<PhysicsWorld>();
+        let entities = specsWorld.entities();
+        let rigidBodies = specsWorld.read_storage::<crate::system::RigidBody>();
+        let colliders = specsWorld.read_storage::<crate::system::Collider>();
+        let links = specsWorld.read_storage::<crate::system::Link>();
+
+        if let Some(root) = links.get(entity).map(|link| link.root) {
+            doomed = (&entities, &links).join()
+                .filter(|(_, link)| link.root == root)
+                .map(|(e, _)| e)
+                .collect();
+
+            let handles: Vec<_> = doomed.iter()
+                .filter_map(|e| colliders.get(*e))
+                .map(|c| c.object_handle)
+                .collect();
+            physics.remove_colliders(&handles);
+
+            if let Some(r) = rigidBodies.get(root) {
+                physics.remove_bodies(&[r.object_handle]);
+            }
+        } else if let Some(r) = rigidBodies.get(entity) {
+            physics.remove_bodies(&[r.object_handle]);
+        } else if let Some(c) = colliders.get(entity) {
+            physics.remove_colliders(&[c.object_handle]);
+        }
+    }
+
+    for entity in doomed {
+        if let Err(e) = specsWorld.delete_entity(entity) {
+            warn!("Could not delete entity {:?}: {:?}", entity, e);
+        }
+    }
+}
+
+
+
+/**
+ * The topmost entity whose collider contains the point, if any.
+ */
+pub fn entity_at(specsWorld: &specs::World, point: &nalgebra::Point2<f32>) -> Option<specs::Entity> {
+    use ncollide2d::query::PointQuery;
+    use specs::Join;
+
+    let physics = specsWorld.read_resource::<PhysicsWorld>();
+    let entities = specsWorld.entities();
+    let colliders = specsWorld.read_storage::<crate::system::Collider>();
+    let collision_world = physics.collision_world();
+
+    (&entities, &colliders).join()
+        .filter(|(_, c)| {
+            collision_world.collision_object(c.object_handle)
+                .map(|object| object.shape().contains_point(object.position(), point))
+                .unwrap_or(false)
+        })
+        .map(|(e, _)| e)
+        .last()
+}
+
+
+
+/// The pose of an entity's rigid body or multibody link
+pub fn body_pose(specsWorld: &specs::World, entity: specs::Entity) -> Option<nalgebra::Isometry2<f32>> {
+    let physics = specsWorld.read_resource::<PhysicsWorld>();
+    let rigidBodies = specsWorld.read_storage::<crate::system::RigidBody>();
+    let handle = rigidBodies.get(entity)?.object_handle;
+
+    match (physics.rigid_body(handle), physics.multibody_link(handle)) {
+        (Some(rb), _) => Some(rb.position().clone()),
+        (None, Some(_)) => Some(physics.body_part(handle).position().clone()),
+        (None, None) => None,
+    }
 }
 
+
+
+/**
+ * Moves a body without simulating. The collider is moved along with the
+ * rigid body so the change shows even while the simulation is paused.
+ * Multibody links are placed by their joints and stay where they are.
+ */
+pub fn set_pose(specsWorld: &specs::World, entity: specs::Entity, pose: nalgebra::Isometry2<f32>) {
+    let mut physics = specsWorld.write_resource::<PhysicsWorld>();
+    let rigidBodies = specsWorld.read_storage::<crate::system::RigidBody>();
+    let colliders = specsWorld.read_storage::<crate::system::Collider>();
+
+    if specsWorld.read_storage::<crate::system::Link>().contains(entity) {
+        warn!("Multibody link {:?} can't be moved", entity);
+        return;
+    }
+
+    if let Some(rb) = rigidBodies.get(entity).and_then(|r| physics.rigid_body_mut(r.object_handle)) {
+        rb.set_position(pose);
+    }
+
+    if let Some(c) = colliders.get(entity) {
+        physics.collision_world_mut().set_position(c.object_handle, pose);
+    }
+}